@@ -0,0 +1,113 @@
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+/// Time for an announce to count half as much towards the rate of its source.
+const HALF_LIFE: Duration = Duration::from_secs(60);
+
+/// Time for a peer added to the torrents cache to count half as much towards
+/// [`DhtConfig::max_peers_per_source`].
+///
+/// [`DhtConfig::max_peers_per_source`]: crate::dht::DhtConfig::max_peers_per_source
+pub const CONTRIBUTION_HALF_LIFE: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum number of sources tracked at once. When exceeded, the source with
+/// the lowest rate is forgotten.
+const MAX_TRACKED_SOURCES: usize = 4096;
+
+/// Tracks an exponentially decaying count of recent announces from each
+/// source IP.
+pub struct AnnounceRates {
+    sources: HashMap<Ipv4Addr, DecayingCount>,
+    half_life: Duration,
+}
+
+#[derive(Clone, Copy)]
+struct DecayingCount {
+    count: f64,
+    updated: Instant,
+}
+
+impl DecayingCount {
+    fn at(&self, now: Instant, half_life: Duration) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated);
+        let half_lives = elapsed.as_secs_f64() / half_life.as_secs_f64();
+
+        self.count * 0.5f64.powf(half_lives)
+    }
+}
+
+impl AnnounceRates {
+    pub fn new() -> AnnounceRates {
+        AnnounceRates::with_half_life(HALF_LIFE)
+    }
+
+    /// Like [`AnnounceRates::new`] but with announces counting half as much
+    /// after `half_life` instead of a minute.
+    pub fn with_half_life(half_life: Duration) -> AnnounceRates {
+        AnnounceRates {
+            sources: HashMap::new(),
+            half_life,
+        }
+    }
+
+    /// Records an announce from `source` at `now` and returns the decayed count
+    /// of announces from `source` including this one.
+    pub fn record(&mut self, source: Ipv4Addr, now: Instant) -> f64 {
+        if !self.sources.contains_key(&source) && self.sources.len() >= MAX_TRACKED_SOURCES {
+            self.forget_slowest(now);
+        }
+
+        let entry = self.sources.entry(source).or_insert(DecayingCount {
+            count: 0.0,
+            updated: now,
+        });
+
+        entry.count = entry.at(now, self.half_life) + 1.0;
+        entry.updated = now;
+
+        entry.count
+    }
+
+    fn forget_slowest(&mut self, now: Instant) {
+        let half_life = self.half_life;
+        let slowest = self
+            .sources
+            .iter()
+            .min_by(|(_, a), (_, b)| a.at(now, half_life).total_cmp(&b.at(now, half_life)))
+            .map(|(source, _)| *source);
+
+        if let Some(source) = slowest {
+            self.sources.remove(&source);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AnnounceRates,
+        HALF_LIFE,
+    };
+    use std::{
+        net::Ipv4Addr,
+        time::Instant,
+    };
+
+    #[test]
+    fn counts_decay() {
+        let mut rates = AnnounceRates::new();
+        let source = Ipv4Addr::new(1, 2, 3, 4);
+        let start = Instant::now();
+
+        assert_eq!(rates.record(source, start), 1.0);
+        assert_eq!(rates.record(source, start), 2.0);
+        assert_eq!(rates.record(source, start + HALF_LIFE), 2.0);
+        assert_eq!(rates.record(Ipv4Addr::new(5, 6, 7, 8), start), 1.0);
+    }
+}
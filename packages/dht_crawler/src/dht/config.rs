@@ -0,0 +1,146 @@
+use crate::routing::SelectionPolicy;
+use krpc_encoding::NodeInfo;
+use std::time::Duration;
+
+/// Tunable behavior of a [`Dht`](crate::Dht).
+#[derive(Debug, Clone)]
+pub struct DhtConfig {
+    /// Maximum number of nodes from a single `find_node` response which are
+    /// followed while discovering nodes.
+    ///
+    /// A malicious node can respond with nodes which are all under its
+    /// control. Following fewer nodes from each response dilutes the influence
+    /// of any single responder at the cost of slower discovery, as fewer nodes
+    /// are explored at each hop.
+    pub max_nodes_per_response: usize,
+
+    /// Maximum number of nodes from a single `find_node` response in the same
+    /// /24 subnet which are followed while discovering nodes.
+    ///
+    /// Sybil clusters are usually hosted on a handful of machines. Capping the
+    /// nodes taken from each subnet makes a cluster much less effective, but
+    /// also skips legitimate nodes sharing a subnet.
+    pub max_nodes_per_subnet: usize,
+
+    /// Number of hops away from a bootstrap node discovery follows returned
+    /// nodes for. Bootstrap nodes are queried at depth zero, so nodes up to
+    /// this many hops away are queried and the nodes they return aren't
+    /// followed. Bounds the traffic and time spent bootstrapping.
+    pub bootstrap_max_depth: usize,
+
+    /// Number of nodes closest to the info hash included in `get_peers`
+    /// responses when no peers are known.
+    pub get_peers_response_nodes: usize,
+
+    /// Client version included in the `v` field of responses sent to other
+    /// nodes. By convention two bytes identifying the client followed by two
+    /// bytes of version.
+    pub response_version: Option<Vec<u8>>,
+
+    /// Nodes included in `find_node` and `get_peers` responses while the
+    /// routing table has fewer than `fallback_threshold` good nodes, so
+    /// queriers we can't help still have somewhere to go. Usually reliable
+    /// bootstrap nodes.
+    pub fallback_nodes: Vec<NodeInfo>,
+
+    /// Number of good nodes in the routing table below which `fallback_nodes`
+    /// are included in responses.
+    pub fallback_threshold: usize,
+
+    /// How long tokens handed out in `get_peers` responses are accepted for.
+    /// When set, tokens embed the time they were issued and expire after
+    /// exactly this long. When `None`, tokens are only invalidated by secret
+    /// rotation.
+    pub token_lifetime: Option<Duration>,
+
+    /// Rate of announces from a single IP, as a count decaying by half every
+    /// minute, above which announces from that IP stop adding new info hashes
+    /// to the torrents cache. Announces are still answered. Keeps a single
+    /// misbehaving peer from flooding the cache with fake info hashes.
+    pub announce_flood_threshold: f64,
+
+    /// Maximum number of peers a single source IP can add to the torrents
+    /// cache across all info hashes, as a count decaying by half every hour.
+    /// Announces beyond the cap are still answered but not stored, and still
+    /// count towards it, so one node can't dominate the harvested peers with
+    /// fake ones. Only the most active sources are tracked, so memory stays
+    /// bounded however many addresses announce. `None` doesn't limit sources.
+    pub max_peers_per_source: Option<usize>,
+
+    /// How nodes included in `find_node` and `get_peers` responses are chosen.
+    pub selection: SelectionPolicy,
+
+    /// Number of queries sent at once during an iterative `get_peers` lookup,
+    /// the alpha parameter from Kademlia. Higher values find peers sooner but
+    /// send more queries to nodes which turn out not to be useful. Values
+    /// below one are treated as one.
+    pub lookup_alpha: usize,
+
+    /// Upper bound on the number of rounds of queries in an iterative
+    /// `get_peers` lookup. Keeps lookups on sparse networks, which may never
+    /// converge, from running forever.
+    pub lookup_max_rounds: usize,
+
+    /// Whether questionable nodes near the target of a `find_node` or
+    /// `get_peers` query are pinged when the response has fewer nodes than
+    /// wanted. The response isn't held back, but nodes which answer the ping
+    /// become good and are included in responses to later queries. Helps
+    /// response quality while the routing table is sparse.
+    pub requery_questionable: bool,
+
+    /// Whether a node which responds during a lookup with an id other than
+    /// the one it was known by is dropped from the rest of the lookup. Such
+    /// nodes are reported either way. A node churning through ids is a common
+    /// sign of a sybil attack.
+    pub drop_nodes_changing_id: bool,
+
+    /// How long a node followed while crawling isn't queried again for, no
+    /// matter how many responses it shows up in. Saves queries to the same
+    /// popular nodes over a long crawl. Zero queries nodes every time they
+    /// are returned.
+    pub crawl_requery_window: Duration,
+
+    /// Whether announces from nodes with ids not derived from their IP as
+    /// described by [BEP-0042] are rejected. Without it a single host can
+    /// announce under any number of ids. Nodes on local networks are exempt.
+    ///
+    /// [BEP-0042]: http://www.bittorrent.org/beps/bep_0042.html
+    pub reject_spoofed_announces: bool,
+}
+
+impl DhtConfig {
+    /// Configuration trading discovery speed for resistance to sybil attacks.
+    pub fn defensive() -> DhtConfig {
+        DhtConfig {
+            max_nodes_per_response: 4,
+            max_nodes_per_subnet: 1,
+            drop_nodes_changing_id: true,
+            reject_spoofed_announces: true,
+            ..DhtConfig::default()
+        }
+    }
+}
+
+impl Default for DhtConfig {
+    fn default() -> DhtConfig {
+        DhtConfig {
+            max_nodes_per_response: 8,
+            max_nodes_per_subnet: 8,
+            bootstrap_max_depth: 5,
+            get_peers_response_nodes: 8,
+            response_version: None,
+            fallback_nodes: Vec::new(),
+            fallback_threshold: 8,
+            token_lifetime: None,
+            announce_flood_threshold: 100.0,
+            max_peers_per_source: None,
+            selection: SelectionPolicy::Closest,
+            lookup_alpha: 3,
+            lookup_max_rounds: 16,
+            drop_nodes_changing_id: false,
+            requery_questionable: false,
+            crawl_requery_window: Duration::from_secs(10 * 60),
+            reject_spoofed_announces: false,
+        }
+    }
+}
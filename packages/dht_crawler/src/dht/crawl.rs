@@ -0,0 +1,381 @@
+use crate::{
+    dht::{
+        limit_response_nodes,
+        lookup,
+        poison::LockRecovering,
+        Dht,
+        QUERY_TIMEOUT,
+    },
+    errors::{
+        ErrorKind,
+        Result,
+    },
+    routing::Node,
+};
+use futures::future::{
+    self,
+    Future,
+};
+use krpc_encoding::{
+    NodeID,
+    NodeInfo,
+};
+use std::{
+    net::SocketAddr,
+    ops::Deref,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use tokio::time::{
+    self,
+    timeout,
+};
+use tokio_krpc::responses::FindNodeResponse;
+
+/// Time between rounds of queries while crawling a partition.
+const CRAWL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tunable behavior of [`Dht::crawl_partition_with`].
+#[derive(Debug, Clone, Default)]
+pub struct CrawlStrategy {
+    /// Minimum XOR distance from every node already in the routing table for
+    /// a node returned by a `find_node` query to be followed. Skipping nodes
+    /// right next to ones we already know spreads the crawl out towards
+    /// regions of the keyspace which haven't been explored yet. `None`, the
+    /// default, follows every node.
+    pub min_contact_distance: Option<NodeID>,
+}
+
+impl Dht {
+    /// Discovers nodes in the slice `[start, end)` of the keyspace. Every
+    /// round picks a random target in the slice, sends `find_node` queries for
+    /// it to the closest nodes in the routing table and then to the nodes
+    /// they return, adding every node which responds to the routing table.
+    ///
+    /// Instances crawling non-overlapping partitions crawl the whole keyspace
+    /// together without querying for the same targets. `end` may be `2^160`
+    /// to include the top of the keyspace. The returned future runs forever
+    /// and must be polled alongside the future returned by [`Dht::start`].
+    pub fn crawl_partition(&self, start: NodeID, end: NodeID) -> Result<impl Future<Output = ()>> {
+        self.crawl_partition_with(start, end, CrawlStrategy::default())
+    }
+
+    /// Like [`Dht::crawl_partition`] but with non-default behavior described
+    /// by `strategy`.
+    pub fn crawl_partition_with(
+        &self,
+        start: NodeID,
+        end: NodeID,
+        strategy: CrawlStrategy,
+    ) -> Result<impl Future<Output = ()>> {
+        if start.deref() >= end.deref() {
+            return Err(ErrorKind::EmptyPartition)?;
+        }
+
+        let dht = self.clone();
+
+        Ok(async move {
+            let mut ticks = time::interval(CRAWL_INTERVAL);
+
+            loop {
+                ticks.tick().await;
+
+                dht.crawl_towards(random_target(&start, &end), &strategy)
+                    .await
+                    .unwrap_or_else(|e| eprintln!("Error During Crawl {}", e));
+            }
+        })
+    }
+
+    async fn crawl_towards(&self, target: NodeID, strategy: &CrawlStrategy) -> Result<()> {
+        self.pause_gate.wait().await;
+
+        let nodes = self
+            .routing_table
+            .lock_recovering()
+            .closest_nodes(&target, lookup::K);
+        let responses = future::join_all(
+            nodes
+                .iter()
+                .map(|node| self.query_find_node(node.address, target.clone())),
+        )
+        .await;
+
+        let mut discovered = Vec::new();
+        {
+            let mut routing_table = self.routing_table.lock_recovering();
+            for (node, response) in nodes.into_iter().zip(responses) {
+                let address = match node.address {
+                    SocketAddr::V4(address) => address,
+                    SocketAddr::V6(_) => continue,
+                };
+
+                if let Some(routing_node) = routing_table.get_or_add(node.node_id, address) {
+                    match response {
+                        Ok(response) => {
+                            routing_node.mark_successful_request();
+                            discovered.extend(limit_response_nodes(response.nodes, &self.config));
+                        }
+                        Err(_) => routing_node.mark_failed_request(),
+                    }
+                }
+            }
+
+            if let Some(min_distance) = &strategy.min_contact_distance {
+                let known = routing_table
+                    .nodes()
+                    .map(|node| node.id.clone())
+                    .collect::<Vec<NodeID>>();
+                discovered = spread_out(discovered, &known, min_distance);
+            }
+        }
+
+        // Skip nodes already followed recently, whether in this round or an
+        // earlier one.
+        {
+            let mut recently_queried = self.recently_queried.lock()?;
+            let now = Instant::now();
+            discovered.retain(|node| {
+                if recently_queried.queried_recently(&node.node_id, now) {
+                    return false;
+                }
+
+                recently_queried.record(node.node_id.clone(), now);
+                true
+            });
+        }
+
+        // Follow the returned nodes one hop, keeping the ones which respond.
+        let responses = future::join_all(
+            discovered
+                .iter()
+                .map(|node| self.query_find_node(node.address, target.clone())),
+        )
+        .await;
+
+        let mut routing_table = self.routing_table.lock_recovering();
+        for (node, response) in discovered.into_iter().zip(responses) {
+            let (address, response) = match (node.address, response) {
+                (SocketAddr::V4(address), Ok(response)) => (address, response),
+                _ => continue,
+            };
+
+            let mut node = Node::new(response.id, address);
+            node.mark_successful_request();
+            routing_table.add_node(node);
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn query_find_node(
+        &self,
+        address: SocketAddr,
+        target: NodeID,
+    ) -> Result<FindNodeResponse> {
+        let response = timeout(
+            QUERY_TIMEOUT,
+            self.transports
+                .request_transport(&address)
+                .find_node(address, target),
+        )
+        .await
+        .map_err(|_| ErrorKind::Timeout)??;
+
+        Ok(response)
+    }
+}
+
+/// Drops the nodes closer than `min_distance` to any of the `known` ids.
+fn spread_out(nodes: Vec<NodeInfo>, known: &[NodeID], min_distance: &NodeID) -> Vec<NodeInfo> {
+    nodes
+        .into_iter()
+        .filter(|node| {
+            known
+                .iter()
+                .all(|id| id.distance(&node.node_id).deref() >= min_distance.deref())
+        })
+        .collect()
+}
+
+/// A uniformly random id in `[start, end)`.
+fn random_target(start: &NodeID, end: &NodeID) -> NodeID {
+    let width = end.deref() - start.deref();
+
+    NodeID::new(start.deref() + NodeID::random().deref() % width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        random_target,
+        spread_out,
+        CrawlStrategy,
+    };
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::testing::{
+            add_good_node,
+            FakeNode,
+        },
+        Dht,
+    };
+    use failure::Error;
+    use futures::{
+        channel::mpsc,
+        StreamExt,
+    };
+    use krpc_encoding::{
+        NodeID,
+        NodeInfo,
+        Query,
+        Response,
+    };
+    use num_bigint::BigUint;
+    use std::{
+        ops::Deref,
+        sync::{
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::{
+        task::LocalSet,
+        time::timeout,
+    };
+
+    fn in_partition(id: &NodeID, start: &NodeID, end: &NodeID) -> bool {
+        id.deref() >= start.deref() && id.deref() < end.deref()
+    }
+
+    #[test]
+    fn random_targets_within_partition() {
+        let start = NodeID::new(BigUint::from(1000u32));
+        let end = NodeID::new(BigUint::from(1003u32));
+
+        for _ in 0..100 {
+            assert!(in_partition(&random_target(&start, &end), &start, &end));
+        }
+    }
+
+    #[test]
+    fn near_duplicates_not_followed() -> Result<(), Error> {
+        let id = |n: u32| NodeID::new(BigUint::from(n));
+        let known = vec![id(0x1000), id(0x2000)];
+        let min_distance = id(0x100);
+
+        let discovered = vec![
+            NodeInfo::new(id(0x1001), "10.0.0.1:6881".parse()?),
+            NodeInfo::new(id(0x3000), "10.0.0.2:6881".parse()?),
+            NodeInfo::new(id(0x20ff), "10.0.0.3:6881".parse()?),
+            NodeInfo::new(id(0x2100), "10.0.0.4:6881".parse()?),
+        ];
+
+        let followed = spread_out(discovered.clone(), &known, &min_distance);
+
+        assert_eq!(followed, vec![discovered[1].clone(), discovered[3].clone()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn crawl_targets_within_partition() -> Result<(), Error> {
+        let sweep = NodeID::keyspace_sweep(4);
+        let (start, end) = (sweep[1].clone(), sweep[2].clone());
+
+        let neighbor = FakeNode::bind(NodeID::random()).await?;
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &neighbor.info())?;
+
+        let (targets_tx, targets_rx) = mpsc::unbounded();
+        let neighbor_id = neighbor.id.clone();
+        neighbor.serve(move |query| match query {
+            Query::FindNode { target, .. } => {
+                let _ = targets_tx.unbounded_send(target);
+                Some(Response::NextHop {
+                    id: neighbor_id.clone(),
+                    token: None,
+                    nodes: Vec::new(),
+                    nodes6: Vec::new(),
+                })
+            }
+            _ => None,
+        });
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local.spawn_local(dht.crawl_partition(start.clone(), end.clone())?);
+
+        let targets = local
+            .run_until(timeout(
+                Duration::from_secs(3),
+                targets_rx.take(2).collect::<Vec<NodeID>>(),
+            ))
+            .await?;
+
+        assert_eq!(targets.len(), 2);
+        for target in &targets {
+            assert!(in_partition(target, &start, &end));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn empty_partition_rejected() -> Result<(), Error> {
+        let (dht, _dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let id = NodeID::random();
+
+        assert!(dht.crawl_partition(id.clone(), id).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn followed_node_not_requeried_within_window() -> Result<(), Error> {
+        let neighbor = FakeNode::bind(NodeID::random()).await?;
+        let popular = FakeNode::bind(NodeID::random()).await?;
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &neighbor.info())?;
+
+        let neighbor_id = neighbor.id.clone();
+        let popular_info = popular.info();
+        neighbor.serve(move |query| match query {
+            Query::FindNode { .. } => Some(Response::NextHop {
+                id: neighbor_id.clone(),
+                token: None,
+                nodes: vec![popular_info.clone()],
+                nodes6: Vec::new(),
+            }),
+            _ => None,
+        });
+
+        // never answers, so it stays out of the routing table
+        let popular_queries = Arc::new(AtomicUsize::new(0));
+        let counter = popular_queries.clone();
+        popular.serve(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            None
+        });
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local
+            .run_until(async {
+                let strategy = CrawlStrategy::default();
+                dht.crawl_towards(NodeID::random(), &strategy).await?;
+                dht.crawl_towards(NodeID::random(), &strategy).await
+            })
+            .await?;
+
+        assert_eq!(popular_queries.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use crate::dht::{
+    lookup,
+    poison::LockRecovering,
+    Dht,
+};
+use futures::future;
+use krpc_encoding::NodeID;
+use std::ops::Deref;
+
+impl Dht {
+    /// For each of `samples` random targets, asks the nodes in the routing
+    /// table closest to the target for nodes near it and records the XOR
+    /// distance from the target to the closest node found. Targets which no
+    /// node answered for are left out, so fewer than `samples` distances may
+    /// be returned.
+    ///
+    /// Distances are spread evenly across the keyspace on a uniformly
+    /// populated network, so aggregating them characterizes how densely the
+    /// network is populated and feeds estimates of its size.
+    pub async fn density_sample(&self, samples: usize) -> Vec<NodeID> {
+        let mut distances = Vec::with_capacity(samples);
+
+        for _ in 0..samples {
+            if let Some(distance) = self.closest_distance(NodeID::random()).await {
+                distances.push(distance);
+            }
+        }
+
+        distances
+    }
+
+    /// Distance from `target` to the closest node found with a single round
+    /// of `find_node` queries, including the responders themselves.
+    async fn closest_distance(&self, target: NodeID) -> Option<NodeID> {
+        let nodes = self
+            .routing_table
+            .lock_recovering()
+            .closest_nodes(&target, lookup::K);
+
+        let responses = future::join_all(
+            nodes
+                .iter()
+                .map(|node| self.query_find_node(node.address, target.clone())),
+        )
+        .await;
+
+        responses
+            .into_iter()
+            .filter_map(|response| response.ok())
+            .flat_map(|response| {
+                let found = response.nodes.into_iter().map(|node| node.node_id);
+
+                std::iter::once(response.id).chain(found)
+            })
+            .map(|id| id.distance(&target))
+            .min_by(|lhs, rhs| lhs.deref().cmp(rhs.deref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::testing::{
+            add_good_node,
+            FakeNode,
+        },
+        Dht,
+    };
+    use failure::Error;
+    use krpc_encoding::{
+        NodeID,
+        NodeInfo,
+        Query,
+        Response,
+    };
+    use num_bigint::BigUint;
+    use std::ops::Deref;
+    use tokio::task::LocalSet;
+
+    #[tokio::test]
+    async fn density_sample_distances() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        // Always knows of a node differing from the target in only the lowest
+        // bit, so every sampled distance is one.
+        let neighbor = FakeNode::bind(NodeID::random()).await?;
+        add_good_node(&dht, &neighbor.info())?;
+        let neighbor_id = neighbor.id.clone();
+        let neighbor_address = neighbor.address;
+        neighbor.serve(move |query| match query {
+            Query::FindNode { target, .. } => Some(Response::NextHop {
+                id: neighbor_id.clone(),
+                token: None,
+                nodes: vec![NodeInfo::new(
+                    NodeID::new(target.deref() ^ BigUint::from(1u8)),
+                    neighbor_address.into(),
+                )],
+                nodes6: Vec::new(),
+            }),
+            _ => None,
+        });
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let distances = local.run_until(dht.density_sample(4)).await;
+
+        assert_eq!(distances, vec![NodeID::new(BigUint::from(1u8)); 4]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn density_sample_skips_unanswered() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let silent = FakeNode::bind(NodeID::random()).await?;
+        add_good_node(&dht, &silent.info())?;
+        silent.serve(|_| None);
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let distances = local.run_until(dht.density_sample(2)).await;
+
+        assert!(distances.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use futures::{
+    stream,
+    Stream,
+};
+use krpc_encoding::NodeID;
+use std::net::SocketAddrV4;
+use tokio::sync::broadcast::{
+    self,
+    error::RecvError,
+};
+
+/// Number of info hashes buffered for each subscriber. Subscribers which fall
+/// further behind miss the oldest ones.
+pub const DISCOVERED_BUFFER: usize = 1024;
+
+/// Broadcasts each info hash seen in inbound queries along with the address
+/// of the node which queried for it.
+pub struct DiscoveredInfoHashes {
+    sender: broadcast::Sender<(NodeID, SocketAddrV4)>,
+}
+
+impl DiscoveredInfoHashes {
+    pub fn new(capacity: usize) -> DiscoveredInfoHashes {
+        let (sender, _) = broadcast::channel(capacity);
+
+        DiscoveredInfoHashes { sender }
+    }
+
+    /// Never waits on subscribers.
+    pub fn record(&self, info_hash: &NodeID, from: SocketAddrV4) {
+        // Fails when nobody is subscribed, in which case there is nobody to
+        // tell.
+        let _ = self.sender.send((info_hash.clone(), from));
+    }
+
+    /// Yields info hashes recorded from now on. Ends once the sender is
+    /// dropped.
+    pub fn subscribe(&self) -> impl Stream<Item = (NodeID, SocketAddrV4)> {
+        stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(discovered) => return Some((discovered, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiscoveredInfoHashes;
+    use futures::StreamExt;
+    use krpc_encoding::NodeID;
+    use std::net::SocketAddrV4;
+
+    #[tokio::test]
+    async fn lagging_subscriber_misses_oldest() -> Result<(), failure::Error> {
+        let discovered = DiscoveredInfoHashes::new(4);
+        let mut subscriber = Box::pin(discovered.subscribe());
+
+        let from: SocketAddrV4 = "1.2.3.4:6881".parse()?;
+        let info_hashes = (0..6).map(|_| NodeID::random()).collect::<Vec<NodeID>>();
+        for info_hash in &info_hashes {
+            discovered.record(info_hash, from);
+        }
+        drop(discovered);
+
+        let received = subscriber.collect::<Vec<_>>().await;
+        let expected = info_hashes[2..]
+            .iter()
+            .map(|info_hash| (info_hash.clone(), from))
+            .collect::<Vec<_>>();
+
+        assert_eq!(received, expected);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,394 @@
+//! Append-only log of every message sent and received, for replaying or
+//! analyzing traffic later.
+//!
+//! The log is a sequence of records with all integers big endian:
+//!
+//! ```text
+//! timestamp: u64, milliseconds since the unix epoch
+//! direction: u8, 0 for received and 1 for sent
+//! address family: u8, 4 or 6, then ip: [u8; 4] or [u8; 16], port: u16
+//! message length: u32, then the bencoded message as sent over the wire
+//! ```
+
+use crate::{
+    dht::{
+        poison::LockRecovering,
+        Dht,
+    },
+    errors::{
+        ErrorKind,
+        Result,
+    },
+};
+use byteorder::{
+    NetworkEndian,
+    ReadBytesExt,
+    WriteBytesExt,
+};
+use std::{
+    convert::TryFrom,
+    fs::{
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        BufWriter,
+        Read,
+        Write,
+    },
+    net::{
+        IpAddr,
+        Ipv4Addr,
+        Ipv6Addr,
+        SocketAddr,
+    },
+    path::Path,
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+use tokio_krpc::MessageDirection;
+
+/// Time after which buffered records are written out on the next message.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A message read back from an event log by [`read_event_log`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedMessage {
+    pub timestamp: SystemTime,
+    pub direction: MessageDirection,
+
+    /// Address the message was sent to or received from.
+    pub address: SocketAddr,
+
+    /// The raw bencoded message.
+    pub message: Vec<u8>,
+}
+
+/// Where messages are logged to, if anywhere. Shared with the transports,
+/// which record every message they send and receive.
+pub(super) struct EventLog {
+    writer: Mutex<Option<EventLogWriter>>,
+}
+
+struct EventLogWriter {
+    writer: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl EventLog {
+    pub fn new() -> EventLog {
+        EventLog {
+            writer: Mutex::new(None),
+        }
+    }
+
+    /// Appends a record of `message` to the log, when logging is enabled.
+    pub fn record(&self, direction: MessageDirection, address: SocketAddr, message: &[u8]) {
+        let mut writer = self.writer.lock_recovering();
+        let writer = match writer.as_mut() {
+            Some(writer) => writer,
+            None => return,
+        };
+
+        writer
+            .write_record(SystemTime::now(), direction, address, message)
+            .unwrap_or_else(|err| eprintln!("Error Writing Event Log {}", err));
+    }
+
+    /// Starts logging to `writer`, closing the previous log if any.
+    fn replace(&self, writer: Option<EventLogWriter>) -> io::Result<()> {
+        let previous = std::mem::replace(&mut *self.writer.lock_recovering(), writer);
+
+        match previous {
+            Some(mut previous) => previous.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        match self.writer.lock_recovering().as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl EventLogWriter {
+    fn write_record(
+        &mut self,
+        timestamp: SystemTime,
+        direction: MessageDirection,
+        address: SocketAddr,
+        message: &[u8],
+    ) -> io::Result<()> {
+        let millis = timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or(0);
+        let length = u32::try_from(message.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too long"))?;
+
+        let writer = &mut self.writer;
+        writer.write_u64::<NetworkEndian>(millis)?;
+        writer.write_u8(match direction {
+            MessageDirection::Received => 0,
+            MessageDirection::Sent => 1,
+        })?;
+
+        match address.ip() {
+            IpAddr::V4(ip) => {
+                writer.write_u8(4)?;
+                writer.write_all(&ip.octets())?;
+            }
+            IpAddr::V6(ip) => {
+                writer.write_u8(6)?;
+                writer.write_all(&ip.octets())?;
+            }
+        }
+        writer.write_u16::<NetworkEndian>(address.port())?;
+
+        writer.write_u32::<NetworkEndian>(length)?;
+        writer.write_all(message)?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+
+        self.writer.flush()
+    }
+}
+
+impl Dht {
+    /// Appends a record of every message sent and received from now on to
+    /// the file at `path`, creating it if needed. Inbound queries, outbound
+    /// queries and the responses to both are logged with a timestamp and the
+    /// address of the other node. Read the log back with [`read_event_log`].
+    ///
+    /// Records are buffered and written out when a message is logged at
+    /// least a second after the last write, or when [`Dht::flush_event_log`]
+    /// or [`Dht::disable_event_log`] is called. Replaces any log enabled
+    /// earlier.
+    pub fn enable_event_log<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|cause| ErrorKind::EventLogError { cause })?;
+
+        self.event_log
+            .replace(Some(EventLogWriter {
+                writer: BufWriter::new(file),
+                last_flush: Instant::now(),
+            }))
+            .map_err(|cause| ErrorKind::EventLogError { cause })?;
+
+        Ok(())
+    }
+
+    /// Writes out records buffered by the log enabled with
+    /// [`Dht::enable_event_log`].
+    pub fn flush_event_log(&self) -> Result<()> {
+        self.event_log
+            .flush()
+            .map_err(|cause| ErrorKind::EventLogError { cause })?;
+
+        Ok(())
+    }
+
+    /// Stops logging messages, writing out any buffered records and closing
+    /// the log.
+    pub fn disable_event_log(&self) -> Result<()> {
+        self.event_log
+            .replace(None)
+            .map_err(|cause| ErrorKind::EventLogError { cause })?;
+
+        Ok(())
+    }
+}
+
+/// Reads every record from a log written by [`Dht::enable_event_log`].
+pub fn read_event_log<R: Read>(reader: &mut R) -> Result<Vec<LoggedMessage>> {
+    let mut messages = Vec::new();
+
+    while let Some(message) =
+        read_record(reader).map_err(|cause| ErrorKind::EventLogError { cause })?
+    {
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+fn read_record<R: Read>(reader: &mut R) -> io::Result<Option<LoggedMessage>> {
+    let millis = match reader.read_u64::<NetworkEndian>() {
+        Ok(millis) => millis,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let direction = match reader.read_u8()? {
+        0 => MessageDirection::Received,
+        1 => MessageDirection::Sent,
+        _ => return Err(malformed("unknown direction")),
+    };
+
+    let ip = match reader.read_u8()? {
+        4 => {
+            let mut octets = [0u8; 4];
+            reader.read_exact(&mut octets)?;
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        6 => {
+            let mut octets = [0u8; 16];
+            reader.read_exact(&mut octets)?;
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return Err(malformed("unknown address family")),
+    };
+    let port = reader.read_u16::<NetworkEndian>()?;
+
+    let length = reader.read_u32::<NetworkEndian>()?;
+    let mut message = vec![0u8; length as usize];
+    reader.read_exact(&mut message)?;
+
+    Ok(Some(LoggedMessage {
+        timestamp: UNIX_EPOCH + Duration::from_millis(millis),
+        direction,
+        address: SocketAddr::new(ip, port),
+        message,
+    }))
+}
+
+fn malformed(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_event_log;
+    use crate::{
+        addr::{
+            AsV4Address,
+            IntoSocketAddr,
+        },
+        dht::testing::{
+            add_good_node,
+            FakeNode,
+        },
+        Dht,
+    };
+    use failure::Error;
+    use futures::{
+        future,
+        StreamExt,
+        TryStreamExt,
+    };
+    use krpc_encoding::{
+        Envelope,
+        Message,
+        NodeID,
+        Query,
+        Response,
+    };
+    use std::{
+        fs::{
+            self,
+            File,
+        },
+        net::SocketAddr,
+    };
+    use tokio::{
+        net::UdpSocket,
+        task::LocalSet,
+    };
+    use tokio_krpc::{
+        KRPCNode,
+        MessageDirection,
+        RequestTransport,
+    };
+
+    #[tokio::test]
+    async fn queries_logged() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("dht-events-{}", NodeID::random()));
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        dht.enable_event_log(&path)?;
+        let dht_address = dht.local_addr().into_v4()?;
+
+        let node = FakeNode::bind(NodeID::random()).await?;
+        let node_address: SocketAddr = node.address.into();
+        add_good_node(&dht, &node.info())?;
+        let node_id = node.id.clone();
+        node.serve(move |query| match query {
+            Query::GetPeers { .. } => Some(Response::NextHop {
+                id: node_id.clone(),
+                token: None,
+                nodes: Vec::new(),
+                nodes6: Vec::new(),
+            }),
+            _ => None,
+        });
+
+        let client_socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let client_address = client_socket.local_addr()?;
+        let (send_transport, request_stream, _) = KRPCNode::new(client_socket).serve();
+        let client = RequestTransport::new(NodeID::random(), send_transport);
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local.spawn_local(
+            request_stream
+                .map_err(|err| eprintln!("Error in Request Stream: {}", err))
+                .for_each(|_| future::ready(())),
+        );
+
+        local
+            .run_until(async {
+                client.ping(dht_address).await?;
+                dht.get_peers(NodeID::random()).await?;
+
+                Ok::<(), Error>(())
+            })
+            .await?;
+
+        dht.disable_event_log()?;
+        let logged = read_event_log(&mut File::open(&path)?)?;
+        fs::remove_file(&path)?;
+
+        let summary = logged
+            .iter()
+            .map(|logged| {
+                let envelope = Envelope::decode(&logged.message)?;
+                let is_query = match envelope.message_type {
+                    Message::Query { .. } => true,
+                    _ => false,
+                };
+
+                Ok((logged.direction, logged.address, is_query))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        assert_eq!(
+            summary,
+            vec![
+                (MessageDirection::Received, client_address, true),
+                (MessageDirection::Sent, client_address, false),
+                (MessageDirection::Sent, node_address, true),
+                (MessageDirection::Received, node_address, false),
+            ]
+        );
+
+        Ok(())
+    }
+}
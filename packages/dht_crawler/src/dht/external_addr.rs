@@ -0,0 +1,162 @@
+use crate::errors::Result;
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    net::{
+        IpAddr,
+        SocketAddrV4,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+/// Number of most recent reports, one per reporting IP, the consensus is
+/// taken over.
+const REPORT_WINDOW: usize = 32;
+
+/// Minimum number of reports agreeing on an address for it to become the
+/// consensus.
+const MIN_AGREEING_REPORTS: usize = 3;
+
+type ChangeCallback = Arc<dyn Fn(Option<SocketAddrV4>, SocketAddrV4) + Send + Sync>;
+
+/// Works out the address other nodes see us at from the `ip` field of their
+/// responses. An address becomes the consensus once more than half of the
+/// recent reports agree on it.
+pub struct ExternalAddrTracker {
+    votes: Mutex<Votes>,
+    callbacks: Mutex<Vec<ChangeCallback>>,
+}
+
+struct Votes {
+    reports: VecDeque<(IpAddr, SocketAddrV4)>,
+    consensus: Option<SocketAddrV4>,
+}
+
+impl Votes {
+    /// Records a report and returns the previous and new consensus if it
+    /// changed.
+    fn record(
+        &mut self,
+        reporter: IpAddr,
+        addr: SocketAddrV4,
+    ) -> Option<(Option<SocketAddrV4>, SocketAddrV4)> {
+        self.reports.retain(|(ip, _)| *ip != reporter);
+        self.reports.push_back((reporter, addr));
+        if self.reports.len() > REPORT_WINDOW {
+            self.reports.pop_front();
+        }
+
+        let mut counts = HashMap::new();
+        for (_, addr) in &self.reports {
+            *counts.entry(*addr).or_insert(0usize) += 1;
+        }
+
+        let total = self.reports.len();
+        let winner = counts
+            .into_iter()
+            .find(|(_, count)| *count >= MIN_AGREEING_REPORTS && count * 2 > total)
+            .map(|(addr, _)| addr)?;
+
+        if self.consensus == Some(winner) {
+            return None;
+        }
+
+        let previous = self.consensus.replace(winner);
+
+        Some((previous, winner))
+    }
+}
+
+impl ExternalAddrTracker {
+    pub fn new() -> ExternalAddrTracker {
+        ExternalAddrTracker {
+            votes: Mutex::new(Votes {
+                reports: VecDeque::new(),
+                consensus: None,
+            }),
+            callbacks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Records that the node at `reporter` sees us at `addr`, calling the
+    /// change callbacks if this changes the consensus.
+    pub fn report(&self, reporter: IpAddr, addr: SocketAddrV4) -> Result<()> {
+        let change = self.votes.lock()?.record(reporter, addr);
+
+        if let Some((previous, current)) = change {
+            // Called without holding any locks so callbacks can use the Dht.
+            let callbacks = self.callbacks.lock()?.clone();
+            for callback in callbacks {
+                callback(previous, current);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn current(&self) -> Result<Option<SocketAddrV4>> {
+        Ok(self.votes.lock()?.consensus)
+    }
+
+    pub fn on_change<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(Option<SocketAddrV4>, SocketAddrV4) + Send + Sync + 'static,
+    {
+        self.callbacks.lock()?.push(Arc::new(callback));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        Dht,
+    };
+    use failure::Error;
+    use std::{
+        net::{
+            IpAddr,
+            Ipv4Addr,
+            SocketAddrV4,
+        },
+        sync::{
+            Arc,
+            Mutex,
+        },
+    };
+
+    #[tokio::test]
+    async fn conflicting_reports_converge() -> Result<(), Error> {
+        let (dht, _dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        dht.on_external_addr_change(move |previous, current| {
+            recorded.lock().unwrap().push((previous, current));
+        })?;
+
+        let old: SocketAddrV4 = "198.51.100.1:6881".parse()?;
+        let new: SocketAddrV4 = "203.0.113.7:6881".parse()?;
+        let reporter = |n: u8| IpAddr::V4(Ipv4Addr::new(10, 0, 0, n));
+
+        for (n, addr) in [old, new, old, new, new, new].iter().enumerate() {
+            dht.external_addr.report(reporter(n as u8), *addr)?;
+        }
+
+        // a reporter repeating itself doesn't count twice
+        dht.external_addr.report(reporter(0), old)?;
+        dht.external_addr.report(reporter(0), old)?;
+
+        assert_eq!(dht.external_addr()?, Some(new));
+        assert_eq!(*changes.lock().unwrap(), vec![(None, new)]);
+
+        Ok(())
+    }
+}
@@ -1,13 +1,20 @@
 use crate::{
-    addr::AsV4Address,
-    dht::Dht,
+    dht::{
+        lookup,
+        poison::LockRecovering,
+        Dht,
+        QueryType,
+        ResponsePolicy,
+    },
     errors::{
+        Error,
         ErrorKind,
         Result,
     },
     routing::{
         FindNodeResult,
         RoutingTable,
+        SelectionPolicy,
     },
 };
 use futures::Stream;
@@ -17,6 +24,8 @@ use krpc_encoding::{
     Envelope,
     Message,
     NodeID,
+    NodeInfo,
+    PortType,
     Query,
     Response,
 };
@@ -24,13 +33,18 @@ use std::{
     net::{
         SocketAddr,
         SocketAddrV4,
+        SocketAddrV6,
     },
     ops::DerefMut,
+    sync::atomic::Ordering,
+    time::Instant,
 };
 use tokio_krpc::InboundQuery;
 
 impl Dht {
-    pub(super) async fn handle_requests<S: Stream<Item = Result<(InboundQuery, SocketAddr)>>>(
+    pub(super) async fn handle_requests<
+        S: Stream<Item = Result<(InboundQuery, SocketAddr, usize)>>,
+    >(
         self,
         stream: S,
     ) {
@@ -50,15 +64,45 @@ impl Dht {
         }
     }
 
-    async fn process_request(&self, result: Result<(InboundQuery, SocketAddr)>) -> Result<()> {
-        let (request, from) = result?;
-        let response = self.handle_request(request, from.into_v4()?);
-        self.send_transport.send(from, response).await?;
+    /// Responds to a query received on the socket at `index` from that same
+    /// socket.
+    async fn process_request(
+        &self,
+        result: Result<(InboundQuery, SocketAddr, usize)>,
+    ) -> Result<()> {
+        let (request, from, index) = result?;
+        if self.shutting_down.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        if self.query_log.sample() {
+            eprintln!(
+                "Query From {} transaction_id={:?}: {:?}",
+                from, request.transaction_id, request.query
+            );
+        }
+
+        let query_type = QueryType::of(&request.query);
+        let response = match from {
+            SocketAddr::V4(from) => self.handle_request(request, from),
+            SocketAddr::V6(from) => self.handle_request_v6(request, from),
+        };
+
+        if self.response_policies.lock()?.get(query_type) == ResponsePolicy::Drop {
+            return Ok(());
+        }
+
+        self.transports
+            .send_transport(index)
+            .send(from, response)
+            .await?;
 
         Ok(())
     }
 
     fn handle_request(&self, request: InboundQuery, from: SocketAddrV4) -> Envelope {
+        self.query_counters.record(&request.query);
+
         let result = match request.query {
             Query::Ping { id } => self.handle_ping(from, id, request.read_only),
             Query::FindNode { id, target } => {
@@ -69,19 +113,10 @@ impl Dht {
             }
             Query::AnnouncePeer {
                 id,
-                implied_port,
                 port,
                 info_hash,
                 token,
-            } => self.handle_announce_peer(
-                from,
-                id,
-                implied_port,
-                port,
-                info_hash,
-                token,
-                request.read_only,
-            ),
+            } => self.handle_announce_peer(from, id, port, info_hash, token, request.read_only),
             _ => Err(ErrorKind::UnimplementedRequestType.into()),
         };
 
@@ -92,17 +127,47 @@ impl Dht {
             },
         };
 
+        Envelope {
+            ip: Some(Addr::from(from)),
+            transaction_id: request.transaction_id,
+            version: self.config.response_version.clone().map(Into::into),
+            message_type,
+            read_only: false,
+        }
+    }
+
+    /// Answers a query which arrived over IPv6. Only pings are answered as
+    /// the routing table, tokens and torrents cache only hold IPv4 addresses,
+    /// so IPv6 nodes aren't remembered.
+    fn handle_request_v6(&self, request: InboundQuery, from: SocketAddrV6) -> Envelope {
+        self.query_counters.record(&request.query);
+
+        let message_type = match request.query {
+            Query::Ping { .. } => Message::Response {
+                response: Response::OnlyID {
+                    id: self.id.clone(),
+                },
+            },
+            _ => {
+                eprintln!("Unsupported IPv6 Query From {}", from);
+
+                Message::Error {
+                    error: Error::from(ErrorKind::UnimplementedRequestType).as_request_error(),
+                }
+            }
+        };
+
         Envelope {
             ip: None,
             transaction_id: request.transaction_id,
-            version: None,
+            version: self.config.response_version.clone().map(Into::into),
             message_type,
             read_only: false,
         }
     }
 
     fn handle_ping(&self, from: SocketAddrV4, id: NodeID, read_only: bool) -> Result<Response> {
-        let mut routing_table = self.routing_table.lock()?;
+        let mut routing_table = self.routing_table.lock_recovering();
         record_request(&mut routing_table, id, from, read_only)?;
 
         Ok(Response::OnlyID {
@@ -117,18 +182,30 @@ impl Dht {
         target: NodeID,
         read_only: bool,
     ) -> Result<Response> {
-        let mut routing_table = self.routing_table.lock()?;
+        let mut routing_table = self.routing_table.lock_recovering();
         record_request(&mut routing_table, id, from, read_only)?;
 
-        let nodes = match routing_table.find_node(&target) {
-            FindNodeResult::Node(node) => vec![node],
-            FindNodeResult::Nodes(nodes) => nodes,
+        let nodes = match (routing_table.find_node(&target), self.config.selection) {
+            (FindNodeResult::Node(node), _) => vec![node],
+            (FindNodeResult::Nodes(nodes), SelectionPolicy::Closest) => {
+                self.requery_questionable_near(&target, lookup::K, nodes.len(), &routing_table)?;
+
+                nodes
+            }
+            (FindNodeResult::Nodes(_), policy) => {
+                let nodes = routing_table.select_nodes(&target, lookup::K, policy);
+                self.requery_questionable_near(&target, lookup::K, nodes.len(), &routing_table)?;
+
+                nodes
+            }
         };
+        let nodes = self.with_fallback_nodes(nodes, routing_table.good_len());
 
         Ok(Response::NextHop {
             id: self.id.clone(),
             token: None,
             nodes,
+            nodes6: Vec::new(),
         })
     }
 
@@ -139,13 +216,20 @@ impl Dht {
         info_hash: NodeID,
         read_only: bool,
     ) -> Result<Response> {
-        let mut routing_table = self.routing_table.lock()?;
+        validate_info_hash(&info_hash, from)?;
+        self.discovered.record(&info_hash, from);
+        self.reservoir.lock()?.offer(info_hash.clone());
+
+        let mut routing_table = self.routing_table.lock_recovering();
         record_request(&mut routing_table, id, from, read_only)?;
 
         let token_bytes = routing_table.generate_token(&from).to_vec();
         let token = Some(token_bytes);
+        let canned_peers = self.canned_peers.lock()?;
         let torrents = self.torrents.lock()?;
-        let torrent = torrents.get(&info_hash);
+        let torrent = canned_peers
+            .get(&info_hash)
+            .or_else(|| torrents.get(&info_hash));
 
         if let Some(peers) = torrent {
             Ok(Response::GetPeers {
@@ -154,48 +238,109 @@ impl Dht {
                 peers: peers.iter().map(|peer| Addr::from(peer.clone())).collect(),
             })
         } else {
-            let nodes = routing_table.find_nodes(&info_hash);
+            let nodes = routing_table.select_nodes(
+                &info_hash,
+                self.config.get_peers_response_nodes,
+                self.config.selection,
+            );
+            self.requery_questionable_near(
+                &info_hash,
+                self.config.get_peers_response_nodes,
+                nodes.len(),
+                &routing_table,
+            )?;
+            let nodes = self.with_fallback_nodes(nodes, routing_table.good_len());
 
             Ok(Response::NextHop {
                 id: self.id.clone(),
                 token,
                 nodes,
+                nodes6: Vec::new(),
             })
         }
     }
 
+    /// Appends the configured fallback nodes to `nodes` when the routing table
+    /// has too few good nodes to give a useful response.
+    fn with_fallback_nodes(&self, mut nodes: Vec<NodeInfo>, good_nodes: usize) -> Vec<NodeInfo> {
+        if good_nodes >= self.config.fallback_threshold {
+            return nodes;
+        }
+
+        for fallback_node in &self.config.fallback_nodes {
+            if !nodes
+                .iter()
+                .any(|node| node.node_id == fallback_node.node_id)
+            {
+                nodes.push(fallback_node.clone());
+            }
+        }
+
+        nodes
+    }
+
     fn handle_announce_peer(
         &self,
         mut from: SocketAddrV4,
         id: NodeID,
-        implied_port: bool,
-        port: Option<u16>,
+        port: PortType,
         info_hash: NodeID,
-        token: Vec<u8>,
+        token: Option<Vec<u8>>,
         read_only: bool,
     ) -> Result<Response> {
-        let mut routing_table = self.routing_table.lock()?;
+        validate_info_hash(&info_hash, from)?;
+        if self.config.reject_spoofed_announces && !id.is_valid_for_ip(*from.ip()) {
+            return Err(ErrorKind::InvalidNodeIDForIP { id, ip: *from.ip() })?;
+        }
+
+        self.discovered.record(&info_hash, from);
+
+        let mut routing_table = self.routing_table.lock_recovering();
+
+        let token = match token {
+            None => return Err(ErrorKind::InvalidToken)?,
+            Some(token) => token,
+        };
 
         if !routing_table.verify_token(&token, &from) {
             return Err(ErrorKind::InvalidToken)?;
         };
 
-        let addr = if implied_port {
-            from
-        } else {
-            let actual_port = match port {
-                None => return Err(ErrorKind::InsufficientAddress)?,
-                Some(port) => port,
-            };
-
-            from.set_port(actual_port);
-            from
+        let addr = match port {
+            PortType::Implied(_) => from,
+            PortType::Port(port) => {
+                from.set_port(port);
+                from
+            }
+            PortType::Missing => return Err(ErrorKind::InsufficientAddress)?,
         };
 
         record_request(&mut routing_table, id, from, read_only)?;
 
+        let rate = self.announce_rates.lock()?.record(*from.ip(), Instant::now());
+        let flooding = rate > self.config.announce_flood_threshold;
+
         let mut torrents = self.torrents.lock()?;
 
+        if flooding && !torrents.contains_key(&info_hash) {
+            return Ok(Response::OnlyID {
+                id: self.id.clone(),
+            });
+        }
+
+        if let Some(max_peers) = self.config.max_peers_per_source {
+            let contributed = self
+                .peer_contributions
+                .lock()?
+                .record(*from.ip(), Instant::now());
+            if contributed > max_peers as f64 {
+                return Ok(Response::OnlyID {
+                    id: self.id.clone(),
+                });
+            }
+        }
+
+        self.reservoir.lock()?.offer(info_hash.clone());
         torrents
             .entry(info_hash)
             .or_insert_with(Vec::new)
@@ -207,6 +352,20 @@ impl Dht {
     }
 }
 
+/// Rejects info hashes which can't have come from a well formed query. Decoding
+/// already checks lengths, so this only guards against other code paths.
+fn validate_info_hash(info_hash: &NodeID, from: SocketAddrV4) -> Result<()> {
+    if !info_hash.has_valid_length() {
+        eprintln!("Malformed Info Hash {} From {}", info_hash, from);
+
+        return Err(ErrorKind::InvalidInfoHash {
+            info_hash: info_hash.clone(),
+        })?;
+    }
+
+    Ok(())
+}
+
 fn record_request<T: DerefMut<Target = RoutingTable>>(
     routing_table: &mut T,
     id: NodeID,
@@ -222,3 +381,461 @@ fn record_request<T: DerefMut<Target = RoutingTable>>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::{
+            DhtConfig,
+            QueryCounts,
+        },
+        errors::Error as DhtError,
+        Dht,
+    };
+    use failure::Error;
+    use futures::StreamExt;
+    use krpc_encoding::{
+        Addr,
+        Envelope,
+        Message,
+        NodeID,
+        NodeInfo,
+        PortType,
+        Query,
+        Response,
+    };
+    use std::net::SocketAddrV4;
+    use tokio_krpc::InboundQuery;
+
+    #[tokio::test]
+    async fn response_includes_source_address() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::Ping {
+                    id: NodeID::random(),
+                },
+                false,
+            ),
+            from,
+        );
+
+        let encoded = response.encode()?;
+        let compact_ip: &[u8] = b"2:ip6:\x81\x15\x3f\xaa\x85\xbe";
+        assert!(encoded
+            .windows(compact_ip.len())
+            .any(|window| window == compact_ip));
+
+        let decoded = Envelope::decode(&encoded)?;
+        assert_eq!(decoded.ip, Some(Addr::from(from)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn announce_peer_without_token() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::AnnouncePeer {
+                    id: NodeID::random(),
+                    port: PortType::Implied(6881),
+                    info_hash: NodeID::random(),
+                    token: None,
+                },
+                false,
+            ),
+            from,
+        );
+
+        match response.message_type {
+            Message::Error { error } => assert_eq!(error.code(), 203),
+            other => panic!("expected error, got {:?}", other),
+        };
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn announce_peer_without_port() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+        let token = dht
+            .routing_table
+            .lock()
+            .map_err(DhtError::from)?
+            .generate_token(&from)
+            .to_vec();
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::AnnouncePeer {
+                    id: NodeID::random(),
+                    port: PortType::Missing,
+                    info_hash: NodeID::random(),
+                    token: Some(token),
+                },
+                false,
+            ),
+            from,
+        );
+
+        match response.message_type {
+            Message::Error { error } => assert_eq!(error.code(), 203),
+            other => panic!("expected error, got {:?}", other),
+        };
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_peers_malformed_info_hash() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::GetPeers {
+                    id: NodeID::random(),
+                    info_hash: NodeID::from_bytes(&[1u8; 21]),
+                },
+                false,
+            ),
+            from,
+        );
+
+        match response.message_type {
+            Message::Error { error } => assert_eq!(error.code(), 203),
+            other => panic!("expected error, got {:?}", other),
+        };
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_counts() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+
+        for query in vec![
+            Query::Ping {
+                id: NodeID::random(),
+            },
+            Query::FindNode {
+                id: NodeID::random(),
+                target: NodeID::random(),
+            },
+            Query::GetPeers {
+                id: NodeID::random(),
+                info_hash: NodeID::random(),
+            },
+            Query::AnnouncePeer {
+                id: NodeID::random(),
+                port: PortType::Implied(6881),
+                info_hash: NodeID::random(),
+                token: None,
+            },
+            Query::Ping {
+                id: NodeID::random(),
+            },
+        ] {
+            dht.handle_request(InboundQuery::new(b"aa".to_vec(), query, false), from);
+        }
+
+        assert_eq!(
+            dht.query_counts(),
+            QueryCounts {
+                ping: 2,
+                find_node: 1,
+                get_peers: 1,
+                announce_peer: 1,
+                other: 0,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn response_includes_configured_version() -> Result<(), Error> {
+        let config = DhtConfig {
+            response_version: Some(b"DC01".to_vec()),
+            ..DhtConfig::default()
+        };
+        let (dht, _) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::Ping {
+                    id: NodeID::random(),
+                },
+                false,
+            ),
+            "129.21.63.170:34238".parse()?,
+        );
+
+        let encoded = response.encode()?;
+        let version: &[u8] = b"1:v4:DC01";
+        assert!(encoded.windows(version.len()).any(|window| window == version));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn find_node_includes_fallback_nodes() -> Result<(), Error> {
+        let fallback_node = NodeInfo::new(NodeID::random(), "67.215.246.10:6881".parse()?);
+        let config = DhtConfig {
+            fallback_nodes: vec![fallback_node.clone()],
+            ..DhtConfig::default()
+        };
+        let (dht, _) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::FindNode {
+                    id: NodeID::random(),
+                    target: NodeID::random(),
+                },
+                false,
+            ),
+            "129.21.63.170:34238".parse()?,
+        );
+
+        match response.message_type {
+            Message::Response {
+                response: Response::NextHop { nodes, .. },
+            } => assert_eq!(nodes, vec![fallback_node]),
+            other => panic!("expected nodes, got {:?}", other),
+        };
+
+        Ok(())
+    }
+
+    /// Sends `dht` an announce of `info_hash` with a valid token from the node
+    /// `id` at `from`, returning the message sent back.
+    fn announce(
+        dht: &Dht,
+        from: SocketAddrV4,
+        id: NodeID,
+        info_hash: NodeID,
+    ) -> Result<Message, Error> {
+        let token = dht
+            .routing_table
+            .lock()
+            .map_err(DhtError::from)?
+            .generate_token(&from)
+            .to_vec();
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::AnnouncePeer {
+                    id,
+                    port: PortType::Implied(6881),
+                    info_hash,
+                    token: Some(token),
+                },
+                false,
+            ),
+            from,
+        );
+
+        Ok(response.message_type)
+    }
+
+    #[tokio::test]
+    async fn announce_flood_not_stored() -> Result<(), Error> {
+        let config = DhtConfig {
+            announce_flood_threshold: 3.0,
+            ..DhtConfig::default()
+        };
+        let (dht, _) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let flooder: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+        let info_hashes = (0..5).map(|_| NodeID::random()).collect::<Vec<NodeID>>();
+        for info_hash in &info_hashes {
+            match announce(&dht, flooder, NodeID::random(), info_hash.clone())? {
+                Message::Response {
+                    response: Response::OnlyID { .. },
+                } => (),
+                other => panic!("expected response, got {:?}", other),
+            }
+        }
+
+        let other_info_hash = NodeID::random();
+        announce(
+            &dht,
+            "1.2.3.4:6881".parse()?,
+            NodeID::random(),
+            other_info_hash.clone(),
+        )?;
+
+        let torrents = dht.torrents.lock().map_err(DhtError::from)?;
+        let stored = info_hashes
+            .iter()
+            .map(|info_hash| torrents.contains_key(info_hash))
+            .collect::<Vec<bool>>();
+        assert_eq!(stored, vec![true, true, true, false, false]);
+        assert!(torrents.contains_key(&other_info_hash));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn peers_per_source_capped() -> Result<(), Error> {
+        let config = DhtConfig {
+            max_peers_per_source: Some(3),
+            ..DhtConfig::default()
+        };
+        let (dht, _) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let dominating: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+        let info_hashes = (0..5).map(|_| NodeID::random()).collect::<Vec<NodeID>>();
+        for info_hash in &info_hashes {
+            match announce(&dht, dominating, NodeID::random(), info_hash.clone())? {
+                Message::Response {
+                    response: Response::OnlyID { .. },
+                } => (),
+                other => panic!("expected response, got {:?}", other),
+            }
+        }
+
+        let other_info_hash = NodeID::random();
+        announce(
+            &dht,
+            "1.2.3.4:6881".parse()?,
+            NodeID::random(),
+            other_info_hash.clone(),
+        )?;
+
+        let torrents = dht.torrents.lock().map_err(DhtError::from)?;
+        let stored = info_hashes
+            .iter()
+            .map(|info_hash| torrents.contains_key(info_hash))
+            .collect::<Vec<bool>>();
+        assert_eq!(stored, vec![true, true, true, false, false]);
+        assert!(torrents.contains_key(&other_info_hash));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spoofed_announce_rejected() -> Result<(), Error> {
+        let config = DhtConfig {
+            reject_spoofed_announces: true,
+            ..DhtConfig::default()
+        };
+        let (dht, _) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+
+        let spoofed_info_hash = NodeID::random();
+        let spoofed_id = NodeID::from_ip_bep42("1.2.3.4".parse()?, 0);
+        match announce(&dht, from, spoofed_id, spoofed_info_hash.clone())? {
+            Message::Error { error } => assert_eq!(error.code(), 203),
+            other => panic!("expected error, got {:?}", other),
+        };
+
+        let info_hash = NodeID::random();
+        match announce(
+            &dht,
+            from,
+            NodeID::from_ip_bep42(*from.ip(), 0),
+            info_hash.clone(),
+        )? {
+            Message::Response {
+                response: Response::OnlyID { .. },
+            } => (),
+            other => panic!("expected response, got {:?}", other),
+        };
+
+        let torrents = dht.torrents.lock().map_err(DhtError::from)?;
+        assert!(!torrents.contains_key(&spoofed_info_hash));
+        assert!(torrents.contains_key(&info_hash));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_peers_returns_canned_peers() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let info_hash = NodeID::random();
+        let canned_peer: SocketAddrV4 = "10.0.0.1:6881".parse()?;
+
+        dht.torrents
+            .lock()
+            .map_err(DhtError::from)?
+            .insert(info_hash.clone(), vec!["1.2.3.4:6881".parse()?]);
+        dht.set_canned_peers(info_hash.clone(), vec![canned_peer])?;
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::GetPeers {
+                    id: NodeID::random(),
+                    info_hash,
+                },
+                false,
+            ),
+            "129.21.63.170:34238".parse()?,
+        );
+
+        match response.message_type {
+            Message::Response {
+                response: Response::GetPeers { peers, .. },
+            } => assert_eq!(peers, vec![Addr::from(canned_peer)]),
+            other => panic!("expected peers, got {:?}", other),
+        };
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn queried_info_hashes_discovered() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let mut discovered = Box::pin(dht.discovered_infohashes());
+
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+        let (wanted, announced) = (NodeID::random(), NodeID::random());
+
+        dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::GetPeers {
+                    id: NodeID::random(),
+                    info_hash: wanted.clone(),
+                },
+                false,
+            ),
+            from,
+        );
+        dht.handle_request(
+            InboundQuery::new(
+                b"ab".to_vec(),
+                Query::AnnouncePeer {
+                    id: NodeID::random(),
+                    port: PortType::Implied(6881),
+                    info_hash: announced.clone(),
+                    token: None,
+                },
+                false,
+            ),
+            from,
+        );
+
+        assert_eq!(discovered.next().await, Some((wanted, from)));
+        assert_eq!(discovered.next().await, Some((announced, from)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    dht::{
+        poison::LockRecovering,
+        Dht,
+        QUERY_TIMEOUT,
+    },
+    errors::Result,
+};
+use futures::{
+    future,
+    stream,
+    StreamExt,
+};
+use std::{
+    net::SocketAddrV4,
+    time::Duration,
+};
+use tokio::time::timeout;
+
+/// Maximum number of pings in flight while measuring responsiveness.
+const RESPONSIVENESS_CONCURRENCY: usize = 8;
+
+/// Maximum number of pings in flight while probing bootstrap nodes.
+const BOOTSTRAP_PROBE_CONCURRENCY: usize = 8;
+
+/// Time to wait for a bootstrap node to answer a ping. Shorter than
+/// [`QUERY_TIMEOUT`] so a probe of unreachable nodes fails fast.
+const BOOTSTRAP_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Share of a sample of routing table nodes which answered a ping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Responsiveness {
+    /// Fraction of sampled nodes which responded, between 0 and 1. Zero when
+    /// no nodes were sampled.
+    pub fraction: f64,
+
+    /// Number of nodes actually pinged. Less than requested when the routing
+    /// table is smaller than the sample.
+    pub sampled: usize,
+}
+
+impl Dht {
+    /// Pings up to `sample` nodes picked at random from the routing table and
+    /// reports how many of them responded before timing out.
+    pub async fn responsiveness(&self, sample: usize) -> Result<Responsiveness> {
+        let nodes = self.routing_table.lock_recovering().random_nodes(sample);
+        let sampled = nodes.len();
+
+        let responded = stream::iter(nodes)
+            .map(|node| {
+                timeout(
+                    QUERY_TIMEOUT,
+                    self.transports
+                        .request_transport(&node.address)
+                        .ping(node.address),
+                )
+            })
+            .buffer_unordered(RESPONSIVENESS_CONCURRENCY)
+            .filter(|result| future::ready(matches!(result, Ok(Ok(_)))))
+            .count()
+            .await;
+
+        let fraction = if sampled == 0 {
+            0.0
+        } else {
+            responded as f64 / sampled as f64
+        };
+
+        Ok(Responsiveness { fraction, sampled })
+    }
+
+    /// Pings each of `addrs` and reports whether it answered, in the same
+    /// order as `addrs`. Useful for warning about unreachable bootstrap nodes
+    /// before starting a crawl.
+    pub async fn check_bootstrap_health(
+        &self,
+        addrs: Vec<SocketAddrV4>,
+    ) -> Vec<(SocketAddrV4, bool)> {
+        stream::iter(addrs)
+            .map(|addr| async move {
+                let result = timeout(
+                    BOOTSTRAP_PROBE_TIMEOUT,
+                    self.transports.request_transport(&addr.into()).ping(addr),
+                )
+                .await;
+
+                (addr, matches!(result, Ok(Ok(_))))
+            })
+            .buffered(BOOTSTRAP_PROBE_CONCURRENCY)
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Responsiveness;
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::testing::{
+            add_good_node,
+            FakeNode,
+        },
+        Dht,
+    };
+    use failure::Error;
+    use krpc_encoding::{
+        NodeID,
+        Query,
+        Response,
+    };
+    use tokio::task::LocalSet;
+
+    #[tokio::test]
+    async fn responsiveness() -> Result<(), Error> {
+        let responsive = FakeNode::bind(NodeID::random()).await?;
+        let unresponsive = FakeNode::bind(NodeID::random()).await?;
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &responsive.info())?;
+        add_good_node(&dht, &unresponsive.info())?;
+
+        let id = responsive.id.clone();
+        responsive.serve(move |query| match query {
+            Query::Ping { .. } => Some(Response::OnlyID { id: id.clone() }),
+            _ => None,
+        });
+        unresponsive.serve(|_| None);
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let result = local.run_until(dht.responsiveness(5)).await?;
+
+        assert_eq!(
+            result,
+            Responsiveness {
+                fraction: 0.5,
+                sampled: 2,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_health() -> Result<(), Error> {
+        let reachable = FakeNode::bind(NodeID::random()).await?;
+        let unreachable = FakeNode::bind(NodeID::random()).await?;
+        let reachable_address = reachable.address;
+        let unreachable_address = unreachable.address;
+
+        let id = reachable.id.clone();
+        reachable.serve(move |query| match query {
+            Query::Ping { .. } => Some(Response::OnlyID { id: id.clone() }),
+            _ => None,
+        });
+        unreachable.serve(|_| None);
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let result = local
+            .run_until(dht.check_bootstrap_health(vec![unreachable_address, reachable_address]))
+            .await;
+
+        assert_eq!(
+            result,
+            vec![(unreachable_address, false), (reachable_address, true)]
+        );
+
+        Ok(())
+    }
+}
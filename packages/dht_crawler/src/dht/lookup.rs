@@ -0,0 +1,390 @@
+use crate::{
+    dht::{
+        poison::LockRecovering,
+        Dht,
+        QUERY_TIMEOUT,
+    },
+    errors::{
+        ErrorKind,
+        Result,
+    },
+};
+use futures::{
+    stream::FuturesUnordered,
+    StreamExt,
+};
+use krpc_encoding::{
+    NodeID,
+    NodeInfo,
+};
+use num_bigint::BigUint;
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    net::{
+        SocketAddr,
+        SocketAddrV4,
+    },
+    ops::Deref,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use tokio::time::timeout;
+use tokio_krpc::responses::{
+    GetPeersResponse,
+    GetPeersResponseType,
+};
+
+/// Number of closest nodes a lookup converges on.
+pub const K: usize = 8;
+
+/// How long tokens from a lookup are reused for announces. Nodes accept
+/// tokens for about ten minutes after handing them out.
+const TOKEN_REUSE_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Tokens handed out during the most recent lookup of an info hash.
+pub(super) struct StoredTokens {
+    fetched_at: Instant,
+    tokens: Vec<(NodeInfo, Vec<u8>)>,
+}
+
+/// Outcome of an iterative `get_peers` lookup.
+pub struct LookupResult {
+    /// Peers returned by any node, in the order they were discovered.
+    pub peers: Vec<SocketAddrV4>,
+
+    /// Nodes which responded with a token along with the token, closest to the
+    /// info hash first.
+    pub tokens: Vec<(NodeInfo, Vec<u8>)>,
+
+    /// Addresses of nodes which responded with an id other than the one they
+    /// were known by earlier in the lookup, in the order they were detected.
+    pub id_changes: Vec<SocketAddr>,
+
+    pub metrics: GetPeersMetrics,
+}
+
+/// Latencies measured over an iterative `get_peers` lookup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GetPeersMetrics {
+    /// Time from the start of the lookup until the first peer was returned.
+    /// `None` when no peers were found.
+    pub time_to_first_peer: Option<Duration>,
+
+    /// Time taken by the whole lookup.
+    pub total: Duration,
+}
+
+impl Dht {
+    /// Iteratively sends `get_peers` queries to nodes closer and closer to
+    /// `info_hash`, starting with the closest nodes in the routing table.
+    ///
+    /// Converges once the `K` closest known nodes have all been queried. At
+    /// most [`DhtConfig::lookup_alpha`] queries are in flight at once.
+    ///
+    /// [`DhtConfig::lookup_alpha`]: crate::dht::DhtConfig::lookup_alpha
+    pub(super) async fn lookup(&self, info_hash: &NodeID) -> Result<LookupResult> {
+        self.lookup_with(info_hash, |_| ()).await
+    }
+
+    /// Like [`Dht::lookup`] but calls `on_peer` with each peer the first time
+    /// it is discovered, as soon as the response containing it arrives.
+    ///
+    /// The lookup sends at most [`DhtConfig::lookup_max_rounds`] rounds of
+    /// queries, so the total number of queries is bounded even if the lookup
+    /// never converges. The tokens handed out are stored for
+    /// [`Dht::stored_tokens`].
+    ///
+    /// [`DhtConfig::lookup_max_rounds`]: crate::dht::DhtConfig::lookup_max_rounds
+    pub(super) async fn lookup_with<F>(
+        &self,
+        info_hash: &NodeID,
+        mut on_peer: F,
+    ) -> Result<LookupResult>
+    where
+        F: FnMut(SocketAddrV4),
+    {
+        let alpha = self.config.lookup_alpha.max(1);
+        let started = Instant::now();
+        let mut time_to_first_peer = None;
+        let mut candidates = self
+            .routing_table
+            .lock_recovering()
+            .closest_nodes(info_hash, K);
+        let mut queried = HashSet::new();
+        let mut peers = Vec::new();
+        let mut tokens = Vec::new();
+        let mut id_changes = Vec::new();
+
+        // The first id each address was known by during the lookup.
+        let mut known_ids = candidates
+            .iter()
+            .map(|node| (node.address, node.node_id.clone()))
+            .collect::<HashMap<SocketAddr, NodeID>>();
+
+        for _ in 0..self.config.lookup_max_rounds {
+            candidates.sort_by_key(|node| distance(&node.node_id, info_hash));
+
+            let round = candidates
+                .iter()
+                .take(K)
+                .filter(|node| !queried.contains(&node.address))
+                .take(alpha)
+                .cloned()
+                .collect::<Vec<NodeInfo>>();
+
+            if round.is_empty() {
+                break;
+            }
+
+            for node in &round {
+                queried.insert(node.address);
+            }
+
+            let mut responses = round
+                .into_iter()
+                .map(|node| async move {
+                    let response = self.query_get_peers(node.address, info_hash.clone()).await;
+
+                    (node, response)
+                })
+                .collect::<FuturesUnordered<_>>();
+
+            while let Some((node, response)) = responses.next().await {
+                let response = match response {
+                    Ok(response) => response,
+                    Err(err) => {
+                        eprintln!("Error During Lookup {}", err);
+                        continue;
+                    }
+                };
+
+                let known_id = known_ids
+                    .entry(node.address)
+                    .or_insert_with(|| response.id.clone());
+                if *known_id != response.id {
+                    eprintln!(
+                        "Node at {} changed id from {} to {} during lookup",
+                        node.address, known_id, response.id
+                    );
+                    id_changes.push(node.address);
+
+                    if self.config.drop_nodes_changing_id {
+                        candidates.retain(|it| it.address != node.address);
+                        continue;
+                    }
+                }
+
+                if let Some(token) = response.token {
+                    tokens.push((NodeInfo::new(response.id, node.address), token));
+                }
+
+                match response.message_type {
+                    GetPeersResponseType::Peers(found) => {
+                        if !found.is_empty() && time_to_first_peer.is_none() {
+                            time_to_first_peer = Some(started.elapsed());
+                        }
+
+                        for peer in found {
+                            if !peers.contains(&peer) {
+                                on_peer(peer);
+                                peers.push(peer);
+                            }
+                        }
+                    }
+                    GetPeersResponseType::NextHop(nodes) => {
+                        for node in nodes {
+                            if !known_ids.contains_key(&node.address) {
+                                known_ids.insert(node.address, node.node_id.clone());
+                                candidates.push(node);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        tokens.sort_by_key(|(node, _)| distance(&node.node_id, info_hash));
+
+        self.lookup_tokens.lock()?.insert(
+            info_hash.clone(),
+            StoredTokens {
+                fetched_at: Instant::now(),
+                tokens: tokens.clone(),
+            },
+        );
+
+        Ok(LookupResult {
+            peers,
+            tokens,
+            id_changes,
+            metrics: GetPeersMetrics {
+                time_to_first_peer,
+                total: started.elapsed(),
+            },
+        })
+    }
+
+    /// Tokens handed out during the most recent lookup of `info_hash`,
+    /// closest to the info hash first. `None` if there was no lookup recently
+    /// enough for the tokens to still be accepted.
+    pub(super) fn stored_tokens(
+        &self,
+        info_hash: &NodeID,
+    ) -> Result<Option<Vec<(NodeInfo, Vec<u8>)>>> {
+        let mut lookup_tokens = self.lookup_tokens.lock()?;
+
+        match lookup_tokens.get(info_hash) {
+            Some(stored) if stored.fetched_at.elapsed() < TOKEN_REUSE_WINDOW => {
+                Ok(Some(stored.tokens.clone()))
+            }
+            Some(_) => {
+                lookup_tokens.remove(info_hash);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn query_get_peers(
+        &self,
+        address: SocketAddr,
+        info_hash: NodeID,
+    ) -> Result<GetPeersResponse> {
+        self.pause_gate.wait().await;
+
+        let response = timeout(
+            QUERY_TIMEOUT,
+            self.transports
+                .request_transport(&address)
+                .get_peers(address, info_hash.as_bytes()),
+        )
+        .await
+        .map_err(|_| ErrorKind::Timeout)??;
+
+        Ok(response)
+    }
+}
+
+fn distance(id: &NodeID, target: &NodeID) -> BigUint {
+    id.deref() ^ target.deref()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::{
+            testing::{
+                add_good_node,
+                FakeNode,
+            },
+            DhtConfig,
+        },
+        Dht,
+    };
+    use failure::Error;
+    use krpc_encoding::{
+        NodeID,
+        Query,
+        Response,
+    };
+    use std::{
+        sync::{
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::{
+        task::LocalSet,
+        time::timeout,
+    };
+
+    #[tokio::test]
+    async fn at_most_alpha_queries_in_flight() -> Result<(), Error> {
+        let config = DhtConfig {
+            lookup_alpha: 2,
+            ..DhtConfig::default()
+        };
+        let (dht, dht_future) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let queries = Arc::new(AtomicUsize::new(0));
+        for _ in 0..6 {
+            let node = FakeNode::bind(NodeID::random()).await?;
+            add_good_node(&dht, &node.info())?;
+
+            let queries = queries.clone();
+            node.serve(move |_| {
+                queries.fetch_add(1, Ordering::SeqCst);
+                None
+            });
+        }
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let lookup = dht.get_peers_with_metrics(NodeID::random());
+        let result = local
+            .run_until(timeout(Duration::from_millis(500), lookup))
+            .await;
+
+        // Nobody answers, so the first round is still waiting on its queries.
+        assert!(result.is_err());
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn node_changing_id_flagged() -> Result<(), Error> {
+        let config = DhtConfig {
+            drop_nodes_changing_id: true,
+            ..DhtConfig::default()
+        };
+        let (dht, dht_future) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let honest = FakeNode::bind(NodeID::random()).await?;
+        let honest_info = honest.info();
+        add_good_node(&dht, &honest_info)?;
+        let honest_id = honest.id.clone();
+        honest.serve(move |query| match query {
+            Query::GetPeers { .. } => Some(Response::NextHop {
+                id: honest_id.clone(),
+                token: Some(b"honest".to_vec()),
+                nodes: Vec::new(),
+                nodes6: Vec::new(),
+            }),
+            _ => None,
+        });
+
+        // answers with a new id every time it is queried
+        let churner = FakeNode::bind(NodeID::random()).await?;
+        let churner_address = churner.info().address;
+        add_good_node(&dht, &churner.info())?;
+        churner.serve(|query| match query {
+            Query::GetPeers { .. } => Some(Response::NextHop {
+                id: NodeID::random(),
+                token: Some(b"churner".to_vec()),
+                nodes: Vec::new(),
+                nodes6: Vec::new(),
+            }),
+            _ => None,
+        });
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let result = local.run_until(dht.lookup(&NodeID::random())).await?;
+
+        assert_eq!(result.id_changes, vec![churner_address]);
+        assert_eq!(result.tokens, vec![(honest_info, b"honest".to_vec())]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    dht::{
+        lookup,
+        poison::LockRecovering,
+        Dht,
+        QUERY_TIMEOUT,
+    },
+    errors::Result,
+};
+use futures::future::{
+    self,
+    Future,
+};
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use tokio::time::{
+    self,
+    timeout,
+    Instant,
+};
+
+/// How often the secret tokens are generated with is rotated. Tokens are
+/// accepted until the second rotation after they were handed out ([BEP-0005]).
+///
+/// [BEP-0005]: http://www.bittorrent.org/beps/bep_0005.html
+const TOKEN_ROTATION_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+impl Dht {
+    /// Every `interval`, sends a `find_node` query for our own id to the nodes
+    /// closest to it in the routing table.
+    ///
+    /// Nodes add the nodes which query them to their own routing tables, so
+    /// this keeps us in the routing tables of our neighbors and keeps inbound
+    /// queries coming. The returned future runs forever and must be polled
+    /// alongside the future returned by [`Dht::start`].
+    ///
+    /// Also rotates the token secret on the first tick at least five minutes
+    /// after the last rotation, so tokens handed out to `get_peers` queriers
+    /// expire.
+    pub fn enable_self_maintenance(&self, interval: Duration) -> impl Future<Output = ()> {
+        let dht = self.clone();
+
+        async move {
+            let mut ticks = time::interval(interval);
+            let mut last_rotation = Instant::now();
+
+            loop {
+                ticks.tick().await;
+
+                if last_rotation.elapsed() >= TOKEN_ROTATION_INTERVAL {
+                    dht.routing_table.lock_recovering().update_token();
+                    last_rotation = Instant::now();
+                }
+
+                dht.refresh_self()
+                    .await
+                    .unwrap_or_else(|e| eprintln!("Error During Self Maintenance {}", e));
+            }
+        }
+    }
+
+    async fn refresh_self(&self) -> Result<()> {
+        self.pause_gate.wait().await;
+
+        let nodes = self
+            .routing_table
+            .lock()?
+            .closest_nodes(&self.id, lookup::K);
+
+        let responses = future::join_all(nodes.iter().map(|node| {
+            timeout(
+                QUERY_TIMEOUT,
+                self.transports
+                    .request_transport(&node.address)
+                    .find_node(node.address, self.id.clone()),
+            )
+        }))
+        .await;
+
+        let mut routing_table = self.routing_table.lock_recovering();
+        for (node, response) in nodes.into_iter().zip(responses) {
+            let address = match node.address {
+                SocketAddr::V4(address) => address,
+                SocketAddr::V6(_) => continue,
+            };
+
+            if let Some(routing_node) = routing_table.get_or_add(node.node_id, address) {
+                match response {
+                    Ok(Ok(_)) => routing_node.mark_successful_request(),
+                    _ => routing_node.mark_failed_request(),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::{
+            poison::LockRecovering,
+            testing::{
+                add_good_node,
+                FakeNode,
+            },
+        },
+        Dht,
+    };
+    use failure::Error;
+    use futures::{
+        channel::mpsc,
+        StreamExt,
+    };
+    use krpc_encoding::{
+        NodeID,
+        Query,
+        Response,
+    };
+    use std::{
+        net::SocketAddrV4,
+        time::Duration,
+    };
+    use tokio::{
+        task::LocalSet,
+        time::{
+            pause,
+            sleep,
+            timeout,
+        },
+    };
+
+    #[tokio::test]
+    async fn self_maintenance_queries_own_id() -> Result<(), Error> {
+        let neighbor = FakeNode::bind(NodeID::random()).await?;
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &neighbor.info())?;
+
+        let (targets_tx, targets_rx) = mpsc::unbounded();
+        let neighbor_id = neighbor.id.clone();
+        neighbor.serve(move |query| match query {
+            Query::FindNode { target, .. } => {
+                let _ = targets_tx.unbounded_send(target);
+                Some(Response::NextHop {
+                    id: neighbor_id.clone(),
+                    token: None,
+                    nodes: Vec::new(),
+                    nodes6: Vec::new(),
+                })
+            }
+            _ => None,
+        });
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local.spawn_local(dht.enable_self_maintenance(Duration::from_millis(50)));
+
+        let targets = local
+            .run_until(timeout(
+                Duration::from_secs(2),
+                targets_rx.take(2).collect::<Vec<NodeID>>(),
+            ))
+            .await?;
+
+        assert_eq!(targets, vec![dht.node_id().clone(), dht.node_id().clone()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn self_maintenance_rotates_tokens() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let from: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+        let token = dht.routing_table.lock_recovering().generate_token(&from);
+        pause();
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local.spawn_local(dht.enable_self_maintenance(Duration::from_secs(60)));
+
+        // Still valid after the first rotation.
+        local
+            .run_until(sleep(Duration::from_secs(5 * 60 + 30)))
+            .await;
+        assert!(dht
+            .routing_table
+            .lock_recovering()
+            .verify_token(&token, &from));
+
+        local.run_until(sleep(Duration::from_secs(5 * 60))).await;
+        assert!(!dht
+            .routing_table
+            .lock_recovering()
+            .verify_token(&token, &from));
+
+        Ok(())
+    }
+}
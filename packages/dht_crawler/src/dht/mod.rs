@@ -1,49 +1,213 @@
 use crate::{
     errors::{
+        Error,
         ErrorKind,
         Result,
     },
     routing::{
         Node,
         RoutingTable,
+        TokenValidator,
     },
 };
-use futures::future;
+use futures::{
+    channel::mpsc,
+    future::{
+        self,
+        AbortHandle,
+        Abortable,
+    },
+    stream::{
+        self,
+        Stream,
+    },
+    FutureExt,
+    StreamExt,
+};
 use futures_util::TryStreamExt;
 use krpc_encoding::{
     NodeID,
     NodeInfo,
 };
 use std::{
-    collections::HashMap,
+    collections::{
+        HashMap,
+        HashSet,
+    },
     net::{
+        Ipv4Addr,
         SocketAddr,
         SocketAddrV4,
+        SocketAddrV6,
     },
     pin::Pin,
     sync::{
+        atomic::AtomicBool,
         Arc,
         Mutex,
     },
+    time::Duration,
+};
+use tokio::{
+    net::UdpSocket,
+    time::timeout,
 };
-use tokio::net::UdpSocket;
 use tokio_krpc::{
+    InboundQuery,
     KRPCNode,
     PortType,
     RequestTransport,
-    SendTransport,
 };
 
+mod announce_rate;
+mod config;
+mod crawl;
+mod density;
+mod discovered;
+mod event_log;
+mod external_addr;
 mod handler;
+mod health;
+mod lookup;
+mod maintenance;
+mod pause;
+mod poison;
+mod query_counts;
+mod query_log;
+mod recently_queried;
+mod requery;
+mod reservoir;
+mod response_policy;
+mod shutdown;
+mod state;
+#[cfg(test)]
+mod testing;
+mod transports;
+
+use self::{
+    announce_rate::{
+        AnnounceRates,
+        CONTRIBUTION_HALF_LIFE,
+    },
+    discovered::{
+        DiscoveredInfoHashes,
+        DISCOVERED_BUFFER,
+    },
+    event_log::EventLog,
+    external_addr::ExternalAddrTracker,
+    lookup::StoredTokens,
+    pause::PauseGate,
+    poison::LockRecovering,
+    query_counts::QueryCounters,
+    query_log::QueryLogSampler,
+    recently_queried::RecentlyQueried,
+    reservoir::Reservoir,
+    response_policy::ResponsePolicies,
+    transports::Transports,
+};
+
+pub use self::{
+    config::DhtConfig,
+    crawl::CrawlStrategy,
+    event_log::{
+        read_event_log,
+        LoggedMessage,
+    },
+    health::Responsiveness,
+    lookup::GetPeersMetrics,
+    query_counts::QueryCounts,
+    response_policy::{
+        QueryType,
+        ResponsePolicy,
+    },
+};
+
+/// Outcome of bootstrapping the routing table.
+#[derive(Debug, Default)]
+pub struct BootstrapReport {
+    /// Number of nodes which responded while bootstrapping and were added to
+    /// the routing table.
+    pub nodes_added: usize,
+
+    /// Addresses of nodes which couldn't be queried along with the error,
+    /// bootstrap nodes included. Use [`Error::kind`] to tell failures apart.
+    ///
+    /// [`Error::kind`]: crate::errors::Error::kind
+    pub failures: Vec<(SocketAddrV4, Error)>,
+}
+
+impl BootstrapReport {
+    fn failed(address: SocketAddrV4, err: Error) -> BootstrapReport {
+        BootstrapReport {
+            nodes_added: 0,
+            failures: vec![(address, err)],
+        }
+    }
+
+    fn merge(mut self, other: BootstrapReport) -> BootstrapReport {
+        self.nodes_added += other.nodes_added;
+        self.failures.extend(other.failures);
+        self
+    }
+}
+
+/// Queries received on any of the sockets along with the index of the socket
+/// which received them.
+type InboundQueries = Pin<Box<dyn Stream<Item = Result<(InboundQuery, SocketAddr, usize)>>>>;
+
+/// Time to wait for a response to a query sent to another node.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// BitTorrent DHT node
 #[derive(Clone)]
 pub struct Dht {
     id: NodeID,
+    config: Arc<DhtConfig>,
     torrents: Arc<Mutex<HashMap<NodeID, Vec<SocketAddrV4>>>>,
-    request_transport: Arc<RequestTransport>,
-    send_transport: Arc<SendTransport>,
+    canned_peers: Arc<Mutex<HashMap<NodeID, Vec<SocketAddrV4>>>>,
+    transports: Arc<Transports>,
     routing_table: Arc<Mutex<RoutingTable>>,
+    announce_rates: Arc<Mutex<AnnounceRates>>,
+
+    /// Decaying count of peers each source IP has added to `torrents`, for
+    /// [`DhtConfig::max_peers_per_source`].
+    peer_contributions: Arc<Mutex<AnnounceRates>>,
+    query_counters: Arc<QueryCounters>,
+    query_log: Arc<QueryLogSampler>,
+    response_policies: Arc<Mutex<ResponsePolicies>>,
+
+    /// Tokens from the most recent lookup of each info hash.
+    lookup_tokens: Arc<Mutex<HashMap<NodeID, StoredTokens>>>,
+    external_addr: Arc<ExternalAddrTracker>,
+
+    /// Sample of info hashes from queries received.
+    reservoir: Arc<Mutex<Reservoir>>,
+
+    /// Info hashes from queries received, for [`Dht::discovered_infohashes`].
+    discovered: Arc<DiscoveredInfoHashes>,
+
+    /// Questionable nodes to ping, queued while answering queries.
+    requery_queue: mpsc::UnboundedSender<NodeInfo>,
+
+    /// Nodes queued or being pinged by the requery worker.
+    requerying: Arc<Mutex<HashSet<NodeID>>>,
+
+    /// Nodes followed while crawling, so they aren't queried again until
+    /// [`DhtConfig::crawl_requery_window`] passes.
+    recently_queried: Arc<Mutex<RecentlyQueried>>,
+
+    /// Holds back outbound discovery queries while paused.
+    pause_gate: Arc<PauseGate>,
+
+    /// Set once shutdown starts. Queries from other nodes are ignored from
+    /// then on.
+    shutting_down: Arc<AtomicBool>,
+
+    /// Stops the future handling inbound messages.
+    serve_handle: AbortHandle,
+
+    /// Log of every message sent and received, when enabled.
+    event_log: Arc<EventLog>,
 }
 
 impl Dht {
@@ -52,114 +216,616 @@ impl Dht {
     /// Start handling inbound messages from other peers in the network.
     /// Continues to handle while the future is polled.
     pub async fn start(bind_addr: SocketAddr) -> Result<(Dht, impl future::Future<Output = ()>)> {
-        let socket = UdpSocket::bind(&bind_addr)
-            .await
-            .map_err(|cause| ErrorKind::BindError { cause })?;
-        let transport = KRPCNode::new(socket);
-        let (send_transport, request_stream) = transport.serve();
+        Self::start_with_config(bind_addr, DhtConfig::default()).await
+    }
+
+    /// Like [`Dht::start`] but with non-default behavior described by
+    /// `config`.
+    pub async fn start_with_config(
+        bind_addr: SocketAddr,
+        config: DhtConfig,
+    ) -> Result<(Dht, impl future::Future<Output = ()>)> {
+        Self::start_multi_with_config(vec![bind_addr], config).await
+    }
+
+    /// Like [`Dht::start`] but listening on every address in `bind_addrs`.
+    /// Useful for nodes with multiple public IPs.
+    ///
+    /// Queries received on any socket share a single routing table and are
+    /// responded to from the socket which received them. Queries to another
+    /// node are always sent from the same socket, picked by the node's IP.
+    pub async fn start_multi(
+        bind_addrs: Vec<SocketAddr>,
+    ) -> Result<(Dht, impl future::Future<Output = ()>)> {
+        Self::start_multi_with_config(bind_addrs, DhtConfig::default()).await
+    }
+
+    /// Like [`Dht::start_multi`] but with non-default behavior described by
+    /// `config`.
+    pub async fn start_multi_with_config(
+        bind_addrs: Vec<SocketAddr>,
+        config: DhtConfig,
+    ) -> Result<(Dht, impl future::Future<Output = ()>)> {
+        if bind_addrs.is_empty() {
+            return Err(ErrorKind::NoBindAddresses)?;
+        }
 
         let id = NodeID::random();
+        let external_addr = Arc::new(ExternalAddrTracker::new());
+        let event_log = Arc::new(EventLog::new());
+        let mut transports = Transports::new();
+        let mut request_streams = Vec::new();
+
+        for (index, bind_addr) in bind_addrs.into_iter().enumerate() {
+            let socket = UdpSocket::bind(&bind_addr)
+                .await
+                .map_err(|cause| ErrorKind::BindError { cause })?;
+            let local_addr = socket
+                .local_addr()
+                .map_err(|cause| ErrorKind::BindError { cause })?;
+            let tracker = external_addr.clone();
+            let log = event_log.clone();
+            let transport = KRPCNode::builder()
+                .on_external_addr_report(move |from, addr| {
+                    tracker
+                        .report(from.ip(), addr)
+                        .unwrap_or_else(|e| eprintln!("Error Recording External Address {}", e))
+                })
+                .on_message(move |direction, address, message| {
+                    log.record(direction, address, message)
+                })
+                .build(socket);
+            let (send_transport, request_stream, _) = transport.serve();
+            let send_transport_arc = Arc::new(send_transport);
+
+            transports.add(
+                local_addr,
+                send_transport_arc.clone(),
+                RequestTransport::new(id.clone(), send_transport_arc),
+            );
+
+            let request_stream: InboundQueries = Box::pin(
+                request_stream
+                    .err_into()
+                    .map_ok(move |(query, from): (InboundQuery, SocketAddr)| (query, from, index)),
+            );
+            request_streams.push(request_stream);
+        }
+
         let torrents = HashMap::new();
-        let routing_table = RoutingTable::new(id.clone());
-        let send_transport_arc = Arc::new(send_transport);
+        let mut routing_table = RoutingTable::new(id.clone());
+        if let Some(lifetime) = config.token_lifetime {
+            routing_table.set_token_validator(TokenValidator::with_lifetime(lifetime));
+        }
+        let (serve_handle, serve_registration) = AbortHandle::new_pair();
+        let (requery_queue, requery_receiver) = mpsc::unbounded();
+        let recently_queried = RecentlyQueried::new(config.crawl_requery_window);
 
         let dht = Dht {
-            id: id.clone(),
+            id,
+            config: Arc::new(config),
             torrents: Arc::new(Mutex::new(torrents)),
-            request_transport: Arc::new(RequestTransport::new(id, send_transport_arc.clone())),
-            send_transport: send_transport_arc,
+            canned_peers: Arc::new(Mutex::new(HashMap::new())),
+            transports: Arc::new(transports),
             routing_table: Arc::new(Mutex::new(routing_table)),
+            announce_rates: Arc::new(Mutex::new(AnnounceRates::new())),
+            peer_contributions: Arc::new(Mutex::new(AnnounceRates::with_half_life(
+                CONTRIBUTION_HALF_LIFE,
+            ))),
+            query_counters: Arc::new(QueryCounters::new()),
+            query_log: Arc::new(QueryLogSampler::new()),
+            response_policies: Arc::new(Mutex::new(ResponsePolicies::new())),
+            lookup_tokens: Arc::new(Mutex::new(HashMap::new())),
+            external_addr,
+            reservoir: Arc::new(Mutex::new(Reservoir::new(0))),
+            discovered: Arc::new(DiscoveredInfoHashes::new(DISCOVERED_BUFFER)),
+            requery_queue,
+            requerying: Arc::new(Mutex::new(HashSet::new())),
+            recently_queried: Arc::new(Mutex::new(recently_queried)),
+            pause_gate: Arc::new(PauseGate::new()),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            serve_handle,
+            event_log,
         };
 
-        Ok((dht.clone(), dht.handle_requests(request_stream.err_into())))
+        let serve = Abortable::new(
+            future::join(
+                dht.clone()
+                    .handle_requests(stream::select_all(request_streams)),
+                dht.clone().requery_questionable(requery_receiver),
+            ),
+            serve_registration,
+        );
+
+        Ok((dht, serve.map(|_| ())))
+    }
+
+    /// Like [`Dht::start`] but listening on both an IPv4 and an IPv6 address.
+    ///
+    /// Queries are answered from the socket which received them and queries
+    /// to other nodes always go out the IPv4 socket. The node id and the
+    /// routing table are shared across both sockets. The routing table, the
+    /// tokens handed out and the torrents cache only hold IPv4 addresses, so
+    /// only pings are answered over IPv6 and IPv6 nodes aren't remembered.
+    pub async fn start_dual(
+        v4_addr: SocketAddrV4,
+        v6_addr: SocketAddrV6,
+    ) -> Result<(Dht, impl future::Future<Output = ()>)> {
+        Self::start_multi(vec![v4_addr.into(), v6_addr.into()]).await
+    }
+
+    /// Identifier of this node in the network.
+    pub fn node_id(&self) -> &NodeID {
+        &self.id
+    }
+
+    /// Address the socket is bound to. When listening on multiple sockets,
+    /// the address of the first one.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.transports.local_addrs()[0]
+    }
+
+    /// The address other nodes see us at, once enough of the nodes which
+    /// responded to our queries agree on it.
+    pub fn external_addr(&self) -> Result<Option<SocketAddrV4>> {
+        self.external_addr.current()
+    }
+
+    /// Calls `callback` with the previous and new address whenever the
+    /// address reported by [`Dht::external_addr`] changes. The previous
+    /// address is `None` the first time an address is agreed on.
+    pub fn on_external_addr_change<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(Option<SocketAddrV4>, SocketAddrV4) + Send + Sync + 'static,
+    {
+        self.external_addr.on_change(callback)
+    }
+
+    /// Logs one in every `rate` queries received from other nodes with their
+    /// source, transaction id and contents. A rate of zero, the default, turns
+    /// logging off.
+    pub fn set_query_log_sampling(&self, rate: u64) {
+        self.query_log.set_rate(rate);
+    }
+
+    /// Stops sending queries to discover nodes and peers, such as those sent
+    /// while bootstrapping, during lookups and during self maintenance.
+    /// Queries which would have been sent wait until [`Dht::resume`] is
+    /// called. Queries from other nodes are still answered and the routing
+    /// table is still updated from them.
+    pub fn pause(&self) {
+        self.pause_gate.pause();
+    }
+
+    /// Lets queries held back by [`Dht::pause`] go out.
+    pub fn resume(&self) {
+        self.pause_gate.resume();
+    }
+
+    /// Whether the node is paused by [`Dht::pause`].
+    pub fn is_paused(&self) -> bool {
+        self.pause_gate.is_paused()
+    }
+
+    /// Sets whether queries of `query_type` from other nodes are answered.
+    /// Dropped queries are still handled, so announces are still stored, but
+    /// no response is sent. All query types are answered by default.
+    ///
+    /// Useful for crawling without serving `find_node` and `get_peers`
+    /// responses which could be used for amplification, while still answering
+    /// pings to stay in other nodes' routing tables.
+    pub fn set_response_policy(&self, query_type: QueryType, policy: ResponsePolicy) -> Result<()> {
+        self.response_policies.lock()?.set(query_type, policy);
+
+        Ok(())
+    }
+
+    /// Starts keeping a uniform random sample of up to `size` info hashes
+    /// from `get_peers` and `announce_peer` queries received, no matter how
+    /// many are received. Replaces any sample kept so far. A size of zero,
+    /// the default, keeps no sample.
+    pub fn infohash_reservoir(&self, size: usize) -> Result<()> {
+        *self.reservoir.lock()? = Reservoir::new(size);
+
+        Ok(())
+    }
+
+    /// The sample of info hashes kept since [`Dht::infohash_reservoir`] was
+    /// last called.
+    pub fn reservoir_sample(&self) -> Result<Vec<NodeID>> {
+        Ok(self.reservoir.lock()?.sample().to_vec())
+    }
+
+    /// Yields each info hash from `get_peers` and `announce_peer` queries
+    /// received from now on, along with the address of the querying node.
+    ///
+    /// The handler never waits on the stream. A stream which falls too far
+    /// behind misses the oldest info hashes it hadn't read yet.
+    pub fn discovered_infohashes(&self) -> impl Stream<Item = (NodeID, SocketAddrV4)> {
+        self.discovered.subscribe()
+    }
+
+    /// Number of queries of each type received from other nodes.
+    pub fn query_counts(&self) -> QueryCounts {
+        self.query_counters.snapshot()
     }
 
     /// Bootstraps the routing table by finding nodes near our node id and
     /// adding them to the routing table.
-    pub async fn bootstrap_routing_table(&self, addrs: Vec<SocketAddrV4>) -> Result<()> {
-        let send_transport = self.request_transport.clone();
+    ///
+    /// Failing to query a node doesn't stop bootstrapping. The returned
+    /// report tells how many nodes were added and which queries failed, so a
+    /// bootstrap which added no nodes at all can be told apart.
+    pub async fn bootstrap_routing_table(
+        &self,
+        addrs: Vec<SocketAddrV4>,
+    ) -> Result<BootstrapReport> {
+        self.bootstrap(addrs, None).await
+    }
+
+    /// Like [`Dht::bootstrap_routing_table`] but stops discovering nodes once
+    /// the routing table has at least `target_node_count` good nodes. Queries
+    /// still in flight at that point are dropped without waiting for them.
+    ///
+    /// # Returns
+    /// The number of good nodes in the routing table once bootstrapping
+    /// stopped. Less than `target_node_count` if discovery ran out of nodes
+    /// first.
+    pub async fn bootstrap_to_target(
+        &self,
+        addrs: Vec<SocketAddrV4>,
+        target_node_count: usize,
+    ) -> Result<usize> {
+        self.bootstrap(addrs, Some(target_node_count)).await?;
+
+        Ok(self.routing_table.lock_recovering().good_len())
+    }
+
+    async fn bootstrap(
+        &self,
+        addrs: Vec<SocketAddrV4>,
+        target: Option<usize>,
+    ) -> Result<BootstrapReport> {
+        let transports = self.transports.clone();
         let routing_table_arc = self.routing_table.clone();
         let id = self.id.clone();
+        let config = self.config.clone();
+        let pause_gate = self.pause_gate.clone();
+        let (stop, stop_registration) = AbortHandle::new_pair();
+        let target = target.map(|good_nodes| BootstrapTarget { good_nodes, stop });
 
-        future::join_all(addrs.into_iter().map(move |addr| {
+        let discovery = future::join_all(addrs.into_iter().map(move |addr| {
             Self::discover_nodes_of(
                 addr,
+                0,
                 id.clone(),
-                send_transport.clone(),
+                config.clone(),
+                target.clone(),
+                transports.clone(),
                 routing_table_arc.clone(),
+                pause_gate.clone(),
             )
-        }))
-        .await;
+            .map(move |result| result.unwrap_or_else(|err| BootstrapReport::failed(addr, err)))
+        }));
 
-        Ok(())
+        // Aborted once the target is reached, dropping queries still in
+        // flight along with the reports of the nodes they came from.
+        let reports = Abortable::new(discovery, stop_registration)
+            .await
+            .unwrap_or_default();
+
+        Ok(reports
+            .into_iter()
+            .fold(BootstrapReport::default(), BootstrapReport::merge))
     }
 
+    /// Queries the node at `addr`, `depth` hops away from a bootstrap node,
+    /// and follows the nodes it returns until
+    /// [`DhtConfig::bootstrap_max_depth`] is reached.
     async fn discover_nodes_of(
         addr: SocketAddrV4,
+        depth: usize,
         self_id: NodeID,
-        request_transport: Arc<RequestTransport>,
+        config: Arc<DhtConfig>,
+        target: Option<BootstrapTarget>,
+        transports: Arc<Transports>,
         routing_table_arc: Arc<Mutex<RoutingTable>>,
-    ) -> Result<()> {
+        pause_gate: Arc<PauseGate>,
+    ) -> Result<BootstrapReport> {
         // todo: weird recursive thing
         // todo: populate routing table
 
-        let response = request_transport
-            .find_node(addr.clone().into(), self_id.clone())
-            // todo: standardize timeout
-            .await?;
+        let mut report = BootstrapReport::default();
+
+        if target_reached(&routing_table_arc, &target)? {
+            return Ok(report);
+        }
+
+        pause_gate.wait().await;
+
+        let response = timeout(
+            QUERY_TIMEOUT,
+            transports
+                .request_transport(&addr.into())
+                .find_node(addr, self_id.clone()),
+        )
+        .await
+        .map_err(|_| ErrorKind::Timeout)??;
+
+        if target_reached(&routing_table_arc, &target)? {
+            return Ok(report);
+        }
 
-        let mut node = Node::new(response.id, addr.into());
+        let mut node = Node::new(response.id.clone(), addr.into());
         node.mark_successful_request();
 
         {
-            let mut routing_table = routing_table_arc.lock()?;
+            let mut routing_table = routing_table_arc.lock_recovering();
+            let len_before = routing_table.len();
             routing_table.add_node(node);
+            if routing_table.len() > len_before {
+                report.nodes_added += 1;
+            }
+        }
+
+        if target_reached(&routing_table_arc, &target)? || depth >= config.bootstrap_max_depth {
+            return Ok(report);
         }
 
+        // Some nodes include themselves in their responses. Following those
+        // entries would query the same node over and over.
+        let responder_id = response.id;
+        let nodes = response
+            .nodes
+            .into_iter()
+            .filter(|node| node.node_id != responder_id && node.address != SocketAddr::V4(addr))
+            .collect();
+        let nodes = limit_response_nodes(nodes, &config);
+
         let f: Pin<Box<dyn future::Future<Output = _>>> =
-            Box::pin(future::join_all(response.nodes.into_iter().map(|node| {
+            Box::pin(future::join_all(nodes.into_iter().map(|node| {
                 Self::discover_neighbors_of(
                     node,
+                    depth + 1,
                     self_id.clone(),
-                    request_transport.clone(),
+                    config.clone(),
+                    target.clone(),
+                    transports.clone(),
                     routing_table_arc.clone(),
+                    pause_gate.clone(),
                 )
             })));
 
-        f.await;
+        let reports = f.await;
 
-        Ok(())
+        Ok(reports.into_iter().fold(report, BootstrapReport::merge))
     }
 
+    /// Like [`Dht::discover_nodes_of`] but recording a failed query in the
+    /// report instead of returning it. IPv6 nodes are skipped.
     async fn discover_neighbors_of(
         node: NodeInfo,
+        depth: usize,
         self_id: NodeID,
-        request_transport: Arc<RequestTransport>,
+        config: Arc<DhtConfig>,
+        target: Option<BootstrapTarget>,
+        transports: Arc<Transports>,
         routing_table_arc: Arc<Mutex<RoutingTable>>,
-    ) {
-        Self::discover_nodes_of(node.address, self_id, request_transport, routing_table_arc)
-            .await
-            .unwrap_or_else(|e| eprintln!("Error While Bootstrapping {}", e));
+        pause_gate: Arc<PauseGate>,
+    ) -> BootstrapReport {
+        let address = match node.address {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => return BootstrapReport::default(),
+        };
+
+        Self::discover_nodes_of(
+            address,
+            depth,
+            self_id,
+            config,
+            target,
+            transports,
+            routing_table_arc,
+            pause_gate,
+        )
+        .await
+        .unwrap_or_else(|err| BootstrapReport::failed(address, err))
+    }
+
+    /// Gets a list of peers seeding `info_hash` with an iterative lookup,
+    /// starting from the closest nodes in the routing table.
+    ///
+    /// The tokens handed out during the lookup are kept, so a following
+    /// [`Dht::announce`] doesn't need to repeat the lookup.
+    pub async fn get_peers(&self, info_hash: NodeID) -> Result<Vec<SocketAddrV4>> {
+        let result = self.lookup(&info_hash).await?;
+
+        Ok(result.peers)
     }
 
-    /// Gets a list of peers seeding `info_hash`.
-    pub async fn get_peers(&self, _info_hash: NodeID) -> Result<Vec<SocketAddrV4>> {
-        // TODO:
-        // * Return From torrents Table if Exists
-        // * Fetch By Calling get_nodes otherwise
-        unimplemented!()
+    /// Like [`Dht::get_peers`] but also reports how long the lookup took to
+    /// find its first peer and to finish.
+    pub async fn get_peers_with_metrics(
+        &self,
+        info_hash: NodeID,
+    ) -> Result<(Vec<SocketAddrV4>, GetPeersMetrics)> {
+        let result = self.lookup(&info_hash).await?;
+
+        Ok((result.peers, result.metrics))
+    }
+
+    /// Yields the peers seeding `info_hash` as they are discovered during the
+    /// lookup, each peer once, instead of waiting for the lookup to finish.
+    /// The stream ends once the lookup converges.
+    pub fn get_peers_stream(&self, info_hash: NodeID) -> impl Stream<Item = SocketAddrV4> + '_ {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let lookup = async move {
+            let result = self
+                .lookup_with(&info_hash, |peer| {
+                    let _ = sender.unbounded_send(peer);
+                })
+                .await;
+
+            if let Err(err) = result {
+                eprintln!("Error During Lookup {}", err);
+            }
+
+            None
+        };
+
+        stream::select(receiver.map(Some), lookup.into_stream()).filter_map(future::ready)
+    }
+
+    /// Answers `get_peers` queries for `info_hash` with `peers` instead of the
+    /// peers actually announced. Meant for experiments observing how other
+    /// nodes and clients react to a controlled swarm. Other info hashes are
+    /// unaffected.
+    pub fn set_canned_peers(&self, info_hash: NodeID, peers: Vec<SocketAddrV4>) -> Result<()> {
+        self.canned_peers.lock()?.insert(info_hash, peers);
+
+        Ok(())
+    }
+
+    /// Lists each cached info hash along with the number of peers known for it,
+    /// most peers first.
+    pub fn info_hashes_with_counts(&self) -> Result<Vec<(NodeID, usize)>> {
+        let mut counts = self
+            .torrents
+            .lock()?
+            .iter()
+            .map(|(info_hash, peers)| (info_hash.clone(), peers.len()))
+            .collect::<Vec<(NodeID, usize)>>();
+
+        counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        Ok(counts)
+    }
+
+    /// Finds the nodes an announce for `info_hash` would be sent to along with
+    /// the tokens they handed out, without sending any announces.
+    pub async fn announce_dry_run(&self, info_hash: NodeID) -> Result<Vec<(NodeInfo, Vec<u8>)>> {
+        let result = self.lookup(&info_hash).await?;
+
+        Ok(result.tokens.into_iter().take(lookup::K).collect())
     }
 
     /// Announces that we have information about an info_hash on `port`.
-    pub async fn announce(&self, _info_hash: NodeID, _port: PortType) -> Result<()> {
-        // TODO:
-        // * Send Announce to all Peers With Tokens
-        unimplemented!()
+    pub async fn announce(&self, info_hash: NodeID, port: PortType) -> Result<()> {
+        self.announce_with_replication(info_hash, port, lookup::K)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Announces `info_hash` on `port` to the `replication` nodes closest to
+    /// `info_hash` which handed out a token. Fewer nodes are announced to when
+    /// fewer nodes with tokens are found.
+    ///
+    /// Reuses the tokens from a recent lookup of `info_hash`, such as one by
+    /// [`Dht::get_peers`], instead of looking up the info hash again.
+    ///
+    /// # Returns
+    /// The number of nodes which acknowledged the announce.
+    pub async fn announce_with_replication(
+        &self,
+        info_hash: NodeID,
+        port: PortType,
+        replication: usize,
+    ) -> Result<usize> {
+        let tokens = match self.stored_tokens(&info_hash)? {
+            Some(tokens) => tokens,
+            None => self.lookup(&info_hash).await?.tokens,
+        };
+
+        let responses =
+            future::join_all(tokens.into_iter().take(replication).map(|(node, token)| {
+                timeout(
+                    QUERY_TIMEOUT,
+                    self.transports
+                        .request_transport(&node.address)
+                        .announce_peer(token, node.address, info_hash.as_bytes(), port),
+                )
+            }))
+            .await;
+
+        Ok(responses
+            .into_iter()
+            .filter(|response| matches!(response, Ok(Ok(_))))
+            .count())
     }
 }
 
+/// Limits the nodes followed from a single response according to
+/// [`DhtConfig::max_nodes_per_response`] and
+/// [`DhtConfig::max_nodes_per_subnet`]. Earlier nodes in the response are
+/// preferred. IPv6 nodes are dropped as the routing table only holds IPv4
+/// nodes.
+fn limit_response_nodes(nodes: Vec<NodeInfo>, config: &DhtConfig) -> Vec<NodeInfo> {
+    let mut nodes_per_subnet: HashMap<[u8; 3], usize> = HashMap::new();
+
+    nodes
+        .into_iter()
+        .filter(|node| {
+            let subnet = match node.address {
+                SocketAddr::V4(address) => subnet_of(address.ip()),
+                SocketAddr::V6(_) => return false,
+            };
+
+            let count = nodes_per_subnet.entry(subnet).or_insert(0);
+            *count += 1;
+
+            *count <= config.max_nodes_per_subnet
+        })
+        .take(config.max_nodes_per_response)
+        .collect()
+}
+
+/// Number of good nodes bootstrapping stops at, along with the handle which
+/// stops it.
+#[derive(Clone)]
+struct BootstrapTarget {
+    good_nodes: usize,
+    stop: AbortHandle,
+}
+
+/// Whether the routing table has at least as many good nodes as `target`,
+/// stopping the bootstrap if so. Never true without a target.
+fn target_reached(
+    routing_table: &Mutex<RoutingTable>,
+    target: &Option<BootstrapTarget>,
+) -> Result<bool> {
+    let target = match target {
+        Some(target) => target,
+        None => return Ok(false),
+    };
+
+    if routing_table.lock_recovering().good_len() < target.good_nodes {
+        return Ok(false);
+    }
+
+    target.stop.abort();
+
+    Ok(true)
+}
+
+/// The /24 subnet `ip` is a part of.
+fn subnet_of(ip: &Ipv4Addr) -> [u8; 3] {
+    let octets = ip.octets();
+
+    [octets[0], octets[1], octets[2]]
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{
+        limit_response_nodes,
+        testing::{
+            add_good_node,
+            FakeNode,
+        },
+        DhtConfig,
+    };
     use crate::{
         addr::{
             AsV4Address,
@@ -169,9 +835,42 @@ mod tests {
         Dht,
     };
     use failure::Error;
+    use futures::{
+        channel::mpsc,
+        StreamExt,
+    };
+    use krpc_encoding::{
+        Envelope,
+        Message,
+        NodeID,
+        NodeInfo,
+        PortType,
+        Query,
+        Response,
+    };
+    use std::{
+        net::{
+            IpAddr,
+            SocketAddrV4,
+        },
+        sync::{
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+            Arc,
+            Mutex,
+        },
+        time::Duration,
+    };
     use tokio::{
+        net::UdpSocket,
         spawn,
-        task::spawn_local,
+        task::{
+            spawn_local,
+            LocalSet,
+        },
+        time::timeout,
     };
 
     #[tokio::test]
@@ -194,4 +893,806 @@ mod tests {
 
         Ok(())
     }
+
+    fn make_nodes(addrs: &[&str]) -> Result<Vec<NodeInfo>, Error> {
+        let mut nodes = Vec::new();
+        for addr in addrs {
+            nodes.push(NodeInfo::new(NodeID::random(), addr.parse()?));
+        }
+
+        Ok(nodes)
+    }
+
+    #[test]
+    fn limit_nodes_per_response() -> Result<(), Error> {
+        let nodes = make_nodes(&[
+            "1.0.0.1:6881",
+            "2.0.0.1:6881",
+            "3.0.0.1:6881",
+            "4.0.0.1:6881",
+        ])?;
+
+        let config = DhtConfig {
+            max_nodes_per_response: 2,
+            ..DhtConfig::default()
+        };
+
+        let limited = limit_response_nodes(nodes.clone(), &config);
+
+        assert_eq!(limited, nodes[..2].to_vec());
+
+        Ok(())
+    }
+
+    #[test]
+    fn limit_nodes_per_subnet() -> Result<(), Error> {
+        let nodes = make_nodes(&[
+            "1.0.0.1:6881",
+            "1.0.0.2:6881",
+            "1.0.0.3:6881",
+            "2.0.0.1:6881",
+            "1.0.1.1:6881",
+        ])?;
+
+        let config = DhtConfig {
+            max_nodes_per_subnet: 2,
+            ..DhtConfig::default()
+        };
+
+        let limited = limit_response_nodes(nodes.clone(), &config);
+
+        assert_eq!(
+            limited,
+            vec![
+                nodes[0].clone(),
+                nodes[1].clone(),
+                nodes[3].clone(),
+                nodes[4].clone()
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn start_multi_responds_from_receiving_socket() -> Result<(), Error> {
+        let (dht, dht_future) =
+            Dht::start_multi(vec!["127.0.0.1:0".into_addr(), "127.0.0.2:0".into_addr()]).await?;
+        let local_addrs = dht.transports.local_addrs().to_vec();
+        assert_eq!(local_addrs.len(), 2);
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local
+            .run_until(async {
+                let client = UdpSocket::bind("127.0.0.1:0").await?;
+
+                for local_addr in &local_addrs {
+                    let ping = Envelope {
+                        ip: None,
+                        transaction_id: b"aa".to_vec(),
+                        version: None,
+                        message_type: Message::Query {
+                            query: Query::Ping {
+                                id: NodeID::random(),
+                            },
+                        },
+                        read_only: false,
+                    };
+                    client.send_to(&ping.encode()?, local_addr).await?;
+
+                    let mut buf = [0u8; 1024];
+                    let (len, from) =
+                        timeout(Duration::from_secs(1), client.recv_from(&mut buf)).await??;
+
+                    assert_eq!(&from, local_addr);
+                    assert_eq!(
+                        Envelope::decode(&buf[..len])?.message_type,
+                        Message::Response {
+                            response: Response::OnlyID { id: dht.id.clone() },
+                        }
+                    );
+                }
+
+                Ok::<(), Error>(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn start_dual_responds_over_both_families() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start_dual("127.0.0.1:0".parse()?, "[::1]:0".parse()?).await?;
+        let local_addrs = dht.transports.local_addrs().to_vec();
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local
+            .run_until(async {
+                for (client_addr, local_addr) in
+                    vec!["127.0.0.1:0", "[::1]:0"].into_iter().zip(&local_addrs)
+                {
+                    let client = UdpSocket::bind(client_addr).await?;
+                    let ping = Envelope {
+                        ip: None,
+                        transaction_id: b"aa".to_vec(),
+                        version: None,
+                        message_type: Message::Query {
+                            query: Query::Ping {
+                                id: NodeID::random(),
+                            },
+                        },
+                        read_only: false,
+                    };
+                    client.send_to(&ping.encode()?, local_addr).await?;
+
+                    let mut buf = [0u8; 1024];
+                    let (len, from) =
+                        timeout(Duration::from_secs(1), client.recv_from(&mut buf)).await??;
+
+                    assert_eq!(&from, local_addr);
+                    assert_eq!(
+                        Envelope::decode(&buf[..len])?.message_type,
+                        Message::Response {
+                            response: Response::OnlyID { id: dht.id.clone() },
+                        }
+                    );
+                }
+
+                Ok::<(), Error>(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_to_target() -> Result<(), Error> {
+        let mut fakes = Vec::new();
+        for _ in 0..6 {
+            fakes.push(FakeNode::bind(NodeID::random()).await?);
+        }
+
+        // each node only knows about the next one
+        let infos = fakes.iter().map(FakeNode::info).collect::<Vec<NodeInfo>>();
+        let seed = fakes[0].address;
+        for (idx, fake) in fakes.into_iter().enumerate() {
+            let id = fake.id.clone();
+            let nodes = infos
+                .get(idx + 1)
+                .cloned()
+                .into_iter()
+                .collect::<Vec<NodeInfo>>();
+
+            fake.serve(move |_| {
+                Some(Response::NextHop {
+                    id: id.clone(),
+                    token: None,
+                    nodes: nodes.clone(),
+                    nodes6: Vec::new(),
+                })
+            });
+        }
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let count = local
+            .run_until(dht.bootstrap_to_target(vec![seed], 3))
+            .await?;
+
+        assert_eq!(count, 3);
+        assert_eq!(dht.routing_table.lock().map_err(DhtError::from)?.len(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_to_target_drops_slow_queries() -> Result<(), Error> {
+        let seed = FakeNode::bind(NodeID::random()).await?;
+        let fast = FakeNode::bind(NodeID::random()).await?;
+        let slow = FakeNode::bind(NodeID::random()).await?;
+
+        let seed_address = seed.address;
+        let (seed_id, fast_id) = (seed.id.clone(), fast.id.clone());
+        let neighbors = vec![fast.info(), slow.info()];
+        seed.serve(move |_| {
+            Some(Response::NextHop {
+                id: seed_id.clone(),
+                token: None,
+                nodes: neighbors.clone(),
+                nodes6: Vec::new(),
+            })
+        });
+        fast.serve(move |_| {
+            Some(Response::NextHop {
+                id: fast_id.clone(),
+                token: None,
+                nodes: Vec::new(),
+                nodes6: Vec::new(),
+            })
+        });
+        // Never answers, so waiting for it takes the whole query timeout.
+        slow.serve(|_| None);
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let count = local
+            .run_until(timeout(
+                Duration::from_secs(1),
+                dht.bootstrap_to_target(vec![seed_address], 2),
+            ))
+            .await??;
+
+        assert_eq!(count, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_stops_at_max_depth() -> Result<(), Error> {
+        let mut fakes = Vec::new();
+        for _ in 0..6 {
+            fakes.push(FakeNode::bind(NodeID::random()).await?);
+        }
+
+        // each node only knows about the next one
+        let infos = fakes.iter().map(FakeNode::info).collect::<Vec<NodeInfo>>();
+        let seed = fakes[0].address;
+        let queried = Arc::new(Mutex::new(Vec::new()));
+        for (idx, fake) in fakes.into_iter().enumerate() {
+            let id = fake.id.clone();
+            let nodes = infos
+                .get(idx + 1)
+                .cloned()
+                .into_iter()
+                .collect::<Vec<NodeInfo>>();
+            let queried = queried.clone();
+
+            fake.serve(move |_| {
+                queried.lock().unwrap().push(idx);
+
+                Some(Response::NextHop {
+                    id: id.clone(),
+                    token: None,
+                    nodes: nodes.clone(),
+                    nodes6: Vec::new(),
+                })
+            });
+        }
+
+        let config = DhtConfig {
+            bootstrap_max_depth: 2,
+            ..DhtConfig::default()
+        };
+        let (dht, dht_future) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local
+            .run_until(dht.bootstrap_routing_table(vec![seed]))
+            .await?;
+
+        // the seed and the nodes one and two hops away
+        assert_eq!(*queried.lock().unwrap(), vec![0, 1, 2]);
+        assert_eq!(dht.routing_table.lock().map_err(DhtError::from)?.len(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_skips_responder_in_own_response() -> Result<(), Error> {
+        let seed = FakeNode::bind(NodeID::random()).await?;
+        let other = FakeNode::bind(NodeID::random()).await?;
+        let seed_address = seed.address;
+        let seed_id = seed.id.clone();
+        let nodes = vec![seed.info(), other.info()];
+
+        let queried = Arc::new(Mutex::new(Vec::new()));
+        let seed_queried = queried.clone();
+        seed.serve(move |_| {
+            seed_queried.lock().unwrap().push("seed");
+
+            Some(Response::NextHop {
+                id: seed_id.clone(),
+                token: None,
+                nodes: nodes.clone(),
+                nodes6: Vec::new(),
+            })
+        });
+
+        let other_id = other.id.clone();
+        let other_queried = queried.clone();
+        other.serve(move |_| {
+            other_queried.lock().unwrap().push("other");
+
+            Some(Response::NextHop {
+                id: other_id.clone(),
+                token: None,
+                nodes: Vec::new(),
+                nodes6: Vec::new(),
+            })
+        });
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local
+            .run_until(dht.bootstrap_routing_table(vec![seed_address]))
+            .await?;
+
+        assert_eq!(*queried.lock().unwrap(), vec!["seed", "other"]);
+        assert_eq!(dht.routing_table.lock().map_err(DhtError::from)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_reports_added_nodes_and_failures() -> Result<(), Error> {
+        let seed = FakeNode::bind(NodeID::random()).await?;
+        let other = FakeNode::bind(NodeID::random()).await?;
+        let broken = FakeNode::bind(NodeID::random()).await?;
+        let seed_address = seed.address;
+        let broken_address = broken.address;
+
+        let seed_id = seed.id.clone();
+        let nodes = vec![other.info(), broken.info()];
+        seed.serve(move |_| {
+            Some(Response::NextHop {
+                id: seed_id.clone(),
+                token: None,
+                nodes: nodes.clone(),
+                nodes6: Vec::new(),
+            })
+        });
+
+        let other_id = other.id.clone();
+        other.serve(move |_| {
+            Some(Response::NextHop {
+                id: other_id.clone(),
+                token: None,
+                nodes: Vec::new(),
+                nodes6: Vec::new(),
+            })
+        });
+
+        // answers find_node with a response missing the nodes
+        let broken_id = broken.id.clone();
+        broken.serve(move |_| {
+            Some(Response::OnlyID {
+                id: broken_id.clone(),
+            })
+        });
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+
+        let report = local
+            .run_until(dht.bootstrap_routing_table(vec![seed_address]))
+            .await?;
+        assert_eq!(report.nodes_added, 2);
+        assert_eq!(
+            report
+                .failures
+                .iter()
+                .map(|(address, _)| *address)
+                .collect::<Vec<SocketAddrV4>>(),
+            vec![broken_address]
+        );
+
+        let report = local
+            .run_until(dht.bootstrap_routing_table(vec![broken_address]))
+            .await?;
+        assert_eq!(report.nodes_added, 0);
+        assert_eq!(report.failures.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accessors() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let local_addr = dht.local_addr();
+        assert_eq!(local_addr.ip(), "127.0.0.1".parse::<IpAddr>()?);
+        assert_ne!(local_addr.port(), 0);
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let response = local
+            .run_until(async {
+                let client = UdpSocket::bind("127.0.0.1:0").await?;
+                let ping = Envelope {
+                    ip: None,
+                    transaction_id: b"aa".to_vec(),
+                    version: None,
+                    message_type: Message::Query {
+                        query: Query::Ping {
+                            id: NodeID::random(),
+                        },
+                    },
+                    read_only: false,
+                };
+                client.send_to(&ping.encode()?, local_addr).await?;
+
+                let mut buf = [0u8; 1024];
+                let len = timeout(Duration::from_secs(1), client.recv(&mut buf)).await??;
+
+                Ok::<Envelope, Error>(Envelope::decode(&buf[..len])?)
+            })
+            .await?;
+
+        assert_eq!(
+            response.message_type,
+            Message::Response {
+                response: Response::OnlyID {
+                    id: dht.node_id().clone(),
+                },
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn info_hashes_with_counts() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let (one, two, three) = (NodeID::random(), NodeID::random(), NodeID::random());
+
+        {
+            let mut torrents = dht.torrents.lock().map_err(DhtError::from)?;
+            torrents.insert(one.clone(), vec!["1.1.1.1:1".parse()?]);
+            torrents.insert(
+                three.clone(),
+                vec![
+                    "1.1.1.1:1".parse()?,
+                    "2.2.2.2:2".parse()?,
+                    "3.3.3.3:3".parse()?,
+                ],
+            );
+            torrents.insert(
+                two.clone(),
+                vec!["1.1.1.1:1".parse()?, "2.2.2.2:2".parse()?],
+            );
+        }
+
+        assert_eq!(
+            dht.info_hashes_with_counts()?,
+            vec![(three, 3), (two, 2), (one, 1)]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn announce_dry_run() -> Result<(), Error> {
+        let info_hash = NodeID::from_hex(b"0000000000000000000000000000000000000000")?;
+        let peer: SocketAddrV4 = "1.2.3.4:6881".parse()?;
+
+        let far = FakeNode::bind(NodeID::from_hex(
+            b"ffffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let near = FakeNode::bind(NodeID::from_hex(
+            b"0fffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let (far_info, near_info) = (far.info(), near.info());
+
+        let (far_id, next_hop) = (far.id.clone(), near_info.clone());
+        far.serve(move |_| {
+            Some(Response::NextHop {
+                id: far_id.clone(),
+                token: Some(b"far".to_vec()),
+                nodes: vec![next_hop.clone()],
+                nodes6: Vec::new(),
+            })
+        });
+
+        let near_id = near.id.clone();
+        near.serve(move |_| {
+            Some(Response::GetPeers {
+                id: near_id.clone(),
+                token: Some(b"near".to_vec()),
+                peers: vec![peer.into()],
+            })
+        });
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &far_info)?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let targets = local.run_until(dht.announce_dry_run(info_hash)).await?;
+
+        assert_eq!(
+            targets,
+            vec![(near_info, b"near".to_vec()), (far_info, b"far".to_vec())]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_peers_with_metrics() -> Result<(), Error> {
+        let peer: SocketAddrV4 = "1.2.3.4:6881".parse()?;
+
+        let far = FakeNode::bind(NodeID::from_hex(
+            b"ffffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let near = FakeNode::bind(NodeID::from_hex(
+            b"0fffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let far_info = far.info();
+
+        let (far_id, next_hop) = (far.id.clone(), near.info());
+        far.serve(move |_| {
+            Some(Response::NextHop {
+                id: far_id.clone(),
+                token: None,
+                nodes: vec![next_hop.clone()],
+                nodes6: Vec::new(),
+            })
+        });
+
+        let near_id = near.id.clone();
+        near.serve(move |_| {
+            Some(Response::GetPeers {
+                id: near_id.clone(),
+                token: None,
+                peers: vec![peer.into()],
+            })
+        });
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &far_info)?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let (peers, metrics) = local
+            .run_until(dht.get_peers_with_metrics(NodeID::random()))
+            .await?;
+
+        assert_eq!(peers, vec![peer]);
+        let time_to_first_peer = metrics.time_to_first_peer.unwrap();
+        assert!(time_to_first_peer > Duration::from_secs(0));
+        assert!(time_to_first_peer <= metrics.total);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn announce_with_replication() -> Result<(), Error> {
+        let info_hash = NodeID::from_hex(b"0000000000000000000000000000000000000000")?;
+
+        let mut announced = Vec::new();
+        let mut nodes = Vec::new();
+        for id in &[
+            b"0fffffffffffffffffffffffffffffffffffffff",
+            b"f0ffffffffffffffffffffffffffffffffffffff",
+        ] {
+            let node = FakeNode::bind(NodeID::from_hex(*id)?).await?;
+            nodes.push(node.info());
+
+            let (sender, receiver) = mpsc::unbounded();
+            announced.push(receiver);
+
+            let node_id = node.id.clone();
+            node.serve(move |query| match query {
+                Query::GetPeers { .. } => Some(Response::NextHop {
+                    id: node_id.clone(),
+                    token: Some(b"token".to_vec()),
+                    nodes: Vec::new(),
+                    nodes6: Vec::new(),
+                }),
+                Query::AnnouncePeer { info_hash, .. } => {
+                    let _ = sender.unbounded_send(info_hash);
+                    Some(Response::OnlyID {
+                        id: node_id.clone(),
+                    })
+                }
+                _ => None,
+            });
+        }
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        for node in &nodes {
+            add_good_node(&dht, node)?;
+        }
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let count = local
+            .run_until(dht.announce_with_replication(info_hash.clone(), PortType::Implied(6881), 1))
+            .await?;
+
+        assert_eq!(count, 1);
+        assert_eq!(announced[0].try_next()?, Some(info_hash));
+        assert!(announced[1].try_next().is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_peers_then_announce() -> Result<(), Error> {
+        let info_hash = NodeID::from_hex(b"0000000000000000000000000000000000000000")?;
+        let peer: SocketAddrV4 = "1.2.3.4:6881".parse()?;
+
+        let far = FakeNode::bind(NodeID::from_hex(
+            b"ffffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let near = FakeNode::bind(NodeID::from_hex(
+            b"0fffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let far_info = far.info();
+
+        let (far_id, next_hop) = (far.id.clone(), near.info());
+        far.serve(move |query| match query {
+            Query::GetPeers { .. } => Some(Response::NextHop {
+                id: far_id.clone(),
+                token: None,
+                nodes: vec![next_hop.clone()],
+                nodes6: Vec::new(),
+            }),
+            _ => None,
+        });
+
+        let get_peers_queries = Arc::new(AtomicUsize::new(0));
+        let (announced_tx, mut announced_rx) = mpsc::unbounded();
+        let near_id = near.id.clone();
+        let near_queries = get_peers_queries.clone();
+        near.serve(move |query| match query {
+            Query::GetPeers { .. } => {
+                near_queries.fetch_add(1, Ordering::SeqCst);
+                Some(Response::GetPeers {
+                    id: near_id.clone(),
+                    token: Some(b"near".to_vec()),
+                    peers: vec![peer.into()],
+                })
+            }
+            Query::AnnouncePeer { info_hash, .. } => {
+                let _ = announced_tx.unbounded_send(info_hash);
+                Some(Response::OnlyID {
+                    id: near_id.clone(),
+                })
+            }
+            _ => None,
+        });
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &far_info)?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let peers = local.run_until(dht.get_peers(info_hash.clone())).await?;
+        assert_eq!(peers, vec![peer]);
+
+        let count = local
+            .run_until(dht.announce_with_replication(info_hash.clone(), PortType::Implied(6881), 1))
+            .await?;
+        assert_eq!(count, 1);
+        assert_eq!(announced_rx.try_next()?, Some(info_hash));
+
+        // the announce used the tokens from the lookup in get_peers
+        assert_eq!(get_peers_queries.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_peers_stream() -> Result<(), Error> {
+        let peers: Vec<SocketAddrV4> = vec![
+            "1.1.1.1:1".parse()?,
+            "2.2.2.2:2".parse()?,
+            "3.3.3.3:3".parse()?,
+        ];
+
+        let seed = FakeNode::bind(NodeID::from_hex(
+            b"ffffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let a = FakeNode::bind(NodeID::from_hex(
+            b"0fffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let b = FakeNode::bind(NodeID::from_hex(
+            b"00ffffffffffffffffffffffffffffffffffffff",
+        )?)
+        .await?;
+        let seed_info = seed.info();
+
+        let (seed_id, next_hops) = (seed.id.clone(), vec![a.info(), b.info()]);
+        seed.serve(move |_| {
+            Some(Response::NextHop {
+                id: seed_id.clone(),
+                token: None,
+                nodes: next_hops.clone(),
+                nodes6: Vec::new(),
+            })
+        });
+
+        for (node, node_peers) in vec![(a, peers[..2].to_vec()), (b, peers[1..].to_vec())] {
+            let id = node.id.clone();
+            node.serve(move |_| {
+                Some(Response::GetPeers {
+                    id: id.clone(),
+                    token: None,
+                    peers: node_peers.iter().map(|peer| (*peer).into()).collect(),
+                })
+            });
+        }
+
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        add_good_node(&dht, &seed_info)?;
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        let mut found = local
+            .run_until(
+                dht.get_peers_stream(NodeID::from_hex(
+                    b"0000000000000000000000000000000000000000",
+                )?)
+                .collect::<Vec<SocketAddrV4>>(),
+            )
+            .await;
+
+        found.sort();
+        assert_eq!(found, peers);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pause_holds_back_discovery() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let node = FakeNode::bind(NodeID::random()).await?;
+        let (node_id, node_address) = (node.id.clone(), node.address);
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counted = queries.clone();
+        node.serve(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+
+            Some(Response::NextHop {
+                id: node_id.clone(),
+                token: None,
+                nodes: Vec::new(),
+                nodes6: Vec::new(),
+            })
+        });
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local
+            .run_until(async {
+                dht.pause();
+                let bootstrap = dht.bootstrap_routing_table(vec![node_address]);
+                futures::pin_mut!(bootstrap);
+
+                assert!(timeout(Duration::from_millis(200), &mut bootstrap)
+                    .await
+                    .is_err());
+                assert_eq!(queries.load(Ordering::SeqCst), 0);
+
+                dht.resume();
+                timeout(Duration::from_secs(1), bootstrap).await??;
+                assert_eq!(queries.load(Ordering::SeqCst), 1);
+
+                Ok::<(), Error>(())
+            })
+            .await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,48 @@
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use tokio::sync::Notify;
+
+/// Holds back outbound discovery queries while the node is paused.
+pub struct PauseGate {
+    paused: AtomicBool,
+    resumed: Notify,
+}
+
+impl PauseGate {
+    pub fn new() -> PauseGate {
+        PauseGate {
+            paused: AtomicBool::new(false),
+            resumed: Notify::new(),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.resumed.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the gate isn't paused. Returns immediately when it isn't
+    /// paused already.
+    pub async fn wait(&self) {
+        loop {
+            // Registered before checking the flag so a resume between the
+            // check and the await isn't missed.
+            let resumed = self.resumed.notified();
+            if !self.is_paused() {
+                return;
+            }
+
+            resumed.await;
+        }
+    }
+}
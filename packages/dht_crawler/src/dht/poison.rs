@@ -0,0 +1,81 @@
+use std::sync::{
+    Mutex,
+    MutexGuard,
+};
+
+/// Locking which survives a panic while the lock was held.
+pub(super) trait LockRecovering<T> {
+    /// Locks the mutex. If a thread panicked while holding the lock, logs a
+    /// warning, clears the poison and carries on with the data as the
+    /// panicking thread left it, rather than failing every later lock.
+    fn lock_recovering(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockRecovering<T> for Mutex<T> {
+    fn lock_recovering(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            eprintln!("Recovering From Poisoned Lock");
+            self.clear_poison();
+
+            poisoned.into_inner()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::testing::add_good_node,
+        Dht,
+    };
+    use failure::Error;
+    use krpc_encoding::{
+        Message,
+        NodeID,
+        NodeInfo,
+        Query,
+        Response,
+    };
+    use std::panic::{
+        self,
+        AssertUnwindSafe,
+    };
+    use tokio_krpc::InboundQuery;
+
+    #[tokio::test]
+    async fn poisoned_routing_table_recovered() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let node = NodeInfo::new(NodeID::random(), "129.21.63.170:6881".parse()?);
+        add_good_node(&dht, &node)?;
+
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _routing_table = dht.routing_table.lock().unwrap();
+            panic!("handler panicked while holding the routing table");
+        }));
+        assert!(panicked.is_err());
+        assert!(dht.routing_table.is_poisoned());
+
+        let response = dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::FindNode {
+                    id: NodeID::random(),
+                    target: node.node_id.clone(),
+                },
+                false,
+            ),
+            "129.21.63.171:6881".parse()?,
+        );
+
+        match response.message_type {
+            Message::Response {
+                response: Response::NextHop { nodes, .. },
+            } => assert_eq!(nodes, vec![node]),
+            other => panic!("expected nodes, got {:?}", other),
+        };
+        assert!(!dht.routing_table.is_poisoned());
+
+        Ok(())
+    }
+}
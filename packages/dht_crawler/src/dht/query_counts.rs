@@ -0,0 +1,55 @@
+use krpc_encoding::Query;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+/// Number of queries received of each type since the node started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueryCounts {
+    pub ping: u64,
+    pub find_node: u64,
+    pub get_peers: u64,
+    pub announce_peer: u64,
+
+    /// Queries of any other type.
+    pub other: u64,
+}
+
+/// Counters behind [`QueryCounts`] which can be updated concurrently.
+#[derive(Default)]
+pub struct QueryCounters {
+    ping: AtomicU64,
+    find_node: AtomicU64,
+    get_peers: AtomicU64,
+    announce_peer: AtomicU64,
+    other: AtomicU64,
+}
+
+impl QueryCounters {
+    pub fn new() -> QueryCounters {
+        QueryCounters::default()
+    }
+
+    pub fn record(&self, query: &Query) {
+        let counter = match query {
+            Query::Ping { .. } => &self.ping,
+            Query::FindNode { .. } => &self.find_node,
+            Query::GetPeers { .. } => &self.get_peers,
+            Query::AnnouncePeer { .. } => &self.announce_peer,
+            _ => &self.other,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> QueryCounts {
+        QueryCounts {
+            ping: self.ping.load(Ordering::Relaxed),
+            find_node: self.find_node.load(Ordering::Relaxed),
+            get_peers: self.get_peers.load(Ordering::Relaxed),
+            announce_peer: self.announce_peer.load(Ordering::Relaxed),
+            other: self.other.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+/// Picks which inbound queries are logged. Every `rate`th query is logged,
+/// starting with the first.
+pub struct QueryLogSampler {
+    /// Log one in this many queries. Zero disables logging.
+    rate: AtomicU64,
+
+    /// Number of queries seen since the rate was last set.
+    seen: AtomicU64,
+}
+
+impl QueryLogSampler {
+    pub fn new() -> QueryLogSampler {
+        QueryLogSampler {
+            rate: AtomicU64::new(0),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+        self.seen.store(0, Ordering::Relaxed);
+    }
+
+    /// Counts a query and returns whether it should be logged.
+    pub fn sample(&self) -> bool {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return false;
+        }
+
+        self.seen.fetch_add(1, Ordering::Relaxed) % rate == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryLogSampler;
+
+    #[test]
+    fn disabled_by_default() {
+        let sampler = QueryLogSampler::new();
+
+        assert!((0..100).all(|_| !sampler.sample()));
+    }
+
+    #[test]
+    fn samples_one_in_rate() {
+        let sampler = QueryLogSampler::new();
+        sampler.set_rate(10);
+
+        let sampled = (0..1000).filter(|_| sampler.sample()).count();
+
+        assert_eq!(sampled, 100);
+    }
+
+    #[test]
+    fn rate_of_one_logs_everything() {
+        let sampler = QueryLogSampler::new();
+        sampler.set_rate(1);
+
+        assert!((0..100).all(|_| sampler.sample()));
+    }
+}
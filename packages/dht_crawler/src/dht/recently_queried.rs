@@ -0,0 +1,113 @@
+use krpc_encoding::NodeID;
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+/// Maximum number of node ids remembered at once. When exceeded, the id
+/// queried longest ago is forgotten early.
+const MAX_RECENTLY_QUERIED: usize = 65536;
+
+/// Ids of nodes queried while crawling within the last `window`, so nodes
+/// returned over and over by different responders are only queried once.
+pub struct RecentlyQueried {
+    window: Duration,
+    queried_at: HashMap<NodeID, Instant>,
+
+    /// Ids in the order they were queried, oldest first.
+    order: VecDeque<(NodeID, Instant)>,
+}
+
+impl RecentlyQueried {
+    pub fn new(window: Duration) -> RecentlyQueried {
+        RecentlyQueried {
+            window,
+            queried_at: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Whether `id` was recorded as queried less than `window` before `now`.
+    pub fn queried_recently(&mut self, id: &NodeID, now: Instant) -> bool {
+        self.forget_expired(now);
+
+        self.queried_at.contains_key(id)
+    }
+
+    /// Records that `id` was queried at `now`.
+    pub fn record(&mut self, id: NodeID, now: Instant) {
+        self.queried_at.insert(id.clone(), now);
+        self.order.push_back((id, now));
+
+        while self.queried_at.len() > MAX_RECENTLY_QUERIED {
+            self.forget_oldest();
+        }
+    }
+
+    fn forget_expired(&mut self, now: Instant) {
+        while let Some((_, queried_at)) = self.order.front() {
+            if now.saturating_duration_since(*queried_at) < self.window {
+                break;
+            }
+
+            self.forget_oldest();
+        }
+    }
+
+    fn forget_oldest(&mut self) {
+        if let Some((id, queried_at)) = self.order.pop_front() {
+            // Only forget the id if it wasn't queried again since.
+            if self.queried_at.get(&id) == Some(&queried_at) {
+                self.queried_at.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        RecentlyQueried,
+        MAX_RECENTLY_QUERIED,
+    };
+    use krpc_encoding::NodeID;
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    #[test]
+    fn forgotten_after_window() {
+        let window = Duration::from_secs(60);
+        let mut recently_queried = RecentlyQueried::new(window);
+        let id = NodeID::random();
+        let now = Instant::now();
+
+        assert!(!recently_queried.queried_recently(&id, now));
+        recently_queried.record(id.clone(), now);
+
+        assert!(recently_queried.queried_recently(&id, now + window / 2));
+        assert!(!recently_queried.queried_recently(&id, now + window));
+    }
+
+    #[test]
+    fn bounded() {
+        let mut recently_queried = RecentlyQueried::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let first = NodeID::random();
+
+        recently_queried.record(first.clone(), now);
+        for _ in 0..MAX_RECENTLY_QUERIED {
+            recently_queried.record(NodeID::random(), now);
+        }
+
+        assert_eq!(recently_queried.queried_at.len(), MAX_RECENTLY_QUERIED);
+        assert!(!recently_queried.queried_recently(&first, now));
+    }
+}
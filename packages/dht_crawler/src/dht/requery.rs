@@ -0,0 +1,177 @@
+use crate::{
+    dht::{
+        poison::LockRecovering,
+        Dht,
+        QUERY_TIMEOUT,
+    },
+    errors::Result,
+    routing::RoutingTable,
+};
+use futures::{
+    channel::mpsc,
+    StreamExt,
+};
+use krpc_encoding::{
+    NodeID,
+    NodeInfo,
+};
+use std::net::SocketAddr;
+use tokio::time::timeout;
+
+/// Number of questionable nodes pinged at once.
+const MAX_CONCURRENT_REQUERIES: usize = 8;
+
+impl Dht {
+    /// Queues pings to the questionable nodes closest to `target` when a
+    /// response only has `found` of the `wanted` nodes. Nodes already being
+    /// pinged aren't queued again. Does nothing unless
+    /// [`DhtConfig::requery_questionable`] is set.
+    ///
+    /// [`DhtConfig::requery_questionable`]: crate::dht::DhtConfig::requery_questionable
+    pub(super) fn requery_questionable_near(
+        &self,
+        target: &NodeID,
+        wanted: usize,
+        found: usize,
+        routing_table: &RoutingTable,
+    ) -> Result<()> {
+        if !self.config.requery_questionable || found >= wanted {
+            return Ok(());
+        }
+
+        let mut requerying = self.requerying.lock()?;
+        for node in routing_table.closest_questionable(target, wanted - found) {
+            if requerying.insert(node.node_id.clone()) {
+                // Only fails once the worker is gone, when nobody is left to
+                // ping.
+                let _ = self.requery_queue.unbounded_send(node);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pings nodes queued by [`Dht::requery_questionable_near`], marking
+    /// them good when they answer and failed when they don't.
+    pub(super) async fn requery_questionable(self, queue: mpsc::UnboundedReceiver<NodeInfo>) {
+        queue
+            .for_each_concurrent(MAX_CONCURRENT_REQUERIES, |node| self.requery(node))
+            .await
+    }
+
+    async fn requery(&self, node: NodeInfo) {
+        self.pause_gate.wait().await;
+
+        let response = timeout(
+            QUERY_TIMEOUT,
+            self.transports
+                .request_transport(&node.address)
+                .ping(node.address),
+        )
+        .await;
+
+        self.requerying.lock_recovering().remove(&node.node_id);
+
+        let address = match node.address {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => return,
+        };
+
+        let mut routing_table = self.routing_table.lock_recovering();
+        if let Some(routing_node) = routing_table.get_or_add(node.node_id, address) {
+            match response {
+                Ok(Ok(_)) => routing_node.mark_successful_request(),
+                _ => routing_node.mark_failed_request(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        dht::{
+            poison::LockRecovering,
+            testing::FakeNode,
+            DhtConfig,
+        },
+        routing::{
+            Node,
+            NodeState,
+        },
+        Dht,
+    };
+    use failure::Error;
+    use krpc_encoding::{
+        NodeID,
+        Query,
+        Response,
+    };
+    use std::time::Duration;
+    use tokio::{
+        task::LocalSet,
+        time::{
+            sleep,
+            timeout,
+        },
+    };
+    use tokio_krpc::InboundQuery;
+
+    #[tokio::test]
+    async fn questionable_node_promoted_after_short_response() -> Result<(), Error> {
+        let config = DhtConfig {
+            requery_questionable: true,
+            ..DhtConfig::default()
+        };
+        let (dht, dht_future) = Dht::start_with_config("127.0.0.1:0".into_addr(), config).await?;
+
+        let questionable = FakeNode::bind(NodeID::random()).await?;
+        let (questionable_id, questionable_address) =
+            (questionable.id.clone(), questionable.address);
+        let response_id = questionable_id.clone();
+        questionable.serve(move |query| match query {
+            Query::Ping { .. } => Some(Response::OnlyID {
+                id: response_id.clone(),
+            }),
+            _ => None,
+        });
+        dht.routing_table
+            .lock_recovering()
+            .add_node(Node::new(questionable_id.clone(), questionable_address));
+
+        // Read-only so the querier isn't added and pinged as well.
+        dht.handle_request(
+            InboundQuery::new(
+                b"aa".to_vec(),
+                Query::FindNode {
+                    id: NodeID::random(),
+                    target: NodeID::random(),
+                },
+                true,
+            ),
+            "129.21.63.170:34238".parse()?,
+        );
+
+        let is_good = || {
+            dht.routing_table
+                .lock_recovering()
+                .get_node(&questionable_id)
+                .map(|node| node.state() == NodeState::Good)
+                .unwrap_or(false)
+        };
+        assert!(!is_good());
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local
+            .run_until(timeout(Duration::from_secs(2), async {
+                while !is_good() {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            }))
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use krpc_encoding::NodeID;
+use rand::Rng;
+
+/// Uniform random sample of up to `capacity` info hashes out of every info
+/// hash offered, kept using reservoir sampling (Algorithm R).
+///
+/// Every offer counts, so info hashes seen more often are more likely to be
+/// in the sample.
+pub struct Reservoir {
+    capacity: usize,
+
+    /// Number of info hashes offered since the reservoir was created.
+    seen: u64,
+
+    sample: Vec<NodeID>,
+}
+
+impl Reservoir {
+    pub fn new(capacity: usize) -> Reservoir {
+        Reservoir {
+            capacity,
+            seen: 0,
+            sample: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn offer(&mut self, info_hash: NodeID) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.seen += 1;
+
+        if self.sample.len() < self.capacity {
+            self.sample.push(info_hash);
+            return;
+        }
+
+        let idx = rand::thread_rng().gen_range(0, self.seen);
+        if idx < self.capacity as u64 {
+            self.sample[idx as usize] = info_hash;
+        }
+    }
+
+    pub fn sample(&self) -> &[NodeID] {
+        &self.sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reservoir;
+    use krpc_encoding::NodeID;
+
+    #[test]
+    fn never_exceeds_capacity() {
+        let mut reservoir = Reservoir::new(16);
+
+        for offered in 1..=1000 {
+            reservoir.offer(NodeID::random());
+            assert_eq!(reservoir.sample().len(), offered.min(16));
+        }
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let mut reservoir = Reservoir::new(0);
+
+        for _ in 0..100 {
+            reservoir.offer(NodeID::random());
+        }
+
+        assert!(reservoir.sample().is_empty());
+    }
+}
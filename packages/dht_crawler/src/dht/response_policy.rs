@@ -0,0 +1,132 @@
+use krpc_encoding::Query;
+use std::collections::HashMap;
+
+/// Kind of query received from another node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryType {
+    Ping,
+    FindNode,
+    GetPeers,
+    AnnouncePeer,
+    SampleInfoHashes,
+    Get,
+    Put,
+}
+
+impl QueryType {
+    pub fn of(query: &Query) -> QueryType {
+        match query {
+            Query::Ping { .. } => QueryType::Ping,
+            Query::FindNode { .. } => QueryType::FindNode,
+            Query::GetPeers { .. } => QueryType::GetPeers,
+            Query::AnnouncePeer { .. } => QueryType::AnnouncePeer,
+            Query::SampleInfoHashes { .. } => QueryType::SampleInfoHashes,
+            Query::Get { .. } => QueryType::Get,
+            Query::Put { .. } => QueryType::Put,
+        }
+    }
+}
+
+/// Whether queries of a type are answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponsePolicy {
+    /// Send the response to the querying node.
+    Respond,
+
+    /// Handle the query but never send the response. The querying node sees
+    /// a timeout.
+    Drop,
+}
+
+/// Response policies of every query type. Query types without a policy are
+/// responded to.
+pub struct ResponsePolicies {
+    policies: HashMap<QueryType, ResponsePolicy>,
+}
+
+impl ResponsePolicies {
+    pub fn new() -> ResponsePolicies {
+        ResponsePolicies {
+            policies: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, query_type: QueryType, policy: ResponsePolicy) {
+        self.policies.insert(query_type, policy);
+    }
+
+    pub fn get(&self, query_type: QueryType) -> ResponsePolicy {
+        self.policies
+            .get(&query_type)
+            .copied()
+            .unwrap_or(ResponsePolicy::Respond)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        QueryType,
+        ResponsePolicy,
+    };
+    use crate::{
+        addr::{
+            AsV4Address,
+            IntoSocketAddr,
+        },
+        Dht,
+    };
+    use failure::Error;
+    use futures::{
+        future,
+        StreamExt,
+        TryStreamExt,
+    };
+    use krpc_encoding::NodeID;
+    use std::time::Duration;
+    use tokio::{
+        net::UdpSocket,
+        task::LocalSet,
+        time::timeout,
+    };
+    use tokio_krpc::{
+        KRPCNode,
+        RequestTransport,
+    };
+
+    #[tokio::test]
+    async fn dropped_query_type_not_answered() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        dht.set_response_policy(QueryType::FindNode, ResponsePolicy::Drop)?;
+        let dht_address = dht.local_addr().into_v4()?;
+
+        let (send_transport, request_stream, _) =
+            KRPCNode::new(UdpSocket::bind("127.0.0.1:0").await?).serve();
+        let client = RequestTransport::new(NodeID::random(), send_transport);
+
+        let local = LocalSet::new();
+        local.spawn_local(dht_future);
+        local.spawn_local(
+            request_stream
+                .map_err(|err| eprintln!("Error in Request Stream: {}", err))
+                .for_each(|_| future::ready(())),
+        );
+
+        local
+            .run_until(async {
+                assert_eq!(client.ping(dht_address).await?, *dht.node_id());
+
+                let find_node = timeout(
+                    Duration::from_millis(500),
+                    client.find_node(dht_address, NodeID::random()),
+                )
+                .await;
+                assert!(find_node.is_err());
+
+                Ok::<(), Error>(())
+            })
+            .await?;
+
+        Ok(())
+    }
+}
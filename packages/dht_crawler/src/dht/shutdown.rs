@@ -0,0 +1,172 @@
+use crate::{
+    dht::{
+        Dht,
+        QUERY_TIMEOUT,
+    },
+    errors::{
+        ErrorKind,
+        Result,
+    },
+};
+use std::{
+    ffi::OsString,
+    fs::{
+        self,
+        File,
+    },
+    io::Write,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::atomic::Ordering,
+    time::Duration,
+};
+use tokio::time::{
+    sleep,
+    timeout,
+};
+
+/// Time between checks for requests still waiting on a response while
+/// draining.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl Dht {
+    /// Stops the node and writes its state, as encoded by
+    /// [`Dht::serialize_state`], to `path`.
+    ///
+    /// Queries from other nodes are ignored from the moment this is called.
+    /// Requests to other nodes already sent are given until they complete or
+    /// time out before the future returned by [`Dht::start`] finishes, so the
+    /// future must keep being polled until then.
+    ///
+    /// The state is written to a temporary file next to `path` which is
+    /// synced to disk before being renamed over `path`, so `path` always holds
+    /// either the previous state or the complete new state. Returns once the
+    /// state and the rename are durably written.
+    pub async fn shutdown_and_persist<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.shutting_down.store(true, Ordering::Relaxed);
+
+        let _ = timeout(QUERY_TIMEOUT, async {
+            // Requests can't be tracked once the transactions lock is
+            // poisoned, so there is nothing left to wait for.
+            while self.transports.pending_requests().unwrap_or(0) > 0 {
+                sleep(DRAIN_POLL_INTERVAL).await;
+            }
+        })
+        .await;
+
+        self.serve_handle.abort();
+
+        let state = self.serialize_state()?;
+        write_atomically(path.as_ref(), &state)
+            .map_err(|cause| ErrorKind::WriteStateError { cause })?;
+
+        Ok(())
+    }
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let temp_path = temp_path_for(path);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)?;
+
+    // The rename is only durable once the directory entry pointing at the new
+    // file is synced too.
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(directory)?.sync_all()
+}
+
+/// Path of the temporary file written before being renamed to `path`. In the
+/// same directory so the rename doesn't cross file systems.
+fn temp_path_for(path: &Path) -> PathBuf {
+    let mut file_name = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_else(OsString::new);
+    file_name.push(".tmp");
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        errors::Error as DhtError,
+        routing::Node,
+        Dht,
+    };
+    use failure::Error;
+    use krpc_encoding::NodeID;
+    use std::{
+        fs,
+        net::SocketAddrV4,
+    };
+    use tokio::task::LocalSet;
+
+    #[tokio::test]
+    async fn shutdown_and_reload() -> Result<(), Error> {
+        let (dht, dht_future) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        let path = std::env::temp_dir().join(format!("dht-state-{}", NodeID::random()));
+
+        let mut node = Node::new(NodeID::random(), "1.2.3.4:6881".parse()?);
+        node.mark_successful_request();
+        let node_id = node.id.clone();
+        dht.routing_table
+            .lock()
+            .map_err(DhtError::from)?
+            .add_node(node);
+
+        let info_hash = NodeID::random();
+        let peers: Vec<SocketAddrV4> = vec!["9.9.9.9:51413".parse()?];
+        dht.torrents
+            .lock()
+            .map_err(DhtError::from)?
+            .insert(info_hash.clone(), peers.clone());
+
+        let local = LocalSet::new();
+        let serving = local.spawn_local(dht_future);
+        local
+            .run_until(async {
+                dht.shutdown_and_persist(&path).await?;
+
+                // The serve loop stops once shutdown completes.
+                serving.await?;
+
+                Ok::<(), Error>(())
+            })
+            .await?;
+
+        let bytes = fs::read(&path)?;
+        fs::remove_file(&path)?;
+
+        let (restored, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        restored.load_state(&bytes)?;
+
+        assert!(restored
+            .routing_table
+            .lock()
+            .map_err(DhtError::from)?
+            .get_node(&node_id)
+            .is_some());
+        assert_eq!(
+            restored
+                .torrents
+                .lock()
+                .map_err(DhtError::from)?
+                .get(&info_hash),
+            Some(&peers)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Checkpointing of the routing table and torrents cache into a single binary
+//! blob.
+//!
+//! All integers are big endian. The blob starts with a one byte format version
+//! followed by the nodes in the routing table and then the torrents:
+//!
+//! ```text
+//! version: u8
+//! node count: u32, then each node as written by `Node::write_to`
+//! torrent count: u32, then for each torrent
+//!     info hash: [u8; 20]
+//!     peer count: u32, then each peer as ip: [u8; 4], port: u16
+//! ```
+
+use crate::{
+    dht::{
+        poison::LockRecovering,
+        Dht,
+    },
+    errors::{
+        ErrorKind,
+        Result,
+    },
+    routing::{
+        Node,
+        RoutingTable,
+    },
+};
+use byteorder::{
+    NetworkEndian,
+    ReadBytesExt,
+    WriteBytesExt,
+};
+use krpc_encoding::NodeID;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io::{
+        self,
+        Cursor,
+        Read,
+        Write,
+    },
+    net::{
+        Ipv4Addr,
+        SocketAddrV4,
+    },
+};
+
+/// Version of the format written by [`Dht::serialize_state`]. Bumped whenever
+/// the format changes.
+const STATE_VERSION: u8 = 1;
+
+type Torrents = HashMap<NodeID, Vec<SocketAddrV4>>;
+
+impl Dht {
+    /// Encodes the routing table and torrents cache into a blob which can be
+    /// restored with [`Dht::load_state`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`ErrorKind::WriteStateError`] when the table or cache has
+    /// more entries than the format can count.
+    pub fn serialize_state(&self) -> Result<Vec<u8>> {
+        let routing_table = self.routing_table.lock_recovering();
+        let torrents = self.torrents.lock()?;
+
+        let mut bytes = Vec::new();
+        write_state(&mut bytes, &routing_table, &torrents)
+            .map_err(|cause| ErrorKind::WriteStateError { cause })?;
+
+        Ok(bytes)
+    }
+
+    /// Restores a blob written by [`Dht::serialize_state`]. Nodes and peers are
+    /// merged into the current routing table and torrents cache.
+    pub fn load_state(&self, bytes: &[u8]) -> Result<()> {
+        let mut reader = Cursor::new(bytes);
+
+        let version = reader
+            .read_u8()
+            .map_err(|cause| ErrorKind::MalformedState { cause })?;
+        if version != STATE_VERSION {
+            return Err(ErrorKind::UnsupportedStateVersion { version })?;
+        }
+
+        let (nodes, torrents) =
+            read_state(&mut reader).map_err(|cause| ErrorKind::MalformedState { cause })?;
+
+        {
+            let mut routing_table = self.routing_table.lock_recovering();
+            for node in nodes {
+                routing_table.add_node(node);
+            }
+        }
+
+        let mut current_torrents = self.torrents.lock()?;
+        for (info_hash, peers) in torrents {
+            let current_peers = current_torrents.entry(info_hash).or_insert_with(Vec::new);
+            for peer in peers {
+                if !current_peers.contains(&peer) {
+                    current_peers.push(peer);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_state<W: Write>(
+    writer: &mut W,
+    routing_table: &RoutingTable,
+    torrents: &Torrents,
+) -> io::Result<()> {
+    writer.write_u8(STATE_VERSION)?;
+
+    writer.write_u32::<NetworkEndian>(len_u32(routing_table.len())?)?;
+    for node in routing_table.nodes() {
+        node.write_to(writer)?;
+    }
+
+    writer.write_u32::<NetworkEndian>(len_u32(torrents.len())?)?;
+    for (info_hash, peers) in torrents {
+        writer.write_all(&info_hash.as_bytes())?;
+        writer.write_u32::<NetworkEndian>(len_u32(peers.len())?)?;
+
+        for peer in peers {
+            writer.write_all(&peer.ip().octets())?;
+            writer.write_u16::<NetworkEndian>(peer.port())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_state<R: Read>(reader: &mut R) -> io::Result<(Vec<Node>, Torrents)> {
+    let node_count = reader.read_u32::<NetworkEndian>()?;
+    let mut nodes = Vec::new();
+    for _ in 0..node_count {
+        nodes.push(Node::read_from(reader)?);
+    }
+
+    let torrent_count = reader.read_u32::<NetworkEndian>()?;
+    let mut torrents = HashMap::new();
+    for _ in 0..torrent_count {
+        let mut info_hash = [0u8; 20];
+        reader.read_exact(&mut info_hash)?;
+
+        let peer_count = reader.read_u32::<NetworkEndian>()?;
+        let mut peers = Vec::new();
+        for _ in 0..peer_count {
+            let mut ip = [0u8; 4];
+            reader.read_exact(&mut ip)?;
+            let port = reader.read_u16::<NetworkEndian>()?;
+
+            peers.push(SocketAddrV4::new(Ipv4Addr::from(ip), port));
+        }
+
+        torrents.insert(NodeID::from_bytes(&info_hash), peers);
+    }
+
+    Ok((nodes, torrents))
+}
+
+fn len_u32(len: usize) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many entries"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        addr::IntoSocketAddr,
+        errors::{
+            Error as DhtError,
+            ErrorKind,
+        },
+        routing::Node,
+        Dht,
+    };
+    use failure::Error;
+    use krpc_encoding::NodeID;
+    use std::net::SocketAddrV4;
+
+    #[tokio::test]
+    async fn state_round_trip() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let mut good = Node::new(NodeID::random(), "1.2.3.4:6881".parse()?);
+        good.mark_successful_request();
+        good.mark_successful_request_from();
+        let mut failing = Node::new(NodeID::random(), "5.6.7.8:6881".parse()?);
+        failing.mark_failed_request();
+
+        let info_hash = NodeID::random();
+        let peers: Vec<SocketAddrV4> = vec!["9.9.9.9:51413".parse()?, "8.8.8.8:1".parse()?];
+
+        {
+            let mut routing_table = dht.routing_table.lock().map_err(DhtError::from)?;
+            routing_table.add_node(good);
+            routing_table.add_node(failing);
+        }
+        dht.torrents
+            .lock()
+            .map_err(DhtError::from)?
+            .insert(info_hash.clone(), peers.clone());
+
+        let bytes = dht.serialize_state()?;
+
+        let (restored, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+        restored.load_state(&bytes)?;
+
+        let original_table = dht.routing_table.lock().map_err(DhtError::from)?;
+        let restored_table = restored.routing_table.lock().map_err(DhtError::from)?;
+        assert_eq!(restored_table.len(), 2);
+        for node in original_table.nodes() {
+            assert_eq!(restored_table.get_node(&node.id), Some(node));
+        }
+
+        assert_eq!(
+            restored.torrents.lock().map_err(DhtError::from)?.get(&info_hash),
+            Some(&peers)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn load_state_rejects_unknown_version() -> Result<(), Error> {
+        let (dht, _) = Dht::start("127.0.0.1:0".into_addr()).await?;
+
+        let err = dht.load_state(&[0xff, 0, 0, 0, 0]).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::UnsupportedStateVersion { version: 0xff } => (),
+            kind => panic!("unexpected error {}", kind),
+        }
+
+        Ok(())
+    }
+}
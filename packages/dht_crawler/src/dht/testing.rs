@@ -0,0 +1,97 @@
+//! Helpers for tests which need other nodes to talk to.
+
+use crate::{
+    addr::AsV4Address,
+    dht::{
+        poison::LockRecovering,
+        Dht,
+    },
+    errors::{
+        ErrorKind,
+        Result,
+    },
+    routing::Node,
+};
+use futures::StreamExt;
+use krpc_encoding::{
+    Envelope,
+    Message,
+    NodeID,
+    NodeInfo,
+    Query,
+    Response,
+};
+use std::net::SocketAddrV4;
+use tokio::{
+    net::UdpSocket,
+    spawn,
+};
+use tokio_krpc::KRPCNode;
+
+/// A node on localhost answering queries with canned responses.
+pub struct FakeNode {
+    pub id: NodeID,
+    pub address: SocketAddrV4,
+    socket: UdpSocket,
+}
+
+impl FakeNode {
+    pub async fn bind(id: NodeID) -> Result<FakeNode> {
+        let socket = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(|cause| ErrorKind::BindError { cause })?;
+        let address = socket
+            .local_addr()
+            .map_err(|cause| ErrorKind::BindError { cause })?
+            .into_v4()?;
+
+        Ok(FakeNode {
+            id,
+            address,
+            socket,
+        })
+    }
+
+    pub fn info(&self) -> NodeInfo {
+        NodeInfo::new(self.id.clone(), self.address.into())
+    }
+
+    /// Answers each query with the response built by `respond`. Queries for
+    /// which `respond` returns `None` are ignored.
+    pub fn serve<F>(self, respond: F)
+    where
+        F: Fn(Query) -> Option<Response> + Send + 'static,
+    {
+        let (send_transport, queries, _) = KRPCNode::new(self.socket).serve();
+
+        spawn(async move {
+            let mut queries = Box::pin(queries);
+
+            while let Some(Ok((query, from))) = queries.next().await {
+                let response = match respond(query.query) {
+                    Some(response) => response,
+                    None => continue,
+                };
+
+                let envelope = Envelope {
+                    ip: None,
+                    transaction_id: query.transaction_id,
+                    version: None,
+                    message_type: Message::Response { response },
+                    read_only: false,
+                };
+
+                let _ = send_transport.send(from, envelope).await;
+            }
+        });
+    }
+}
+
+/// Adds `node` to the routing table of `dht` as a good node.
+pub fn add_good_node(dht: &Dht, node: &NodeInfo) -> Result<()> {
+    let mut routing_node = Node::new(node.node_id.clone(), node.address.into_v4()?);
+    routing_node.mark_successful_request();
+    dht.routing_table.lock_recovering().add_node(routing_node);
+
+    Ok(())
+}
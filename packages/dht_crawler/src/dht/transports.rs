@@ -0,0 +1,105 @@
+use crate::errors::Result;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+};
+use tokio_krpc::{
+    RequestTransport,
+    SendTransport,
+};
+
+/// Handles for sending messages out of each socket the node is listening on.
+pub struct Transports {
+    local_addrs: Vec<SocketAddr>,
+
+    /// Indices of the sockets bound to IPv4 addresses.
+    v4_indices: Vec<usize>,
+
+    /// Indices of the sockets bound to IPv6 addresses.
+    v6_indices: Vec<usize>,
+
+    send_transports: Vec<Arc<SendTransport>>,
+    request_transports: Vec<RequestTransport>,
+}
+
+impl Transports {
+    pub fn new() -> Transports {
+        Transports {
+            local_addrs: Vec::new(),
+            v4_indices: Vec::new(),
+            v6_indices: Vec::new(),
+            send_transports: Vec::new(),
+            request_transports: Vec::new(),
+        }
+    }
+
+    /// Adds a socket bound to `local_addr`. Sockets are numbered in the order
+    /// they are added.
+    pub fn add(
+        &mut self,
+        local_addr: SocketAddr,
+        send_transport: Arc<SendTransport>,
+        request_transport: RequestTransport,
+    ) {
+        if local_addr.is_ipv4() {
+            self.v4_indices.push(self.local_addrs.len());
+        } else {
+            self.v6_indices.push(self.local_addrs.len());
+        }
+
+        self.local_addrs.push(local_addr);
+        self.send_transports.push(send_transport);
+        self.request_transports.push(request_transport);
+    }
+
+    /// Addresses the sockets are bound to, in the order they were added.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Picks the socket queries to `address` are sent from.
+    ///
+    /// Each destination IP is always mapped to the same socket of its address
+    /// family, by its numeric value modulo the number of sockets of that
+    /// family. Remote nodes tie the tokens they hand out to the address they
+    /// see queries coming from, so queries to a node must keep coming from the
+    /// same address.
+    pub fn request_transport(&self, address: &SocketAddr) -> &RequestTransport {
+        &self.request_transports[self.index_for(address)]
+    }
+
+    /// The socket at `index`, in the order the sockets were added.
+    pub fn send_transport(&self, index: usize) -> &SendTransport {
+        &self.send_transports[index]
+    }
+
+    /// Number of requests sent from any socket which are still waiting for a
+    /// response.
+    pub fn pending_requests(&self) -> Result<usize> {
+        let mut pending = 0;
+        for transport in &self.send_transports {
+            pending += transport.pending_requests()?;
+        }
+
+        Ok(pending)
+    }
+
+    fn index_for(&self, address: &SocketAddr) -> usize {
+        let (hash, indices) = match address {
+            SocketAddr::V4(address) => (u32::from(*address.ip()) as usize, &self.v4_indices),
+            SocketAddr::V6(address) => (u128::from(*address.ip()) as usize, &self.v6_indices),
+        };
+
+        if indices.is_empty() {
+            return hash % self.request_transports.len();
+        }
+
+        indices[hash % indices.len()]
+    }
+}
+
+impl Default for Transports {
+    fn default() -> Transports {
+        Transports::new()
+    }
+}
@@ -8,7 +8,10 @@ use std::{
     self,
     fmt,
     io,
-    net::SocketAddrV6,
+    net::{
+        Ipv4Addr,
+        SocketAddrV6,
+    },
     sync::PoisonError,
 };
 
@@ -35,6 +38,40 @@ pub enum ErrorKind {
     #[fail(display = "Insufficient address information provided")]
     InsufficientAddress,
 
+    #[fail(display = "Info hash {} doesn't fit in 20 bytes", info_hash)]
+    InvalidInfoHash { info_hash: proto::NodeID },
+
+    #[fail(display = "Node id {} isn't valid for {}", id, ip)]
+    InvalidNodeIDForIP { id: proto::NodeID, ip: Ipv4Addr },
+
+    #[fail(display = "CAS mismatch, re-read value and try again")]
+    CasMismatch,
+
+    #[fail(display = "Sequence number less than current")]
+    SequenceNumberLessThanCurrent,
+
+    //// Persistence Errors
+    #[fail(display = "Unsupported state format version {}", version)]
+    UnsupportedStateVersion { version: u8 },
+
+    #[fail(display = "Malformed state")]
+    MalformedState {
+        #[fail(cause)]
+        cause: io::Error,
+    },
+
+    #[fail(display = "Failed to write state")]
+    WriteStateError {
+        #[fail(cause)]
+        cause: io::Error,
+    },
+
+    #[fail(display = "Failed to access event log")]
+    EventLogError {
+        #[fail(cause)]
+        cause: io::Error,
+    },
+
     //// Wrapping Other Errors
     #[fail(display = "Lock poisoned")]
     LockPoisoned,
@@ -54,11 +91,17 @@ pub enum ErrorKind {
         cause: tokio_krpc::send_errors::Error,
     },
 
+    #[fail(display = "No addresses to bind to")]
+    NoBindAddresses,
+
     #[fail(display = "Failed to bind")]
     BindError {
         #[fail(cause)]
         cause: io::Error,
     },
+
+    #[fail(display = "Partition start must come before its end")]
+    EmptyPartition,
 }
 
 impl Fail for Error {
@@ -72,11 +115,19 @@ impl Fail for Error {
 }
 
 impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        self.inner.get_context()
+    }
+
     pub fn as_request_error(&self) -> proto::KRPCError {
         let (code, message) = match self.inner.get_context() {
             ErrorKind::UnimplementedRequestType => (204, "Unimplemented"),
             ErrorKind::InvalidToken => (203, "Invalid Token"),
             ErrorKind::InsufficientAddress => (203, "Not enough address info provided"),
+            ErrorKind::InvalidInfoHash { .. } => (203, "Invalid Info Hash"),
+            ErrorKind::InvalidNodeIDForIP { .. } => (203, "Invalid Node ID"),
+            ErrorKind::CasMismatch => (301, "CAS mismatch, re-read value and try again"),
+            ErrorKind::SequenceNumberLessThanCurrent => (302, "Sequence number less than current"),
             _ => (202, "Server Error"),
         };
 
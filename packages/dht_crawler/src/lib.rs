@@ -9,6 +9,8 @@
 pub mod addr;
 pub mod dht;
 pub mod errors;
+pub mod peer_source;
 pub mod routing;
+pub mod storage;
 
 pub use crate::dht::Dht;
@@ -0,0 +1,142 @@
+//! Sources of peers for a torrent which can be used interchangeably with the
+//! DHT, such as trackers or peer exchange.
+
+use crate::{
+    dht::Dht,
+    errors::Result,
+};
+use futures::future;
+use krpc_encoding::NodeID;
+use std::{
+    future::Future,
+    net::SocketAddrV4,
+    pin::Pin,
+};
+
+pub type PeersFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<SocketAddrV4>>> + 'a>>;
+
+/// Something which can find peers seeding an info hash.
+pub trait PeerSource {
+    /// Gets a list of peers seeding `info_hash`.
+    fn get_peers(&self, info_hash: NodeID) -> PeersFuture<'_>;
+}
+
+impl PeerSource for Dht {
+    fn get_peers(&self, info_hash: NodeID) -> PeersFuture<'_> {
+        Box::pin(Dht::get_peers(self, info_hash))
+    }
+}
+
+/// Queries many sources at once and combines their peers.
+pub struct MergedPeerSource {
+    sources: Vec<Box<dyn PeerSource>>,
+}
+
+impl MergedPeerSource {
+    pub fn new(sources: Vec<Box<dyn PeerSource>>) -> MergedPeerSource {
+        MergedPeerSource { sources }
+    }
+}
+
+impl PeerSource for MergedPeerSource {
+    /// Gets the peers found by every source without duplicates. Sources which
+    /// fail are skipped unless all of them fail, in which case the first error
+    /// is returned.
+    fn get_peers(&self, info_hash: NodeID) -> PeersFuture<'_> {
+        Box::pin(async move {
+            let results = future::join_all(
+                self.sources
+                    .iter()
+                    .map(|source| source.get_peers(info_hash.clone())),
+            )
+            .await;
+
+            let mut peers = Vec::new();
+            let mut first_error = None;
+            let mut any_succeeded = false;
+
+            for result in results {
+                match result {
+                    Ok(source_peers) => {
+                        any_succeeded = true;
+                        for peer in source_peers {
+                            if !peers.contains(&peer) {
+                                peers.push(peer);
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+
+            match first_error {
+                Some(err) if !any_succeeded => Err(err),
+                _ => Ok(peers),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MergedPeerSource,
+        PeerSource,
+        PeersFuture,
+    };
+    use crate::errors::{
+        ErrorKind,
+        Result,
+    };
+    use failure::Error;
+    use krpc_encoding::NodeID;
+    use std::net::SocketAddrV4;
+
+    struct FixedPeers(Vec<SocketAddrV4>);
+
+    impl PeerSource for FixedPeers {
+        fn get_peers(&self, _info_hash: NodeID) -> PeersFuture<'_> {
+            let result: Result<_> = Ok(self.0.clone());
+
+            Box::pin(async move { result })
+        }
+    }
+
+    struct Failing;
+
+    impl PeerSource for Failing {
+        fn get_peers(&self, _info_hash: NodeID) -> PeersFuture<'_> {
+            let result: Result<Vec<SocketAddrV4>> = Err(ErrorKind::Timeout.into());
+
+            Box::pin(async move { result })
+        }
+    }
+
+    #[tokio::test]
+    async fn merges_peers() -> std::result::Result<(), Error> {
+        let a: SocketAddrV4 = "1.1.1.1:1".parse()?;
+        let b: SocketAddrV4 = "2.2.2.2:2".parse()?;
+        let c: SocketAddrV4 = "3.3.3.3:3".parse()?;
+
+        let sources: Vec<Box<dyn PeerSource>> = vec![
+            Box::new(FixedPeers(vec![a, b])),
+            Box::new(Failing),
+            Box::new(FixedPeers(vec![b, c])),
+        ];
+        let merged = MergedPeerSource::new(sources);
+
+        assert_eq!(merged.get_peers(NodeID::random()).await?, vec![a, b, c]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn all_sources_failing() {
+        let sources: Vec<Box<dyn PeerSource>> = vec![Box::new(Failing), Box::new(Failing)];
+        let merged = MergedPeerSource::new(sources);
+
+        assert!(merged.get_peers(NodeID::random()).await.is_err());
+    }
+}
@@ -48,7 +48,20 @@ impl Bucket {
         NodeID::new(self.start.deref() + (self.end.deref() - self.start.deref()) / 2u8)
     }
 
-    pub fn split(&mut self) -> Bucket {
+    /// Whether the bucket spans enough keys to be split into two non-empty
+    /// buckets.
+    pub fn can_split(&self) -> bool {
+        self.end.deref() - self.start.deref() >= BigUint::from(2u8)
+    }
+
+    /// Splits the bucket in half, keeping the lower half and returning the
+    /// upper half. Returns `None` without changing the bucket if it spans a
+    /// single key, as one of the halves would be empty.
+    pub fn split(&mut self) -> Option<Bucket> {
+        if !self.can_split() {
+            return None;
+        }
+
         let midpoint = self.midpoint();
 
         let next_bucket_end = mem::replace(&mut self.end, midpoint.clone());
@@ -67,7 +80,7 @@ impl Bucket {
             nodes.push(node);
         }
 
-        next_bucket
+        Some(next_bucket)
     }
 
     pub fn is_full(&self) -> bool {
@@ -104,6 +117,12 @@ impl Bucket {
             .filter(|node| node.state() == NodeState::Good)
     }
 
+    pub fn questionable_nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes
+            .iter()
+            .filter(|node| node.state() == NodeState::Questionable)
+    }
+
     pub fn get(&self, id: &NodeID) -> Option<&Node> {
         self.nodes.iter().find(|node| &node.id == id)
     }
@@ -183,7 +202,7 @@ mod tests {
 
         assert_eq!(bucket.nodes.len(), 6);
 
-        let next_bucket = bucket.split();
+        let next_bucket = bucket.split().unwrap();
 
         for i in (10 as u8)..13 {
             let id = NodeID::new(BigUint::from(i));
@@ -200,6 +219,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn split_single_key() {
+        let mut bucket = Bucket::new(
+            NodeID::new(BigUint::from(10u8)),
+            NodeID::new(BigUint::from(11u8)),
+        );
+        bucket.add_node(Node::new_with_id(10));
+
+        assert!(!bucket.can_split());
+        assert!(bucket.split().is_none());
+        assert_eq!(*bucket.start, BigUint::from(10u8));
+        assert_eq!(*bucket.end, BigUint::from(11u8));
+        assert_eq!(bucket.nodes.len(), 1);
+    }
+
     #[test]
     fn get_empty() {
         let bucket = Bucket::initial_bucket();
@@ -1,13 +1,23 @@
 mod bucket;
 mod node;
+mod ranker;
+mod sybil;
 mod table;
 mod token_validator;
 
 pub use self::{
-    node::Node,
+    node::{
+        Node,
+        NodeState,
+    },
+    ranker::{
+        NodeRanker,
+        XorDistance,
+    },
     table::{
         FindNodeResult,
         RoutingTable,
+        SelectionPolicy,
     },
     token_validator::TokenValidator,
 };
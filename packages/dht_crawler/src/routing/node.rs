@@ -1,3 +1,8 @@
+use byteorder::{
+    NetworkEndian,
+    ReadBytesExt,
+    WriteBytesExt,
+};
 use chrono::{
     NaiveDateTime,
     Utc,
@@ -6,13 +11,26 @@ use krpc_encoding::{
     NodeID,
     NodeInfo,
 };
-use std::net::SocketAddrV4;
+use std::{
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    net::{
+        Ipv4Addr,
+        SocketAddrV4,
+    },
+};
 
 #[derive(Debug, PartialEq)]
 pub struct Node {
     pub id: NodeID,
     pub address: SocketAddrV4,
 
+    /// Time this node was first discovered.
+    first_seen: NaiveDateTime,
+
     /// Last time a message was sent from ourselves to this node and a response
     /// was received successfully.
     last_request_to: Option<NaiveDateTime>,
@@ -26,13 +44,13 @@ pub struct Node {
 
 impl<'a> Into<NodeInfo> for &'a Node {
     fn into(self) -> NodeInfo {
-        NodeInfo::new(self.id.clone(), self.address)
+        NodeInfo::new(self.id.clone(), self.address.into())
     }
 }
 
 impl Into<NodeInfo> for Node {
     fn into(self) -> NodeInfo {
-        NodeInfo::new(self.id, self.address)
+        NodeInfo::new(self.id, self.address.into())
     }
 }
 
@@ -57,12 +75,17 @@ impl Node {
         Node {
             id,
             address,
+            first_seen: Utc::now().naive_utc(),
             last_request_to: None,
             last_request_from: None,
             failed_requests: 0,
         }
     }
 
+    pub fn first_seen(&self) -> NaiveDateTime {
+        self.first_seen
+    }
+
     pub fn mark_successful_request(&mut self) {
         self.failed_requests = 0;
         self.last_request_to = Some(Utc::now().naive_utc());
@@ -98,6 +121,44 @@ impl Node {
         }
     }
 
+    /// Writes the node, including its contact history, in the format read by
+    /// [`Node::read_from`].
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.id.as_bytes())?;
+        writer.write_all(&self.address.ip().octets())?;
+        writer.write_u16::<NetworkEndian>(self.address.port())?;
+        write_time(writer, self.first_seen)?;
+        write_optional_time(writer, self.last_request_to)?;
+        write_optional_time(writer, self.last_request_from)?;
+        writer.write_u8(self.failed_requests)?;
+
+        Ok(())
+    }
+
+    /// Reads a node written by [`Node::write_to`].
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Node> {
+        let mut id = [0u8; 20];
+        reader.read_exact(&mut id)?;
+
+        let mut ip = [0u8; 4];
+        reader.read_exact(&mut ip)?;
+        let port = reader.read_u16::<NetworkEndian>()?;
+
+        Ok(Node {
+            id: NodeID::from_bytes(&id),
+            address: SocketAddrV4::new(Ipv4Addr::from(ip), port),
+            first_seen: read_time(reader)?,
+            last_request_to: read_optional_time(reader)?,
+            last_request_from: read_optional_time(reader)?,
+            failed_requests: reader.read_u8()?,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn set_first_seen(&mut self, first_seen: NaiveDateTime) {
+        self.first_seen = first_seen;
+    }
+
     #[cfg(test)]
     pub fn new_with_id(id: u8) -> Node {
         use num_bigint::BigUint;
@@ -108,6 +169,36 @@ impl Node {
     }
 }
 
+fn write_time<W: Write>(writer: &mut W, time: NaiveDateTime) -> io::Result<()> {
+    writer.write_i64::<NetworkEndian>(time.timestamp())?;
+    writer.write_u32::<NetworkEndian>(time.timestamp_subsec_nanos())
+}
+
+fn read_time<R: Read>(reader: &mut R) -> io::Result<NaiveDateTime> {
+    let secs = reader.read_i64::<NetworkEndian>()?;
+    let nanos = reader.read_u32::<NetworkEndian>()?;
+
+    NaiveDateTime::from_timestamp_opt(secs, nanos)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "timestamp out of range"))
+}
+
+fn write_optional_time<W: Write>(writer: &mut W, time: Option<NaiveDateTime>) -> io::Result<()> {
+    match time {
+        None => writer.write_u8(0),
+        Some(time) => {
+            writer.write_u8(1)?;
+            write_time(writer, time)
+        }
+    }
+}
+
+fn read_optional_time<R: Read>(reader: &mut R) -> io::Result<Option<NaiveDateTime>> {
+    match reader.read_u8()? {
+        0 => Ok(None),
+        _ => Ok(Some(read_time(reader)?)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{
@@ -162,6 +253,7 @@ mod tests {
         let node = Node {
             id: NodeID::new(BigUint::from(10u8)),
             address: "127.0.0.1:3000".parse()?,
+            first_seen: Utc::now().naive_utc(),
             last_request_to: Some(epoch),
             last_request_from: Some(Utc::now().naive_utc() - Duration::minutes(10)),
             failed_requests: 0,
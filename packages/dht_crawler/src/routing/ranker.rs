@@ -0,0 +1,26 @@
+use krpc_encoding::{
+    NodeID,
+    NodeInfo,
+};
+use num_bigint::BigUint;
+use std::ops::Deref;
+
+/// Orders nodes by how good a candidate they are for a lookup of a target.
+/// Nodes with lower ranks are preferred.
+pub trait NodeRanker {
+    type Rank: Ord;
+
+    fn rank(&self, target: &NodeID, node: &NodeInfo) -> Self::Rank;
+}
+
+/// Ranks nodes by XOR distance to the target, as in Kademlia.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XorDistance;
+
+impl NodeRanker for XorDistance {
+    type Rank = BigUint;
+
+    fn rank(&self, target: &NodeID, node: &NodeInfo) -> BigUint {
+        node.node_id.deref() ^ target.deref()
+    }
+}
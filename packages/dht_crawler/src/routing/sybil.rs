@@ -0,0 +1,225 @@
+use crate::routing::{
+    Node,
+    RoutingTable,
+};
+use chrono::NaiveDateTime;
+use krpc_encoding::NODE_ID_SIZE_BITS;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::Deref,
+};
+
+/// Number of leading id bits two nodes must share to count as clustered.
+/// Random ids share this many bits with vanishingly small probability at the
+/// size of a routing table.
+const CLUSTERED_PREFIX_BITS: usize = 32;
+
+/// Nodes from one subnet first seen at most this many seconds apart count as
+/// inserted together.
+const RAPID_INSERTION_WINDOW_SECS: i64 = 60;
+
+impl RoutingTable {
+    /// Heuristic score from 0 to 1 of how likely it is the table has been
+    /// filled by a sybil attack. Higher is more likely.
+    ///
+    /// The score is the mean of:
+    ///
+    /// * **Subnet concentration** - The fraction of nodes sharing their /24
+    ///   subnet with another node. Sybil nodes tend to be hosted on a few
+    ///   machines.
+    /// * **Id clustering** - The fraction of nodes sharing their first
+    ///   `CLUSTERED_PREFIX_BITS` bits of id with another node. Sybil nodes pick
+    ///   ids near a target to eclipse it, while honest ids are random.
+    /// * **Rapid insertion** - The fraction of nodes first seen within
+    ///   `RAPID_INSERTION_WINDOW_SECS` of another node from their /24 subnet.
+    ///   Sybil nodes are brought up in bulk, while honest neighbours join the
+    ///   network independently.
+    /// * **BEP 42 mismatch** - The fraction of nodes whose id isn't derived
+    ///   from their address as described in BEP 42. Sybil nodes choose their
+    ///   ids freely to place themselves near a target.
+    ///
+    /// Tables with fewer than two nodes score zero.
+    pub fn sybil_score(&self) -> f64 {
+        let len = self.len();
+        if len < 2 {
+            return 0.0;
+        }
+
+        let subnets = self.nodes().map(subnet_of);
+
+        let prefixes = self
+            .nodes()
+            .map(|node| node.id.deref() >> (NODE_ID_SIZE_BITS - CLUSTERED_PREFIX_BITS));
+
+        let mismatched = self
+            .nodes()
+            .filter(|node| !node.id.is_valid_for_ip(*node.address.ip()))
+            .count();
+
+        let subnet_concentration = shared_fraction(subnets, len);
+        let id_clustering = shared_fraction(prefixes, len);
+        let rapid_insertion = self.rapid_insertion_fraction(len);
+        let bep42_mismatch = mismatched as f64 / len as f64;
+
+        (subnet_concentration + id_clustering + rapid_insertion + bep42_mismatch) / 4.0
+    }
+
+    /// Fraction of `len` nodes first seen within `RAPID_INSERTION_WINDOW_SECS`
+    /// of another node from the same subnet.
+    fn rapid_insertion_fraction(&self, len: usize) -> f64 {
+        let mut first_seen_by_subnet: HashMap<[u8; 3], Vec<NaiveDateTime>> = HashMap::new();
+        for node in self.nodes() {
+            first_seen_by_subnet
+                .entry(subnet_of(node))
+                .or_insert_with(Vec::new)
+                .push(node.first_seen());
+        }
+
+        let mut rapid = 0;
+        for times in first_seen_by_subnet.values_mut() {
+            times.sort();
+
+            rapid += (0..times.len())
+                .filter(|&i| {
+                    let near = |other: usize| {
+                        times[i]
+                            .signed_duration_since(times[other])
+                            .num_seconds()
+                            .abs()
+                            <= RAPID_INSERTION_WINDOW_SECS
+                    };
+
+                    (i > 0 && near(i - 1)) || (i + 1 < times.len() && near(i + 1))
+                })
+                .count();
+        }
+
+        rapid as f64 / len as f64
+    }
+}
+
+/// The /24 subnet `node` is addressed in.
+fn subnet_of(node: &Node) -> [u8; 3] {
+    let octets = node.address.ip().octets();
+    [octets[0], octets[1], octets[2]]
+}
+
+/// Fraction of `len` items which are equal to at least one other item.
+fn shared_fraction<T: Hash + Eq>(items: impl Iterator<Item = T>, len: usize) -> f64 {
+    let mut counts: HashMap<T, usize> = HashMap::new();
+    for item in items {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+
+    let shared: usize = counts.values().filter(|count| **count > 1).sum();
+
+    shared as f64 / len as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routing::{
+        Node,
+        RoutingTable,
+    };
+    use chrono::{
+        Duration,
+        Utc,
+    };
+    use krpc_encoding::NodeID;
+    use num_bigint::BigUint;
+    use std::net::{
+        Ipv4Addr,
+        SocketAddrV4,
+    };
+
+    #[test]
+    fn sybil_table_scores_high() {
+        let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+        let prefix = BigUint::from(0xdeadbeefu32) << 128;
+
+        for i in 1..=8u8 {
+            let mut node = Node::new(
+                NodeID::new(&prefix + BigUint::from(i)),
+                SocketAddrV4::new(Ipv4Addr::new(203, 0, 113, i), 6881),
+            );
+            node.mark_successful_request();
+            table.add_node(node);
+        }
+
+        assert!(table.sybil_score() > 0.9);
+    }
+
+    #[test]
+    fn rapid_insertion_scores_higher() {
+        let table_seen_apart = |apart: Duration| {
+            let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+            let start = Utc::now().naive_utc();
+
+            for i in 0..8u8 {
+                let ip = Ipv4Addr::new(203, 0, 113, i + 1);
+                let mut node = Node::new(NodeID::from_ip_bep42(ip, i), SocketAddrV4::new(ip, 6881));
+                node.set_first_seen(start + apart * i32::from(i));
+                node.mark_successful_request();
+                table.add_node(node);
+            }
+
+            table
+        };
+
+        let rapid = table_seen_apart(Duration::seconds(1)).sybil_score();
+        let gradual = table_seen_apart(Duration::hours(1)).sybil_score();
+
+        assert_eq!(rapid, 0.5);
+        assert_eq!(gradual, 0.25);
+    }
+
+    #[test]
+    fn bep42_mismatch_scores_higher() {
+        let table_with_ids = |bep42: bool| {
+            let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+
+            for i in 0..8u8 {
+                let ip = Ipv4Addr::new(203, i, 113, 1);
+                let id = if bep42 {
+                    NodeID::from_ip_bep42(ip, i)
+                } else {
+                    NodeID::new(BigUint::from(i) << 156)
+                };
+                let mut node = Node::new(id, SocketAddrV4::new(ip, 6881));
+                node.mark_successful_request();
+                table.add_node(node);
+            }
+
+            table
+        };
+
+        assert_eq!(table_with_ids(true).sybil_score(), 0.0);
+        assert_eq!(table_with_ids(false).sybil_score(), 0.25);
+    }
+
+    #[test]
+    fn diverse_table_scores_low() {
+        let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+
+        for i in 0..8u8 {
+            let id = BigUint::from(i) << 156;
+            let mut node = Node::new(
+                NodeID::new(id),
+                SocketAddrV4::new(Ipv4Addr::new(10, i, 0, 1), 6881),
+            );
+            node.mark_successful_request();
+            table.add_node(node);
+        }
+
+        assert_eq!(table.sybil_score(), 0.0);
+    }
+
+    #[test]
+    fn empty_table_scores_zero() {
+        let table = RoutingTable::new(NodeID::random());
+
+        assert_eq!(table.sybil_score(), 0.0);
+    }
+}
@@ -1,6 +1,10 @@
 use crate::routing::{
     bucket::Bucket,
     node::Node,
+    ranker::{
+        NodeRanker,
+        XorDistance,
+    },
     token_validator::TokenValidator,
 };
 use krpc_encoding::{
@@ -9,9 +13,24 @@ use krpc_encoding::{
 };
 use std::{
     cmp,
+    collections::HashSet,
     net::SocketAddrV4,
+    ops::Deref,
 };
 
+/// How nodes handed out to other nodes are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionPolicy {
+    /// The nodes closest to the target. `find_node` responses hold the good
+    /// nodes of the target's bucket, like before policies were added.
+    Closest,
+
+    /// The nodes closest to the target, preferring nodes which were discovered
+    /// earlier among nodes sharing the same number of bits of distance to the
+    /// target. Long lived nodes are more likely to stay up.
+    ClosestPreferOld,
+}
+
 pub enum FindNodeResult {
     Node(NodeInfo),
     Nodes(Vec<NodeInfo>),
@@ -50,12 +69,14 @@ impl RoutingTable {
                 return;
             }
 
-            let (prev_bucket_idx, next_bucket_idx) = self.split_bucket(bucket_idx);
-
-            if self.buckets[prev_bucket_idx].could_hold_node(&node.id) {
-                prev_bucket_idx
-            } else {
-                next_bucket_idx
+            match self.split_bucket(bucket_idx) {
+                Some((prev_bucket_idx, _))
+                    if self.buckets[prev_bucket_idx].could_hold_node(&node.id) =>
+                {
+                    prev_bucket_idx
+                }
+                Some((_, next_bucket_idx)) => next_bucket_idx,
+                None => bucket_idx,
             }
         } else {
             bucket_idx
@@ -77,6 +98,20 @@ impl RoutingTable {
         }
     }
 
+    /// Finds the node with `id`, or the `k` good nodes across the entire table
+    /// ranked best by `ranker` if the exact node couldn't be found.
+    pub fn find_node_with<R: NodeRanker>(
+        &self,
+        id: &NodeID,
+        k: usize,
+        ranker: &R,
+    ) -> FindNodeResult {
+        match self.get_node(id) {
+            Some(node) => FindNodeResult::Node(node.into()),
+            None => FindNodeResult::Nodes(self.select_nodes_by(id, k, ranker)),
+        }
+    }
+
     /// Finds nodes in the same bucket as `id` in the routing table.
     pub fn find_nodes(&self, id: &NodeID) -> Vec<NodeInfo> {
         let bucket_idx = self.get_bucket_idx(id);
@@ -85,6 +120,110 @@ impl RoutingTable {
         bucket.good_nodes().map(|node| node.into()).collect()
     }
 
+    /// Finds the `k` good nodes closest to `target` by XOR distance across
+    /// the entire table, closest first.
+    pub fn closest_nodes(&self, target: &NodeID, k: usize) -> Vec<NodeInfo> {
+        self.select_nodes(target, k, SelectionPolicy::Closest)
+    }
+
+    /// Like [`RoutingTable::closest_nodes`] but skips nodes with ids in
+    /// `exclude`, such as nodes already queried during a lookup.
+    pub fn closest_excluding(
+        &self,
+        target: &NodeID,
+        exclude: &HashSet<NodeID>,
+        k: usize,
+    ) -> Vec<NodeInfo> {
+        let mut nodes = self
+            .buckets
+            .iter()
+            .flat_map(|bucket| bucket.good_nodes())
+            .filter(|node| !exclude.contains(&node.id))
+            .map(|node| node.into())
+            .collect::<Vec<NodeInfo>>();
+
+        nodes.sort_by_key(|node| XorDistance.rank(target, node));
+        nodes.truncate(k);
+
+        nodes
+    }
+
+    /// Finds the `k` questionable nodes closest to `target` by XOR distance
+    /// across the entire table, closest first.
+    pub fn closest_questionable(&self, target: &NodeID, k: usize) -> Vec<NodeInfo> {
+        let mut nodes = self
+            .buckets
+            .iter()
+            .flat_map(|bucket| bucket.questionable_nodes())
+            .map(|node| node.into())
+            .collect::<Vec<NodeInfo>>();
+
+        nodes.sort_by_key(|node| XorDistance.rank(target, node));
+        nodes.truncate(k);
+
+        nodes
+    }
+
+    /// Picks `k` good nodes near `target` according to `policy`, best first.
+    pub fn select_nodes(
+        &self,
+        target: &NodeID,
+        k: usize,
+        policy: SelectionPolicy,
+    ) -> Vec<NodeInfo> {
+        match policy {
+            SelectionPolicy::Closest => self.select_nodes_by(target, k, &XorDistance),
+            SelectionPolicy::ClosestPreferOld => {
+                let mut nodes = self
+                    .buckets
+                    .iter()
+                    .flat_map(|bucket| bucket.good_nodes())
+                    .collect::<Vec<&Node>>();
+
+                nodes.sort_by_key(|node| {
+                    let distance = node.id.deref() ^ target.deref();
+
+                    (distance.bits(), node.first_seen())
+                });
+
+                nodes.into_iter().take(k).map(|node| node.into()).collect()
+            }
+        }
+    }
+
+    /// Picks the `k` good nodes ranked best for `target` by `ranker`, best
+    /// first.
+    pub fn select_nodes_by<R: NodeRanker>(
+        &self,
+        target: &NodeID,
+        k: usize,
+        ranker: &R,
+    ) -> Vec<NodeInfo> {
+        let mut nodes = self
+            .buckets
+            .iter()
+            .flat_map(|bucket| bucket.good_nodes())
+            .map(|node| node.into())
+            .collect::<Vec<NodeInfo>>();
+
+        nodes.sort_by_key(|node| ranker.rank(target, node));
+        nodes.truncate(k);
+
+        nodes
+    }
+
+    /// Iterates over every node in the table regardless of state.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.buckets.iter().flat_map(|bucket| bucket.nodes.iter())
+    }
+
+    /// Picks up to `count` nodes in any state uniformly at random.
+    pub fn random_nodes(&self, count: usize) -> Vec<NodeInfo> {
+        let nodes = self.nodes().map(|node| node.into());
+
+        rand::seq::sample_iter(&mut rand::thread_rng(), nodes, count).unwrap_or_else(|all| all)
+    }
+
     /// Gets the node with `id` from the table.
     pub fn get_node(&self, id: &NodeID) -> Option<&Node> {
         let bucket_idx = self.get_bucket_idx(id);
@@ -106,17 +245,18 @@ impl RoutingTable {
             .expect("No bucket was found for NodeID.")
     }
 
-    /// Splits the bucket at `idx` into two buckets.
-    fn split_bucket(&mut self, idx: usize) -> (usize, usize) {
+    /// Splits the bucket at `idx` into two buckets. Returns `None` if the
+    /// bucket is too narrow to split.
+    fn split_bucket(&mut self, idx: usize) -> Option<(usize, usize)> {
         let next_bucket = {
             let bucket = &mut self.buckets[idx];
-            bucket.split()
+            bucket.split()?
         };
 
         let next_bucket_idx = idx + 1;
         self.buckets.insert(next_bucket_idx, next_bucket);
 
-        (idx, next_bucket_idx)
+        Some((idx, next_bucket_idx))
     }
 
     pub fn verify_token(&self, token: &[u8], addr: &SocketAddrV4) -> bool {
@@ -127,6 +267,14 @@ impl RoutingTable {
         self.token_validator.generate_token(addr)
     }
 
+    /// Replaces the validator used to generate and verify tokens. Tokens
+    /// handed out by the previous validator stop being valid.
+    pub fn set_token_validator(&mut self, token_validator: TokenValidator) {
+        self.token_validator = token_validator;
+    }
+
+    /// Rotates the token secret, so tokens generated before the previous
+    /// rotation stop being valid.
     pub fn update_token(&mut self) {
         self.token_validator.rotate_tokens();
     }
@@ -145,4 +293,191 @@ impl RoutingTable {
     pub fn len(&self) -> usize {
         self.buckets.iter().map(|bucket| bucket.nodes.len()).sum()
     }
+
+    /// Number of good nodes in the table.
+    pub fn good_len(&self) -> usize {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.good_nodes().count())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FindNodeResult,
+        RoutingTable,
+        SelectionPolicy,
+    };
+    use crate::routing::{
+        node::Node,
+        NodeRanker,
+    };
+    use chrono::{
+        Duration,
+        Utc,
+    };
+    use krpc_encoding::{
+        NodeID,
+        NodeInfo,
+    };
+    use num_bigint::BigUint;
+    use std::{
+        cmp::Reverse,
+        collections::HashSet,
+        ops::Deref,
+    };
+
+    /// Prefers the nodes furthest from the target.
+    struct Furthest;
+
+    impl NodeRanker for Furthest {
+        type Rank = Reverse<BigUint>;
+
+        fn rank(&self, target: &NodeID, node: &NodeInfo) -> Reverse<BigUint> {
+            Reverse(node.node_id.deref() ^ target.deref())
+        }
+    }
+
+    #[test]
+    fn closest_nodes() {
+        let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+
+        for id in 1..=16 {
+            let mut node = Node::new_with_id(id);
+            node.mark_successful_request();
+            table.add_node(node);
+        }
+
+        let closest = table
+            .closest_nodes(&NodeID::new(BigUint::from(5u8)), 3)
+            .into_iter()
+            .map(|node| node.node_id)
+            .collect::<Vec<NodeID>>();
+
+        assert_eq!(
+            closest,
+            vec![
+                NodeID::new(BigUint::from(5u8)),
+                NodeID::new(BigUint::from(4u8)),
+                NodeID::new(BigUint::from(7u8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn closest_nodes_across_buckets() {
+        let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+        let ids = NodeID::keyspace_sweep(32);
+
+        // Added in bit-reversed order so each bucket is split while both
+        // halves still have room, keeping every node.
+        for i in 0..32u8 {
+            let id = ids[(i.reverse_bits() >> 3) as usize].clone();
+            let mut node = Node::new(id, "127.0.0.1:3000".parse().unwrap());
+            node.mark_successful_request();
+            table.add_node(node);
+        }
+        assert_eq!(table.buckets.len(), 4);
+
+        let closest = table
+            .closest_nodes(&ids[0], 20)
+            .into_iter()
+            .map(|node| node.node_id)
+            .collect::<Vec<NodeID>>();
+
+        assert_eq!(closest, ids[..20].to_vec());
+    }
+
+    #[test]
+    fn closest_excluding() {
+        let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+
+        for id in 1..=16 {
+            let mut node = Node::new_with_id(id);
+            node.mark_successful_request();
+            table.add_node(node);
+        }
+
+        let exclude = [5u8, 4]
+            .iter()
+            .map(|id| NodeID::new(BigUint::from(*id)))
+            .collect::<HashSet<NodeID>>();
+
+        let closest = table
+            .closest_excluding(&NodeID::new(BigUint::from(5u8)), &exclude, 3)
+            .into_iter()
+            .map(|node| node.node_id)
+            .collect::<Vec<NodeID>>();
+
+        assert!(closest.iter().all(|id| !exclude.contains(id)));
+        assert_eq!(
+            closest,
+            vec![
+                NodeID::new(BigUint::from(7u8)),
+                NodeID::new(BigUint::from(6u8)),
+                NodeID::new(BigUint::from(1u8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn select_nodes_prefer_old() {
+        let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+        let now = Utc::now().naive_utc();
+
+        // 4 through 7 are all 3 bits away from the target, 7 is the oldest
+        for (id, age_minutes) in &[(1, 0), (4, 10), (5, 20), (6, 30), (7, 40)] {
+            let mut node = Node::new_with_id(*id);
+            node.set_first_seen(now - Duration::minutes(*age_minutes));
+            node.mark_successful_request();
+            table.add_node(node);
+        }
+
+        let select = |policy| {
+            table
+                .select_nodes(&NodeID::new(BigUint::from(0u8)), 3, policy)
+                .into_iter()
+                .map(|node| node.node_id)
+                .collect::<Vec<NodeID>>()
+        };
+
+        let ids = |ids: &[u8]| {
+            ids.iter()
+                .map(|id| NodeID::new(BigUint::from(*id)))
+                .collect::<Vec<NodeID>>()
+        };
+
+        assert_eq!(select(SelectionPolicy::Closest), ids(&[1, 4, 5]));
+        assert_eq!(select(SelectionPolicy::ClosestPreferOld), ids(&[1, 7, 6]));
+    }
+
+    #[test]
+    fn find_node_with_custom_ranker() {
+        let mut table = RoutingTable::new(NodeID::new(BigUint::from(0u8)));
+
+        for id in 1..=8 {
+            let mut node = Node::new_with_id(id);
+            node.mark_successful_request();
+            table.add_node(node);
+        }
+
+        let nodes = match table.find_node_with(&NodeID::new(BigUint::from(0u8)), 3, &Furthest) {
+            FindNodeResult::Nodes(nodes) => nodes,
+            FindNodeResult::Node(node) => panic!("unexpected exact match {:?}", node),
+        };
+
+        assert_eq!(
+            nodes
+                .into_iter()
+                .map(|node| node.node_id)
+                .collect::<Vec<NodeID>>(),
+            vec![
+                NodeID::new(BigUint::from(8u8)),
+                NodeID::new(BigUint::from(7u8)),
+                NodeID::new(BigUint::from(6u8)),
+            ]
+        );
+    }
 }
@@ -1,3 +1,7 @@
+use hmac::{
+    Hmac,
+    Mac,
+};
 use krpc_encoding as proto;
 use rand;
 use sha1::{
@@ -5,7 +9,23 @@ use sha1::{
     Digest,
     Sha1,
 };
-use std::net::SocketAddrV4;
+use std::{
+    net::SocketAddrV4,
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// Bytes of a timestamped token holding the time it was issued.
+const TIMESTAMP_LEN: usize = 4;
+
+/// Length of the secrets tokens are generated with. Long enough that the
+/// secret can't be brute forced from a token.
+const SECRET_LEN: usize = 20;
 
 /// Generates and validates tokens. A token generated with
 /// [`TokenValidator::generate_token`] is valid until
@@ -26,12 +46,22 @@ use std::net::SocketAddrV4;
 /// validator.rotate_tokens();
 /// assert_eq!(false, validator.verify_token(&addr, &token));
 /// ```
+///
+/// A validator created with [`TokenValidator::with_lifetime`] instead embeds
+/// the time each token was issued, so tokens expire after exactly the
+/// lifetime on top of becoming invalid after two rotations. These tokens are
+/// still 20 bytes: a big-endian `u32` of seconds since the unix epoch followed
+/// by the first 16 bytes of an HMAC-SHA1 over the address and the timestamp.
 pub struct TokenValidator {
     /// Secret used when generating tokens for `get_peers` and `announce_peer`.
-    token_secret: [u8; 4],
+    token_secret: [u8; SECRET_LEN],
 
     /// Last secret. Tokens generated with this secret are also valid.
-    last_token_secret: [u8; 4],
+    last_token_secret: [u8; SECRET_LEN],
+
+    /// How long timestamped tokens are valid for. `None` for tokens without a
+    /// timestamp.
+    lifetime: Option<Duration>,
 }
 
 impl TokenValidator {
@@ -39,29 +69,89 @@ impl TokenValidator {
         TokenValidator {
             token_secret: rand::random(),
             last_token_secret: rand::random(),
+            lifetime: None,
+        }
+    }
+
+    /// Creates a validator for timestamped tokens which are valid for
+    /// `lifetime` after being generated.
+    pub fn with_lifetime(lifetime: Duration) -> TokenValidator {
+        TokenValidator {
+            lifetime: Some(lifetime),
+            ..TokenValidator::new()
         }
     }
 
     /// Generates a token for `addr`. This token will be valid
     pub fn generate_token(&self, addr: &SocketAddrV4) -> [u8; 20] {
-        generate_token(addr, &self.token_secret)
+        self.generate_token_at(addr, unix_now())
     }
 
     pub fn verify_token(&self, addr: &SocketAddrV4, token: &[u8]) -> bool {
-        // This is vulnerable to a side-channel attack.
-        generate_token(addr, &self.token_secret) == token
-            || generate_token(addr, &self.last_token_secret) == token
+        self.verify_token_at(addr, token, unix_now())
+    }
+
+    fn generate_token_at(&self, addr: &SocketAddrV4, now: u32) -> [u8; 20] {
+        if self.lifetime.is_none() {
+            return generate_token(addr, &self.token_secret);
+        }
+
+        let mac = timestamped_mac(addr, now, &self.token_secret)
+            .finalize()
+            .into_bytes();
+        let mut token = [0u8; 20];
+        token[..TIMESTAMP_LEN].copy_from_slice(&now.to_be_bytes());
+        token[TIMESTAMP_LEN..].copy_from_slice(&mac[..20 - TIMESTAMP_LEN]);
+
+        token
+    }
+
+    fn verify_token_at(&self, addr: &SocketAddrV4, token: &[u8], now: u32) -> bool {
+        let lifetime = match self.lifetime {
+            // This is vulnerable to a side-channel attack.
+            None => {
+                return generate_token(addr, &self.token_secret) == token
+                    || generate_token(addr, &self.last_token_secret) == token
+            }
+            Some(lifetime) => lifetime,
+        };
+
+        if token.len() != 20 {
+            return false;
+        }
+
+        let (issued_at, tag) = token.split_at(TIMESTAMP_LEN);
+        let mut issued_at_bytes = [0u8; TIMESTAMP_LEN];
+        issued_at_bytes.copy_from_slice(issued_at);
+        let issued_at = u32::from_be_bytes(issued_at_bytes);
+
+        // Tokens from the future weren't issued by us.
+        let age = match now.checked_sub(issued_at) {
+            Some(age) => Duration::from_secs(u64::from(age)),
+            None => return false,
+        };
+        if age > lifetime {
+            return false;
+        }
+
+        [&self.token_secret, &self.last_token_secret]
+            .iter()
+            .any(|secret| {
+                timestamped_mac(addr, issued_at, secret)
+                    .verify_truncated_left(tag)
+                    .is_ok()
+            })
     }
 
     pub fn rotate_tokens(&mut self) {
-        let new_secret: [u8; 4] = rand::random();
+        let new_secret: [u8; SECRET_LEN] = rand::random();
         self.last_token_secret = self.token_secret;
         self.token_secret = new_secret;
     }
 }
 
 /// Generates a token given an address and secret.
-fn generate_token(addr: &SocketAddrV4, secret: &[u8; 4]) -> [u8; 20] {
+fn generate_token(addr: &SocketAddrV4, secret: &[u8; SECRET_LEN]) -> [u8; 20] {
     let mut hasher = Sha1::new();
 
     let addr_bytes = proto::addr_to_bytes(addr);
@@ -71,3 +161,83 @@ fn generate_token(addr: &SocketAddrV4, secret: &[u8; 4]) -> [u8; 20] {
 
     hasher.finalize_fixed().into()
 }
+
+/// Starts an HMAC over an address and the time a token for it was issued.
+fn timestamped_mac(addr: &SocketAddrV4, issued_at: u32, secret: &[u8; SECRET_LEN]) -> HmacSha1 {
+    let mut mac = HmacSha1::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(&proto::addr_to_bytes(addr));
+    mac.update(&issued_at.to_be_bytes());
+
+    mac
+}
+
+/// Seconds since the unix epoch.
+fn unix_now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenValidator;
+    use std::{
+        net::SocketAddrV4,
+        time::Duration,
+    };
+
+    type Error = Box<dyn std::error::Error>;
+
+    const ISSUED_AT: u32 = 1_600_000_000;
+
+    #[test]
+    fn timestamped_token_valid_within_lifetime() -> Result<(), Error> {
+        let validator = TokenValidator::with_lifetime(Duration::from_secs(600));
+        let addr: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+
+        let token = validator.generate_token_at(&addr, ISSUED_AT);
+
+        assert_eq!(&token[..4], &ISSUED_AT.to_be_bytes());
+        assert!(validator.verify_token_at(&addr, &token, ISSUED_AT));
+        assert!(validator.verify_token_at(&addr, &token, ISSUED_AT + 600));
+
+        Ok(())
+    }
+
+    #[test]
+    fn timestamped_token_expires() -> Result<(), Error> {
+        let validator = TokenValidator::with_lifetime(Duration::from_secs(600));
+        let addr: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+
+        let token = validator.generate_token_at(&addr, ISSUED_AT);
+
+        assert!(!validator.verify_token_at(&addr, &token, ISSUED_AT + 601));
+        assert!(!validator.verify_token_at(&addr, &token, ISSUED_AT - 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn forged_timestamped_token_rejected() -> Result<(), Error> {
+        let validator = TokenValidator::with_lifetime(Duration::from_secs(600));
+        let addr: SocketAddrV4 = "129.21.63.170:34238".parse()?;
+        let token = validator.generate_token_at(&addr, ISSUED_AT);
+
+        // pushing the timestamp forward to extend the token's life
+        let mut extended = token;
+        extended[..4].copy_from_slice(&(ISSUED_AT + 300).to_be_bytes());
+        assert!(!validator.verify_token_at(&addr, &extended, ISSUED_AT + 700));
+
+        // a token for another address
+        let other: SocketAddrV4 = "129.21.63.171:34238".parse()?;
+        assert!(!validator.verify_token_at(&other, &token, ISSUED_AT));
+
+        // a token from another validator
+        let forger = TokenValidator::with_lifetime(Duration::from_secs(600));
+        let forged = forger.generate_token_at(&addr, ISSUED_AT);
+        assert!(!validator.verify_token_at(&addr, &forged, ISSUED_AT));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use crate::errors::{
+    ErrorKind,
+    Result,
+};
+use krpc_encoding::NodeID;
+use std::collections::HashMap;
+
+/// A mutable item as defined in [BEP-0044].
+///
+/// Signatures are not verified by the store. Callers must verify `signature`
+/// against `key`, `salt`, `sequence` and `value` before calling
+/// [`MutableItemStore::put`].
+///
+/// [BEP-0044]: http://www.bittorrent.org/beps/bep_0044.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutableItem {
+    /// Bencoded value of the item.
+    pub value: Vec<u8>,
+
+    /// ed25519 public key of the item's owner.
+    pub key: Vec<u8>,
+
+    /// ed25519 signature over the salt, sequence number and value.
+    pub signature: Vec<u8>,
+
+    /// Salt mixed into the target. Allows a single key to own many items.
+    pub salt: Option<Vec<u8>>,
+
+    /// Monotonically increasing sequence number of the item.
+    pub sequence: i64,
+}
+
+/// Mutable items stored on this node keyed by target.
+pub struct MutableItemStore {
+    items: HashMap<NodeID, MutableItem>,
+}
+
+impl MutableItemStore {
+    pub fn new() -> MutableItemStore {
+        MutableItemStore {
+            items: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, target: &NodeID) -> Option<&MutableItem> {
+        self.items.get(target)
+    }
+
+    /// Stores `item` under `target`, replacing the existing item.
+    ///
+    /// # Errors
+    ///
+    /// * [`ErrorKind::CasMismatch`] if `cas` is provided and doesn't match the
+    ///   sequence number of the stored item.
+    /// * [`ErrorKind::SequenceNumberLessThanCurrent`] if the sequence number of
+    ///   `item` isn't greater than the sequence number of the stored item.
+    ///   Re-putting the stored item with the same sequence number and value is
+    ///   allowed.
+    pub fn put(&mut self, target: NodeID, item: MutableItem, cas: Option<i64>) -> Result<()> {
+        if let Some(current) = self.items.get(&target) {
+            if let Some(expected_sequence) = cas {
+                if expected_sequence != current.sequence {
+                    return Err(ErrorKind::CasMismatch)?;
+                }
+            }
+
+            let is_refresh = item.sequence == current.sequence && item.value == current.value;
+            if item.sequence <= current.sequence && !is_refresh {
+                return Err(ErrorKind::SequenceNumberLessThanCurrent)?;
+            }
+        }
+
+        self.items.insert(target, item);
+
+        Ok(())
+    }
+}
+
+impl Default for MutableItemStore {
+    fn default() -> MutableItemStore {
+        MutableItemStore::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MutableItem,
+        MutableItemStore,
+    };
+    use crate::errors::ErrorKind;
+    use krpc_encoding::NodeID;
+
+    fn make_item(sequence: i64, value: &[u8]) -> MutableItem {
+        MutableItem {
+            value: value.to_vec(),
+            key: vec![0u8; 32],
+            signature: vec![0u8; 64],
+            salt: None,
+            sequence,
+        }
+    }
+
+    fn make_store(target: &NodeID) -> MutableItemStore {
+        let mut store = MutableItemStore::new();
+        store
+            .put(target.clone(), make_item(4, b"12:Hello World!"), None)
+            .unwrap();
+
+        store
+    }
+
+    #[test]
+    fn put_greater_sequence() {
+        let target = NodeID::random();
+        let mut store = make_store(&target);
+
+        store
+            .put(target.clone(), make_item(5, b"3:foo"), None)
+            .unwrap();
+
+        assert_eq!(store.get(&target).map(|item| item.sequence), Some(5));
+    }
+
+    #[test]
+    fn reject_lower_sequence() {
+        let target = NodeID::random();
+        let mut store = make_store(&target);
+
+        let err = store
+            .put(target.clone(), make_item(3, b"3:foo"), None)
+            .unwrap_err();
+
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::SequenceNumberLessThanCurrent
+        ));
+        assert_eq!(err.as_request_error().code(), 302);
+        assert_eq!(store.get(&target).map(|item| item.sequence), Some(4));
+    }
+
+    #[test]
+    fn reject_equal_sequence_different_value() {
+        let target = NodeID::random();
+        let mut store = make_store(&target);
+
+        let err = store
+            .put(target.clone(), make_item(4, b"3:foo"), None)
+            .unwrap_err();
+
+        assert_eq!(err.as_request_error().code(), 302);
+    }
+
+    #[test]
+    fn allow_equal_sequence_same_value() {
+        let target = NodeID::random();
+        let mut store = make_store(&target);
+
+        store
+            .put(target.clone(), make_item(4, b"12:Hello World!"), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn reject_cas_mismatch() {
+        let target = NodeID::random();
+        let mut store = make_store(&target);
+
+        let err = store
+            .put(target.clone(), make_item(5, b"3:foo"), Some(3))
+            .unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::CasMismatch));
+        assert_eq!(err.as_request_error().code(), 301);
+        assert_eq!(store.get(&target).map(|item| item.sequence), Some(4));
+    }
+
+    #[test]
+    fn accept_cas_match() {
+        let target = NodeID::random();
+        let mut store = make_store(&target);
+
+        store
+            .put(target.clone(), make_item(5, b"3:foo"), Some(4))
+            .unwrap();
+
+        assert_eq!(store.get(&target).map(|item| item.sequence), Some(5));
+    }
+}
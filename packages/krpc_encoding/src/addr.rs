@@ -1,3 +1,7 @@
+use crate::errors::{
+    ErrorKind,
+    Result as EncodingResult,
+};
 use byteorder::{
     NetworkEndian,
     ReadBytesExt,
@@ -17,7 +21,9 @@ use std::{
     fmt,
     net::{
         Ipv4Addr,
+        Ipv6Addr,
         SocketAddrV4,
+        SocketAddrV6,
     },
     ops::Deref,
     str::FromStr,
@@ -70,6 +76,16 @@ pub fn write_to(addr: &SocketAddrV4, raw: &mut [u8]) {
         .expect("Failed to encode port.");
 }
 
+pub fn write_v6_to(addr: &SocketAddrV6, raw: &mut [u8]) {
+    let ip = addr.ip();
+    let port = addr.port();
+
+    raw[..16].clone_from_slice(&ip.octets());
+    (&mut raw[16..])
+        .write_u16::<NetworkEndian>(port)
+        .expect("Failed to encode port.");
+}
+
 /// Encode `addr` with the "Compact IP-address/port info" format
 pub fn to_bytes(addr: &SocketAddrV4) -> [u8; 6] {
     let mut raw = [0u8; 6];
@@ -85,6 +101,77 @@ pub fn from_bytes(v: &[u8]) -> SocketAddrV4 {
     SocketAddrV4::new(ip, port)
 }
 
+pub fn from_v6_bytes(v: &[u8]) -> SocketAddrV6 {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&v[..16]);
+    let port = (&v[16..]).read_u16::<NetworkEndian>().unwrap();
+
+    SocketAddrV6::new(Ipv6Addr::from(octets), port, 0, 0)
+}
+
+/// Length of a compact IPv4 address and port.
+const COMPACT_V4_LEN: usize = 6;
+
+/// Length of a compact IPv6 address and port.
+const COMPACT_V6_LEN: usize = 18;
+
+/// Encodes `addrs` back to back in the "Compact IP-address/port info" format,
+/// the format of the peers in the `values` of a `get_peers` response.
+pub fn encode_compact(addrs: &[Addr]) -> Vec<u8> {
+    let mut bytes = vec![0u8; addrs.len() * COMPACT_V4_LEN];
+    for (addr, raw) in addrs.iter().zip(bytes.chunks_exact_mut(COMPACT_V4_LEN)) {
+        write_to(addr, raw);
+    }
+
+    bytes
+}
+
+/// Decodes addresses written by [`encode_compact`]. Fails if `bytes` ends with
+/// a partial address.
+pub fn decode_compact(bytes: &[u8]) -> EncodingResult<Vec<Addr>> {
+    check_compact_len(bytes, COMPACT_V4_LEN)?;
+
+    Ok(bytes
+        .chunks_exact(COMPACT_V4_LEN)
+        .map(|raw| Addr(from_bytes(raw)))
+        .collect())
+}
+
+/// Like [`encode_compact`] but for IPv6 peers, 18 bytes each, as described in
+/// [BEP-0032].
+///
+/// [BEP-0032]: http://www.bittorrent.org/beps/bep_0032.html
+pub fn encode_compact_v6(addrs: &[SocketAddrV6]) -> Vec<u8> {
+    let mut bytes = vec![0u8; addrs.len() * COMPACT_V6_LEN];
+    for (addr, raw) in addrs.iter().zip(bytes.chunks_exact_mut(COMPACT_V6_LEN)) {
+        write_v6_to(addr, raw);
+    }
+
+    bytes
+}
+
+/// Decodes addresses written by [`encode_compact_v6`]. Fails if `bytes` ends
+/// with a partial address.
+pub fn decode_compact_v6(bytes: &[u8]) -> EncodingResult<Vec<SocketAddrV6>> {
+    check_compact_len(bytes, COMPACT_V6_LEN)?;
+
+    Ok(bytes
+        .chunks_exact(COMPACT_V6_LEN)
+        .map(from_v6_bytes)
+        .collect())
+}
+
+fn check_compact_len(bytes: &[u8], record_len: usize) -> EncodingResult<()> {
+    if bytes.len() % record_len != 0 {
+        return Err(ErrorKind::InvalidCompactLength {
+            len: bytes.len(),
+            record_len,
+        })?;
+    }
+
+    Ok(())
+}
+
 impl Serialize for Addr {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -127,7 +214,13 @@ impl<'de> Visitor<'de> for NodeInfoVisitor {
 
 #[cfg(test)]
 mod tests {
-    use super::Addr;
+    use super::{
+        decode_compact,
+        decode_compact_v6,
+        encode_compact,
+        encode_compact_v6,
+        Addr,
+    };
     use serde_test::{
         assert_tokens,
         Token,
@@ -135,7 +228,9 @@ mod tests {
     use std::net::{
         Ipv4Addr,
         SocketAddrV4,
+        SocketAddrV6,
     };
+    type Error = Box<dyn std::error::Error>;
 
     #[test]
     fn serde() {
@@ -148,4 +243,42 @@ mod tests {
             &[Token::Bytes(&[129, 21, 60, 66, 0x2e, 0xf3])],
         );
     }
+
+    #[test]
+    fn compact_round_trip() -> Result<(), Error> {
+        let addrs = vec![
+            "129.21.60.66:12019".parse::<Addr>()?,
+            "10.0.0.1:6881".parse::<Addr>()?,
+        ];
+
+        let encoded = encode_compact(&addrs);
+        assert_eq!(
+            encoded,
+            vec![129, 21, 60, 66, 0x2e, 0xf3, 10, 0, 0, 1, 0x1a, 0xe1]
+        );
+        assert_eq!(decode_compact(&encoded)?, addrs);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compact_v6_round_trip() -> Result<(), Error> {
+        let addrs = vec![
+            "[2001:db8::1]:6881".parse::<SocketAddrV6>()?,
+            "[::1]:1".parse::<SocketAddrV6>()?,
+        ];
+
+        let encoded = encode_compact_v6(&addrs);
+        assert_eq!(encoded.len(), 36);
+        assert_eq!(decode_compact_v6(&encoded)?, addrs);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compact_trailing_partial_record_rejected() {
+        assert!(decode_compact(&[10, 0, 0, 1, 0x1a, 0xe1, 10]).is_err());
+        assert!(decode_compact_v6(&[0u8; 20]).is_err());
+        assert_eq!(decode_compact(&[]).unwrap(), Vec::new());
+    }
 }
@@ -0,0 +1,71 @@
+/// Clients identified by the first two bytes of the `v` key of messages they
+/// send. Add entries here to recognize more clients.
+const KNOWN_CLIENTS: &[(&[u8; 2], &str)] = &[
+    (b"UT", "uTorrent"),
+    (b"LT", "libtorrent"),
+    (b"lt", "libtorrent (Rasterbar)"),
+    (b"TR", "Transmission"),
+    (b"AZ", "Azureus"),
+    (b"KT", "KTorrent"),
+    (b"DE", "Deluge"),
+    (b"qB", "qBittorrent"),
+];
+
+/// Decodes the client version sent in the `v` key of a message into the
+/// client's name and version number.
+///
+/// By convention the version is two bytes identifying the client followed by
+/// a two byte big endian version number. Returns `None` when the version isn't
+/// four bytes long or the client isn't one of the known clients.
+///
+/// ```
+/// use krpc_encoding::decode_client_version;
+///
+/// assert_eq!(
+///     decode_client_version(b"UT\x01\x02"),
+///     Some(("uTorrent".to_string(), 0x0102))
+/// );
+/// ```
+pub fn decode_client_version(v: &[u8]) -> Option<(String, u16)> {
+    if v.len() != 4 {
+        return None;
+    }
+
+    let (client, version) = v.split_at(2);
+    let (_, name) = KNOWN_CLIENTS
+        .iter()
+        .find(|(prefix, _)| &prefix[..] == client)?;
+
+    Some((
+        name.to_string(),
+        u16::from_be_bytes([version[0], version[1]]),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_client_version;
+
+    #[test]
+    fn known_clients() {
+        assert_eq!(
+            decode_client_version(b"UT\x00\xb4"),
+            Some(("uTorrent".to_string(), 180))
+        );
+        assert_eq!(
+            decode_client_version(b"LT\x01\x02"),
+            Some(("libtorrent".to_string(), 258))
+        );
+    }
+
+    #[test]
+    fn unknown_client() {
+        assert_eq!(decode_client_version(b"ZZ\x00\x01"), None);
+    }
+
+    #[test]
+    fn wrong_length() {
+        assert_eq!(decode_client_version(b"UT"), None);
+        assert_eq!(decode_client_version(b"UT\x00\x01\x02"), None);
+    }
+}
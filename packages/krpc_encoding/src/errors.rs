@@ -15,6 +15,18 @@ pub enum ErrorKind {
         #[source]
         cause: BencodeError,
     },
+
+    #[error("expected a 20 byte node id, got {} bytes", len)]
+    InvalidNodeIDLength { len: usize },
+
+    #[error("{len} bytes isn't a whole number of {record_len} byte compact addresses")]
+    InvalidCompactLength { len: usize, record_len: usize },
+
+    #[error("node id isn't valid hex")]
+    InvalidNodeIDHex {
+        #[source]
+        cause: hex::FromHexError,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
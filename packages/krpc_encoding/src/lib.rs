@@ -60,18 +60,28 @@
 //!
 //! [BEP-0005]: https://www.bittorrent.org/beps/bep_0005.html
 
-mod addr;
+pub mod addr;
 mod booleans;
+mod client_version;
 pub mod errors;
 mod messages;
 mod node_id;
+mod node_ids;
 mod node_info;
+mod optional_bytes;
+mod port_type;
+
+/// Any bencoded value, such as the value of a [BEP-0044] item.
+///
+/// [BEP-0044]: http://www.bittorrent.org/beps/bep_0044.html
+pub use serde_bencode::value::Value;
 
 pub use self::{
     addr::{
         to_bytes as addr_to_bytes,
         Addr,
     },
+    client_version::decode_client_version,
     messages::{
         Envelope,
         KRPCError,
@@ -84,4 +94,5 @@ pub use self::{
         NODE_ID_SIZE_BITS,
     },
     node_info::NodeInfo,
+    port_type::PortType,
 };
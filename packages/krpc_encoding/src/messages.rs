@@ -4,12 +4,18 @@ use crate::{
         ErrorKind,
         Result,
     },
+    node_ids,
     node_info,
+    optional_bytes,
     Addr,
     NodeID,
     NodeInfo,
+    PortType,
+};
+use serde_bencode::{
+    self,
+    value::Value,
 };
-use serde_bencode;
 use serde_bytes::{
     self,
     ByteBuf,
@@ -89,12 +95,20 @@ pub enum Message {
 
 /// Error sent when a query cannot be fulfilled
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
-pub struct KRPCError(u8, String);
+pub struct KRPCError(u16, String);
 
 impl KRPCError {
-    pub fn new(error_code: u8, message: &str) -> KRPCError {
+    pub fn new(error_code: u16, message: &str) -> KRPCError {
         KRPCError(error_code, message.to_string())
     }
+
+    pub fn code(&self) -> u16 {
+        self.0
+    }
+
+    pub fn message(&self) -> &str {
+        &self.1
+    }
 }
 
 impl fmt::Display for KRPCError {
@@ -162,25 +176,24 @@ pub enum Query {
         /// Node ID of the querying node
         id: NodeID,
 
-        /// Whether or not the peer's port is implied by the source port of the
-        /// UDP packet containing this query
-        ///
-        /// If `true`, the value of `port` should be ignored. This is useful for
-        /// peers behind a NAT that may not know their external port, and
-        /// supporting uTP, they accept incoming connections on the same port as
-        /// the DHT port.
-        #[serde(deserialize_with = "booleans::deserialize")]
-        implied_port: bool,
-
-        /// Peer's port
-        port: Option<u16>,
+        /// Port the peer is listening on
+        #[serde(flatten)]
+        port: PortType,
 
         /// Infohash of the torrent being announced
         info_hash: NodeID,
 
         /// Token received in response to a previous [Query::GetPeers]
-        #[serde(with = "serde_bytes")]
-        token: Vec<u8>,
+        ///
+        /// Required by the protocol but some clients omit it. Decoding is
+        /// lenient so the queried node can respond with an error instead of
+        /// dropping the query.
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_bytes"
+        )]
+        token: Option<Vec<u8>>,
     },
 
     /// `sample_infohashes` query from [BEP-0051]
@@ -192,14 +205,137 @@ pub enum Query {
         id: NodeID,
         target: NodeID,
     },
+
+    /// Get an item stored with [`Query::Put`], from [BEP-0044].
+    ///
+    /// Nodes storing the item respond with [`Response::GetData`]. Other nodes
+    /// respond with [`Response::NextHop`] with the nodes closest to `target`.
+    /// Both include a token for a future [`Query::Put`].
+    ///
+    /// [BEP-0044]: http://www.bittorrent.org/beps/bep_0044.html
+    #[serde(rename = "get")]
+    Get {
+        /// Node ID of the querying node
+        id: NodeID,
+
+        /// SHA-1 hash of the value for immutable items, or of the public key
+        /// and salt for mutable items.
+        target: NodeID,
+    },
+
+    /// Store an item on the queried node, from [BEP-0044].
+    ///
+    /// Immutable items only carry `v`. Mutable items also carry `k`, `sig`
+    /// and `seq`, and optionally `salt` and `cas`.
+    ///
+    /// [BEP-0044]: http://www.bittorrent.org/beps/bep_0044.html
+    #[serde(rename = "put")]
+    Put {
+        /// Node ID of the querying node
+        id: NodeID,
+
+        /// Token received in response to a previous [`Query::Get`]
+        #[serde(with = "serde_bytes")]
+        token: Vec<u8>,
+
+        /// Value being stored. Any bencoded value under 1000 bytes encoded.
+        #[serde(rename = "v")]
+        v: Value,
+
+        /// ed25519 public key of the item's owner. Only for mutable items.
+        #[serde(
+            rename = "k",
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_bytes"
+        )]
+        k: Option<Vec<u8>>,
+
+        /// ed25519 signature over `salt`, `seq` and `v`. Only for mutable
+        /// items.
+        #[serde(
+            rename = "sig",
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_bytes"
+        )]
+        sig: Option<Vec<u8>>,
+
+        /// Appended to `k` when computing the target, allowing a key to own
+        /// many items.
+        #[serde(
+            rename = "salt",
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_bytes"
+        )]
+        salt: Option<Vec<u8>>,
+
+        /// Sequence number of a mutable item. Must increase with each put.
+        #[serde(rename = "seq", default, skip_serializing_if = "Option::is_none")]
+        seq: Option<i64>,
+
+        /// Sequence number the stored item must have for the put to succeed.
+        #[serde(rename = "cas", default, skip_serializing_if = "Option::is_none")]
+        cas: Option<i64>,
+    },
 }
 
 /// Possible responses
 ///
 /// See [`Query`] to understand when each variant is used.
+///
+/// Deserialized by looking at which fields are present rather than trying each
+/// variant in turn, as a response to `find_node` from an IPv6 node may only
+/// have `nodes6`.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
-#[serde(untagged)]
+#[serde(untagged, from = "RawResponse")]
 pub enum Response {
+    /// Response to [`Query::Get`] from a node storing the item.
+    GetData {
+        /// Identifier of queried node
+        id: NodeID,
+
+        /// Token used in [`Query::Put`]
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_bytes"
+        )]
+        token: Option<Vec<u8>>,
+
+        /// Nodes close to the target
+        #[serde(default, with = "node_info")]
+        nodes: Vec<NodeInfo>,
+
+        /// Stored value
+        #[serde(rename = "v")]
+        v: Value,
+
+        /// ed25519 public key of the item's owner. Only for mutable items.
+        #[serde(
+            rename = "k",
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_bytes"
+        )]
+        k: Option<Vec<u8>>,
+
+        /// ed25519 signature over the salt, `seq` and `v`. Only for mutable
+        /// items.
+        #[serde(
+            rename = "sig",
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_bytes"
+        )]
+        sig: Option<Vec<u8>>,
+
+        /// Sequence number of a mutable item
+        #[serde(rename = "seq", default, skip_serializing_if = "Option::is_none")]
+        seq: Option<i64>,
+    },
+
     NextHop {
         /// Identifier of queried node
         id: NodeID,
@@ -212,8 +348,15 @@ pub enum Response {
         /// [BEP-0042]: http://www.bittorrent.org/beps/bep_0042.html
         token: Option<Vec<u8>>,
 
+        /// IPv4 nodes close to the target
         #[serde(with = "node_info")]
         nodes: Vec<NodeInfo>,
+
+        /// IPv6 nodes close to the target, from [BEP-0032]
+        ///
+        /// [BEP-0032]: http://www.bittorrent.org/beps/bep_0032.html
+        #[serde(default, skip_serializing_if = "Vec::is_empty", with = "node_info::v6")]
+        nodes6: Vec<NodeInfo>,
     },
 
     GetPeers {
@@ -244,6 +387,7 @@ pub enum Response {
         id: NodeID,
 
         /// Number of seconds this node should not be queried again for
+        #[serde(skip_serializing_if = "Option::is_none")]
         interval: Option<u16>,
 
         /// Nodes close to target in request
@@ -251,9 +395,107 @@ pub enum Response {
         nodes: Vec<NodeInfo>,
 
         /// Number of info hashes this peer has
+        #[serde(skip_serializing_if = "Option::is_none")]
         num: Option<u32>,
 
-        /// Sample of info-hashes
+        /// Sample of info-hashes, packed into a single byte string
+        #[serde(with = "node_ids")]
         samples: Vec<NodeID>,
     },
 }
+
+/// Every field a [`Response`] may have. Responses are classified by the most
+/// specific field present rather than by trying each variant in turn.
+#[derive(Deserialize)]
+struct RawResponse {
+    id: NodeID,
+
+    #[serde(default, deserialize_with = "optional_bytes::deserialize")]
+    token: Option<Vec<u8>>,
+
+    #[serde(default, deserialize_with = "node_info::deserialize_some")]
+    nodes: Option<Vec<NodeInfo>>,
+
+    #[serde(default, deserialize_with = "node_info::v6::deserialize_some")]
+    nodes6: Option<Vec<NodeInfo>>,
+
+    #[serde(default)]
+    values: Option<Vec<Addr>>,
+
+    #[serde(default)]
+    v: Option<Value>,
+
+    #[serde(default, deserialize_with = "optional_bytes::deserialize")]
+    k: Option<Vec<u8>>,
+
+    #[serde(default, deserialize_with = "optional_bytes::deserialize")]
+    sig: Option<Vec<u8>>,
+
+    #[serde(default)]
+    seq: Option<i64>,
+
+    #[serde(default)]
+    interval: Option<u16>,
+
+    #[serde(default)]
+    num: Option<u32>,
+
+    #[serde(default, deserialize_with = "node_ids::deserialize_some")]
+    samples: Option<Vec<NodeID>>,
+}
+
+impl From<RawResponse> for Response {
+    fn from(raw: RawResponse) -> Response {
+        let RawResponse {
+            id,
+            token,
+            nodes,
+            nodes6,
+            values,
+            v,
+            k,
+            sig,
+            seq,
+            interval,
+            num,
+            samples,
+        } = raw;
+
+        if let Some(v) = v {
+            return Response::GetData {
+                id,
+                token,
+                nodes: nodes.unwrap_or_default(),
+                v,
+                k,
+                sig,
+                seq,
+            };
+        }
+
+        if let Some(samples) = samples {
+            return Response::Samples {
+                id,
+                interval,
+                nodes: nodes.unwrap_or_default(),
+                num,
+                samples,
+            };
+        }
+
+        if nodes.is_some() || nodes6.is_some() {
+            return Response::NextHop {
+                id,
+                token,
+                nodes: nodes.unwrap_or_default(),
+                nodes6: nodes6.unwrap_or_default(),
+            };
+        }
+
+        if let Some(peers) = values {
+            return Response::GetPeers { id, token, peers };
+        }
+
+        Response::OnlyID { id }
+    }
+}
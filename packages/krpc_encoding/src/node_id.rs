@@ -1,3 +1,8 @@
+use crate::errors::{
+    Error,
+    ErrorKind,
+    Result as EncodingResult,
+};
 use hex;
 use num_bigint::BigUint;
 use num_traits::One;
@@ -13,7 +18,9 @@ use serde::{
     Serializer,
 };
 use std::{
+    convert::TryFrom,
     fmt,
+    net::Ipv4Addr,
     ops::Deref,
 };
 
@@ -23,6 +30,11 @@ pub struct NodeID(BigUint);
 
 pub const NODE_ID_SIZE_BITS: usize = 20 * 8;
 
+/// Bits of each byte of an IPv4 address which go into a [BEP-0042] node id.
+///
+/// [BEP-0042]: http://www.bittorrent.org/beps/bep_0042.html
+const BEP42_IPV4_MASK: [u8; 4] = [0x03, 0x0f, 0x3f, 0xff];
+
 impl NodeID {
     pub fn new(id: BigUint) -> NodeID {
         NodeID(id)
@@ -36,13 +48,90 @@ impl NodeID {
         NodeID(BigUint::from_bytes_be(bytes))
     }
 
-    pub fn from_hex(bytes: &[u8; 40]) -> NodeID {
-        let raw: &[u8] = bytes;
-        let bytes = hex::decode(raw).unwrap();
+    /// Like [`NodeID::from_bytes`] but fails unless `bytes` is exactly 20
+    /// bytes long. Use for ids and info hashes from untrusted input.
+    pub fn try_from_bytes(bytes: &[u8]) -> EncodingResult<NodeID> {
+        if bytes.len() != 20 {
+            return Err(ErrorKind::InvalidNodeIDLength { len: bytes.len() })?;
+        }
+
+        Ok(NodeID::from_bytes(bytes))
+    }
+
+    /// Returns `count` ids evenly spaced across the keyspace, starting at
+    /// zero and in increasing order. Useful as lookup targets to visit every
+    /// part of the keyspace.
+    pub fn keyspace_sweep(count: usize) -> Vec<NodeID> {
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let step = (BigUint::one() << NODE_ID_SIZE_BITS) / BigUint::from(count);
+
+        (0..count)
+            .map(|i| NodeID::new(&step * BigUint::from(i)))
+            .collect()
+    }
+
+    /// Parses 40 hex digits. Fails on anything which doesn't decode to
+    /// exactly 20 bytes.
+    pub fn from_hex(hex: &[u8]) -> EncodingResult<NodeID> {
+        let bytes = hex::decode(hex).map_err(|cause| ErrorKind::InvalidNodeIDHex { cause })?;
+
+        NodeID::try_from_bytes(&bytes)
+    }
+
+    /// Generates an id for a node reachable at `ip` as described by
+    /// [BEP-0042]. The top 21 bits are derived from `ip` and the low three
+    /// bits of `rand`, the last byte is `rand` and everything else is random.
+    /// Nodes enforcing BEP-0042 prefer ids generated this way.
+    ///
+    /// [BEP-0042]: http://www.bittorrent.org/beps/bep_0042.html
+    pub fn from_ip_bep42(ip: Ipv4Addr, rand: u8) -> NodeID {
+        let crc = bep42_crc(ip, rand);
+        let mut bytes = rand::random::<[u8; 20]>();
+        bytes[0] = (crc >> 24) as u8;
+        bytes[1] = (crc >> 16) as u8;
+        bytes[2] = ((crc >> 8) as u8 & 0xf8) | (bytes[2] & 0x07);
+        bytes[19] = rand;
 
         NodeID::from_bytes(&bytes)
     }
 
+    /// Returns true if the id could have been generated by
+    /// [`NodeID::from_ip_bep42`] for `ip`. Addresses on local networks are
+    /// exempt from BEP-0042 and accept any id.
+    pub fn is_valid_for_ip(&self, ip: Ipv4Addr) -> bool {
+        if ip.is_private() || ip.is_loopback() || ip.is_link_local() {
+            return true;
+        }
+
+        let bytes = match self.to_bytes_be_padded() {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+
+        let crc = bep42_crc(ip, bytes[19]);
+
+        bytes[0] == (crc >> 24) as u8
+            && bytes[1] == (crc >> 16) as u8
+            && bytes[2] & 0xf8 == (crc >> 8) as u8 & 0xf8
+    }
+
+    /// Big endian bytes of the id, left padded to 20 bytes. `None` if the id
+    /// doesn't fit.
+    fn to_bytes_be_padded(&self) -> Option<[u8; 20]> {
+        let bytes = self.0.to_bytes_be();
+        if bytes.len() > 20 {
+            return None;
+        }
+
+        let mut output = [0u8; 20];
+        output[20 - bytes.len()..].copy_from_slice(&bytes);
+
+        Some(output)
+    }
+
     pub fn as_bytes(&self) -> [u8; 20] {
         let mut bytes = self.0.to_bytes_be();
         bytes.resize(20, 0);
@@ -52,6 +141,23 @@ impl NodeID {
         output
     }
 
+    /// Kademlia distance between two ids, the bitwise XOR of the ids.
+    pub fn distance(&self, other: &NodeID) -> NodeID {
+        NodeID(&self.0 ^ &other.0)
+    }
+
+    /// Number of leading zero bits in the 160 bit id. For a distance from our
+    /// own id, this is the index of the bucket the other id falls into.
+    pub fn leading_zeros(&self) -> usize {
+        NODE_ID_SIZE_BITS.saturating_sub(self.bits())
+    }
+
+    /// Returns true if the id fits in 20 bytes. Ids built from longer byte
+    /// strings or large numbers can't be encoded.
+    pub fn has_valid_length(&self) -> bool {
+        self.bits() <= NODE_ID_SIZE_BITS
+    }
+
     /// Returns true if the value of the nth bit is 1. The 0th bit is the most
     /// significant bit.
     pub fn nth_bit(&self, n: usize) -> bool {
@@ -133,9 +239,21 @@ impl<'a> From<&'a [u8; 20]> for NodeID {
     }
 }
 
-impl<'a> From<&'a [u8; 40]> for NodeID {
-    fn from(bytes: &[u8; 40]) -> Self {
-        NodeID::from_hex(bytes)
+impl<'a> TryFrom<&'a [u8; 40]> for NodeID {
+    type Error = Error;
+
+    /// Parses 40 hex digits, see [`NodeID::from_hex`].
+    fn try_from(hex: &[u8; 40]) -> EncodingResult<NodeID> {
+        NodeID::from_hex(hex)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for NodeID {
+    type Error = Error;
+
+    /// Fails unless `bytes` is exactly 20 bytes long.
+    fn try_from(bytes: &[u8]) -> EncodingResult<NodeID> {
+        NodeID::try_from_bytes(bytes)
     }
 }
 
@@ -145,10 +263,48 @@ impl From<[u8; 20]> for NodeID {
     }
 }
 
+/// CRC32C of the masked `ip` with the low three bits of `rand` in the top
+/// bits, the value [BEP-0042] node ids are derived from.
+///
+/// [BEP-0042]: http://www.bittorrent.org/beps/bep_0042.html
+fn bep42_crc(ip: Ipv4Addr, rand: u8) -> u32 {
+    let mut masked = ip.octets();
+    for (byte, mask) in masked.iter_mut().zip(BEP42_IPV4_MASK.iter()) {
+        *byte &= mask;
+    }
+    masked[0] |= (rand & 0x07) << 5;
+
+    crc32c(&masked)
+}
+
+/// CRC-32 with the Castagnoli polynomial.
+fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
 #[cfg(test)]
 mod tests {
     use super::NodeID;
     use num_bigint::BigUint;
+    use std::{
+        convert::TryFrom,
+        net::Ipv4Addr,
+        ops::Deref,
+    };
+
+    type Error = Box<dyn std::error::Error>;
 
     #[test]
     fn as_bytes() {
@@ -161,11 +317,156 @@ mod tests {
     }
 
     #[test]
-    fn first_bit() {
+    fn keyspace_sweep_evenly_spaced() -> Result<(), Error> {
+        let ids = NodeID::keyspace_sweep(16);
+        assert_eq!(ids.len(), 16);
+        assert_eq!(ids[0], NodeID::new(BigUint::from(0u8)));
+
+        let step = ids[1].deref() - ids[0].deref();
+        for pair in ids.windows(2) {
+            assert!(pair[0].deref() < pair[1].deref());
+            assert_eq!(pair[1].deref() - pair[0].deref(), step);
+        }
+
+        assert!(ids.iter().all(NodeID::has_valid_length));
+        assert_eq!(
+            ids[15],
+            NodeID::from_hex(b"f000000000000000000000000000000000000000")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn distance_is_xor() -> Result<(), Error> {
+        let a = NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7f")?;
+        let b = NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc70")?;
+
+        assert_eq!(
+            a.distance(&b),
+            NodeID::from_hex(b"000000000000000000000000000000000000000f")?
+        );
+        assert_eq!(a.distance(&b), b.distance(&a));
+        assert_eq!(a.distance(&a), NodeID::new(BigUint::from(0u8)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn leading_zeros() -> Result<(), Error> {
+        assert_eq!(
+            NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7f")?.leading_zeros(),
+            0
+        );
+        assert_eq!(
+            NodeID::from_hex(b"000000000000000000000000000000000000000f")?.leading_zeros(),
+            156
+        );
+        assert_eq!(
+            NodeID::from_hex(b"00ffffffffffffffffffffffffffffffffffffff")?.leading_zeros(),
+            8
+        );
+        assert_eq!(NodeID::new(BigUint::from(0u8)).leading_zeros(), 160);
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_hex_rejects_malformed() {
+        assert!(NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7g").is_err());
+        assert!(NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc").is_err());
+        assert!(NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7f00").is_err());
+    }
+
+    #[test]
+    fn try_from_slice_requires_20_bytes() {
+        assert!(NodeID::try_from(&[1u8; 19][..]).is_err());
+        assert!(NodeID::try_from(&[1u8; 21][..]).is_err());
+        assert_eq!(
+            NodeID::try_from(&[1u8; 20][..]).ok(),
+            Some(NodeID::from_bytes(&[1u8; 20]))
+        );
+    }
+
+    #[test]
+    fn try_from_bytes_too_short() {
+        assert!(NodeID::try_from_bytes(&[1u8; 19]).is_err());
+        assert!(NodeID::try_from_bytes(&[1u8; 20]).is_ok());
+    }
+
+    #[test]
+    fn valid_length() {
+        assert!(NodeID::from_bytes(&[0xffu8; 20]).has_valid_length());
+        assert!(!NodeID::from_bytes(&[1u8; 21]).has_valid_length());
+    }
+
+    /// Test vectors from BEP-0042: ip, rand and an example id.
+    const BEP42_VECTORS: [([u8; 4], u8, &[u8; 40]); 5] = [
+        (
+            [124, 31, 75, 21],
+            1,
+            b"5fbfbff10c5d6a4ec8a88e4c6ab4c28b95eee401",
+        ),
+        (
+            [21, 75, 31, 124],
+            86,
+            b"5a3ce9c14e7a08645677bbd1cfe7d8f956d53256",
+        ),
+        (
+            [65, 23, 51, 170],
+            22,
+            b"a5d43220bc8f112a3d426c84764f8c2a1150e616",
+        ),
+        (
+            [84, 124, 73, 14],
+            65,
+            b"1b0321dd1bb1fe518101ceef99462b947a01ff41",
+        ),
+        (
+            [43, 213, 53, 83],
+            90,
+            b"e56f6cbf5b7c4be0237986d5243b87aa6d51305a",
+        ),
+    ];
+
+    #[test]
+    fn bep42_vectors() -> Result<(), Error> {
+        for (ip, rand, expected) in BEP42_VECTORS.iter() {
+            let ip = Ipv4Addr::from(*ip);
+            let expected = NodeID::from_hex(*expected)?;
+            let id = NodeID::from_ip_bep42(ip, *rand);
+
+            // Only the top 21 bits and the last byte are fixed, the rest are
+            // random.
+            assert_eq!(id.deref() >> 139, expected.deref() >> 139);
+            assert_eq!(id.deref() & BigUint::from(0xffu8), BigUint::from(*rand));
+
+            assert!(id.is_valid_for_ip(ip));
+            assert!(expected.is_valid_for_ip(ip));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn bep42_rejects_other_ip() -> Result<(), Error> {
+        let id = NodeID::from_hex(b"5fbfbff10c5d6a4ec8a88e4c6ab4c28b95eee401")?;
+
+        assert!(!id.is_valid_for_ip(Ipv4Addr::new(21, 75, 31, 124)));
+        assert!(!NodeID::from_bytes(&[0u8; 20]).is_valid_for_ip(Ipv4Addr::new(124, 31, 75, 21)));
+        assert!(id.is_valid_for_ip(Ipv4Addr::new(192, 168, 1, 1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn first_bit() -> Result<(), Error> {
         ensure_bits_for(
-            b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7f".into(),
+            NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7f")?,
             "10001011 10010010 10010010 10110010 11110111 01011101 00010010 01110111 00100000 11101011 11001101 10001010 11111110 01100110 10111111 10100101 00001100 00101010 11011100 01111111"
-        )
+        );
+
+        Ok(())
     }
 
     fn ensure_bits_for(id: NodeID, expected_bits: &str) {
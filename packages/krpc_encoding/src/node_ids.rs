@@ -0,0 +1,50 @@
+//! Serialization for lists of ids packed into a single byte string, such as
+//! the `samples` of a [BEP-0051] response.
+//!
+//! [BEP-0051]: http://www.bittorrent.org/beps/bep_0051.html
+
+use crate::NodeID;
+use log::warn;
+use serde::{
+    Deserializer,
+    Serializer,
+};
+
+/// Length of a packed id.
+const ID_LEN: usize = 20;
+
+pub fn serialize<S>(ids: &Vec<NodeID>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut bytes = Vec::with_capacity(ids.len() * ID_LEN);
+    for id in ids {
+        bytes.extend_from_slice(&id.as_bytes());
+    }
+
+    serializer.serialize_bytes(&bytes)
+}
+
+/// Decodes every complete 20 byte id. A trailing partial id is discarded.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<NodeID>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let bytes: Vec<u8> = serde_bytes::deserialize(deserializer)?;
+
+    let trailing = bytes.len() % ID_LEN;
+    if trailing != 0 {
+        warn!("discarding {} trailing bytes of packed ids", trailing);
+    }
+
+    Ok(bytes.chunks_exact(ID_LEN).map(NodeID::from_bytes).collect())
+}
+
+/// Like [`deserialize`] but for fields where a missing value and an empty
+/// value mean different things.
+pub fn deserialize_some<'de, D>(deserializer: D) -> Result<Option<Vec<NodeID>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Some(deserialize(deserializer)?))
+}
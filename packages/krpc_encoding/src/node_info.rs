@@ -2,6 +2,7 @@ use crate::{
     addr,
     NodeID,
 };
+use log::warn;
 use serde::{
     de::{
         self,
@@ -12,83 +13,215 @@ use serde::{
 };
 use std::{
     fmt,
-    net::SocketAddrV4,
+    hash::{
+        Hash,
+        Hasher,
+    },
+    net::SocketAddr,
 };
 
 /// Contact information for a node in the DHT network
 ///
-/// Implements "Compact node info" serialization and de-serialization.
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Implements "Compact node info" serialization and de-serialization. IPv4
+/// nodes are carried in `nodes` and IPv6 nodes in `nodes6` ([BEP-0032]).
+///
+/// Equality and hashing only look at `node_id`, so the same node seen at
+/// different addresses is treated as one node, for example when deduplicating
+/// with a `HashSet`. Compare `address` separately where it matters.
+///
+/// [BEP-0032]: http://www.bittorrent.org/beps/bep_0032.html
+#[derive(Debug, Clone)]
 pub struct NodeInfo {
     pub node_id: NodeID,
-    pub address: SocketAddrV4,
+    pub address: SocketAddr,
+}
+
+impl PartialEq for NodeInfo {
+    fn eq(&self, other: &NodeInfo) -> bool {
+        self.node_id == other.node_id
+    }
+}
+
+impl Eq for NodeInfo {}
+
+impl Hash for NodeInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.node_id.hash(state);
+    }
 }
 
 impl NodeInfo {
-    pub fn new(node_id: NodeID, addr: SocketAddrV4) -> NodeInfo {
+    pub fn new(node_id: NodeID, addr: SocketAddr) -> NodeInfo {
         NodeInfo {
             node_id,
             address: addr,
         }
     }
 
-    fn to_bytes(&self) -> [u8; 26] {
-        let mut output = [0u8; 26];
-        (&mut output[..20]).copy_from_slice(&self.node_id.as_bytes());
-        addr::write_to(&self.address, &mut output[20..]);
+    fn write_to(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.node_id.as_bytes());
 
-        output
+        match self.address {
+            SocketAddr::V4(ref address) => {
+                let mut raw = [0u8; 6];
+                addr::write_to(address, &mut raw);
+                output.extend_from_slice(&raw);
+            }
+            SocketAddr::V6(ref address) => {
+                let mut raw = [0u8; 18];
+                addr::write_v6_to(address, &mut raw);
+                output.extend_from_slice(&raw);
+            }
+        }
     }
 
     fn from_bytes(bytes: &[u8]) -> NodeInfo {
         let node_id = NodeID::from_bytes(&bytes[..20]);
         let address = addr::from_bytes(&bytes[20..]);
 
-        NodeInfo { node_id, address }
+        NodeInfo {
+            node_id,
+            address: address.into(),
+        }
+    }
+
+    fn from_v6_bytes(bytes: &[u8]) -> NodeInfo {
+        let node_id = NodeID::from_bytes(&bytes[..20]);
+        let address = addr::from_v6_bytes(&bytes[20..]);
+
+        NodeInfo {
+            node_id,
+            address: address.into(),
+        }
     }
 }
 
-pub fn serialize<S>(nodes: &Vec<NodeInfo>, serializer: S) -> Result<S::Ok, S::Error>
+/// Length of a compact IPv4 node info entry.
+const V4_ENTRY_LEN: usize = 26;
+
+/// Length of a compact IPv6 node info entry.
+const V6_ENTRY_LEN: usize = 38;
+
+fn serialize_family<S>(nodes: &[NodeInfo], v6: bool, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let bytes = nodes
-        .iter()
-        .flat_map(|node| node.to_bytes().to_vec())
-        .collect::<Vec<u8>>();
+    let mut bytes = Vec::new();
+    for node in nodes.iter().filter(|node| node.address.is_ipv6() == v6) {
+        node.write_to(&mut bytes);
+    }
 
     serializer.serialize_bytes(&bytes)
 }
 
+/// Serializes the IPv4 nodes in `nodes`. IPv6 nodes are skipped, they are
+/// serialized into `nodes6` by [`v6::serialize`].
+pub fn serialize<S>(nodes: &Vec<NodeInfo>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serialize_family(nodes, false, serializer)
+}
+
 pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<NodeInfo>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    deserializer.deserialize_bytes(NodeInfoVecVisitor)
+    deserializer.deserialize_bytes(NodeInfoVecVisitor { v6: false })
 }
 
-struct NodeInfoVecVisitor;
+/// Like [`deserialize`] but for fields where a missing value and an empty
+/// value mean different things.
+pub fn deserialize_some<'de, D>(deserializer: D) -> Result<Option<Vec<NodeInfo>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Some(deserialize(deserializer)?))
+}
+
+/// Compact node info for the `nodes6` field. Entries are 38 bytes, a 20 byte
+/// id followed by a 16 byte IPv6 address and a 2 byte port.
+pub mod v6 {
+    use super::{
+        serialize_family,
+        NodeInfo,
+        NodeInfoVecVisitor,
+    };
+    use serde::{
+        Deserializer,
+        Serializer,
+    };
+
+    /// Serializes the IPv6 nodes in `nodes`. IPv4 nodes are skipped.
+    pub fn serialize<S>(nodes: &Vec<NodeInfo>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_family(nodes, true, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<NodeInfo>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(NodeInfoVecVisitor { v6: true })
+    }
+
+    pub fn deserialize_some<'de, D>(deserializer: D) -> Result<Option<Vec<NodeInfo>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Some(deserialize(deserializer)?))
+    }
+}
+
+struct NodeInfoVecVisitor {
+    v6: bool,
+}
+
+impl NodeInfoVecVisitor {
+    fn entry_len(&self) -> usize {
+        if self.v6 {
+            V6_ENTRY_LEN
+        } else {
+            V4_ENTRY_LEN
+        }
+    }
+}
 
 impl<'de> Visitor<'de> for NodeInfoVecVisitor {
     type Value = Vec<NodeInfo>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str("a byte array with a size which is a multiple of 26")
+        formatter.write_str("a byte array of compact node infos")
     }
 
+    /// Decodes every complete 26 (or 38 for IPv6) byte entry. A trailing
+    /// partial entry, usually from a response truncated to fit the MTU, is
+    /// discarded rather than failing the whole message so the complete entries
+    /// are still usable.
     fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
-        let len = v.len();
-        if len % 26 != 0 {
-            return Err(de::Error::invalid_length(len, &self));
+        let entry_len = self.entry_len();
+        let trailing = v.len() % entry_len;
+        if trailing != 0 {
+            warn!(
+                "discarding {} trailing bytes of a truncated compact node info",
+                trailing
+            );
         }
 
-        let mut output: Vec<NodeInfo> = Vec::with_capacity(len / 26);
+        let len = v.len() - trailing;
+        let mut output: Vec<NodeInfo> = Vec::with_capacity(len / entry_len);
 
-        for idx in (0..len).step_by(26) {
-            let node_info = NodeInfo::from_bytes(&v[idx..]);
+        for idx in (0..len).step_by(entry_len) {
+            let node_info = if self.v6 {
+                NodeInfo::from_v6_bytes(&v[idx..idx + entry_len])
+            } else {
+                NodeInfo::from_bytes(&v[idx..idx + entry_len])
+            };
             output.push(node_info);
         }
 
@@ -108,7 +241,11 @@ mod tests {
     use super::NodeInfo;
     type Error = Box<dyn std::error::Error>;
     use std::{
-        net::SocketAddrV4,
+        collections::HashSet,
+        net::{
+            SocketAddrV4,
+            SocketAddrV6,
+        },
         str::FromStr,
     };
 
@@ -119,7 +256,46 @@ mod tests {
             SocketAddrV4::from_str("129.21.60.68:3454")?.into(),
         );
 
-        let _bytes = node.to_bytes();
+        let mut bytes = Vec::new();
+        node.write_to(&mut bytes);
+
+        assert_eq!(bytes.len(), 26);
+
+        Ok(())
+    }
+
+    #[test]
+    fn v6_round_trip() -> Result<(), Error> {
+        let node = NodeInfo::new(
+            b"abcdefghij0123456789".into(),
+            SocketAddrV6::from_str("[2001:db8::1]:6881")?.into(),
+        );
+
+        let mut bytes = Vec::new();
+        node.write_to(&mut bytes);
+
+        assert_eq!(bytes.len(), 38);
+        let decoded = NodeInfo::from_v6_bytes(&bytes);
+        assert_eq!(decoded, node);
+        assert_eq!(decoded.address, node.address);
+
+        Ok(())
+    }
+
+    #[test]
+    fn same_id_different_address_deduplicated() -> Result<(), Error> {
+        let id = b"abcdefghij0123456789";
+        let mut nodes = HashSet::new();
+        nodes.insert(NodeInfo::new(
+            id.into(),
+            SocketAddrV4::from_str("129.21.60.68:3454")?.into(),
+        ));
+        nodes.insert(NodeInfo::new(
+            id.into(),
+            SocketAddrV4::from_str("129.21.60.69:6881")?.into(),
+        ));
+
+        assert_eq!(nodes.len(), 1);
 
         Ok(())
     }
@@ -0,0 +1,85 @@
+use crate::booleans;
+use serde_derive::{
+    Deserialize,
+    Serialize,
+};
+
+/// Port a peer announced with [`Query::AnnouncePeer`](crate::Query) is
+/// listening on.
+///
+/// Encoded as the `implied_port` and `port` keys of the query. [BEP-0005]
+/// requires `port` on every announce, so it is sent even when the implied port
+/// is used. An announce which isn't using the implied port always carries a
+/// port, so one without it can't be built:
+///
+/// ```compile_fail
+/// use krpc_encoding::{NodeID, PortType, Query};
+///
+/// let query = Query::AnnouncePeer {
+///     id: NodeID::random(),
+///     port: PortType::Port,
+///     info_hash: NodeID::random(),
+///     token: None,
+/// };
+/// ```
+///
+/// [BEP-0005]: http://www.bittorrent.org/beps/bep_0005.html
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(from = "RawPortType", into = "RawPortType")]
+pub enum PortType {
+    /// The peer is listening on the source port of the UDP packet containing
+    /// the query. Useful for peers behind a NAT which don't know their
+    /// external port.
+    ///
+    /// The wrapped port is sent in the `port` key for nodes which ignore
+    /// `implied_port`, usually the port the peer listens on locally. Zero when
+    /// decoded from a query which left `port` out.
+    Implied(u16),
+
+    /// The peer is listening on this port.
+    Port(u16),
+
+    /// Neither `implied_port` nor `port` was set. Only produced when decoding
+    /// announces from other nodes so they can be answered with an error
+    /// rather than dropped. Announces with a missing port can't be sent.
+    Missing,
+}
+
+/// Wire representation of [`PortType`].
+#[derive(Serialize, Deserialize)]
+struct RawPortType {
+    #[serde(deserialize_with = "booleans::deserialize")]
+    implied_port: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+}
+
+impl From<RawPortType> for PortType {
+    fn from(raw: RawPortType) -> PortType {
+        match (raw.implied_port, raw.port) {
+            (true, port) => PortType::Implied(port.unwrap_or(0)),
+            (false, Some(port)) => PortType::Port(port),
+            (false, None) => PortType::Missing,
+        }
+    }
+}
+
+impl From<PortType> for RawPortType {
+    fn from(port_type: PortType) -> RawPortType {
+        match port_type {
+            PortType::Implied(port) => RawPortType {
+                implied_port: true,
+                port: Some(port),
+            },
+            PortType::Port(port) => RawPortType {
+                implied_port: false,
+                port: Some(port),
+            },
+            PortType::Missing => RawPortType {
+                implied_port: false,
+                port: None,
+            },
+        }
+    }
+}
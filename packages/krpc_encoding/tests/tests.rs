@@ -2,9 +2,12 @@ use krpc_encoding::{
     Envelope,
     KRPCError,
     Message,
+    NodeID,
     NodeInfo,
+    PortType,
     Query,
     Response,
+    Value,
 };
 use std::{
     net::SocketAddrV4,
@@ -19,6 +22,10 @@ fn test_serialize_deserialize(parsed: Envelope, raw: &[u8]) -> Result<(), Error>
     let raw_decoded = Envelope::decode(raw)?;
     assert_eq!(parsed, raw_decoded);
 
+    // NodeInfo equality ignores addresses, so check they were decoded by
+    // encoding again.
+    assert_eq!(raw, &raw_decoded.encode()?[..]);
+
     Ok(())
 }
 
@@ -76,6 +83,26 @@ fn ping_response() -> Result<(), Error> {
     test_serialize_deserialize(parsed, raw)
 }
 
+#[test]
+fn ping_response_unknown_keys() -> Result<(), Error> {
+    let expected = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Response {
+            response: Response::OnlyID {
+                id: b"mnopqrstuvwxyz123456".into(),
+            },
+        },
+        read_only: false,
+    };
+
+    let raw = b"d3:foo3:bar1:rd3:baz3:qux2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re";
+    assert_eq!(Envelope::decode(raw)?, expected);
+
+    Ok(())
+}
+
 #[test]
 fn error() -> Result<(), Error> {
     let parsed = Envelope {
@@ -101,10 +128,9 @@ fn announce_peer_request() -> Result<(), Error> {
         message_type: Message::Query {
             query: Query::AnnouncePeer {
                 id: b"abcdefghij0123456789".into(),
-                implied_port: true,
-                port: Some(6881),
+                port: PortType::Implied(6881),
                 info_hash: b"mnopqrstuvwxyz123456".into(),
-                token: b"aoeusnth".to_vec(),
+                token: Some(b"aoeusnth".to_vec()),
             },
         },
         read_only: false,
@@ -114,6 +140,55 @@ fn announce_peer_request() -> Result<(), Error> {
     test_serialize_deserialize(parsed, raw)
 }
 
+#[test]
+fn announce_peer_request_implied_port_without_port() -> Result<(), Error> {
+    let raw = b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234565:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe";
+
+    match Envelope::decode(raw)?.message_type {
+        Message::Query {
+            query: Query::AnnouncePeer { port, .. },
+        } => assert_eq!(port, PortType::Implied(0)),
+        other => panic!("expected announce_peer, got {:?}", other),
+    };
+
+    Ok(())
+}
+
+#[test]
+fn announce_peer_request_missing_port() -> Result<(), Error> {
+    let raw = b"d1:ad2:id20:abcdefghij012345678912:implied_porti0e9:info_hash20:mnopqrstuvwxyz1234565:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe";
+
+    match Envelope::decode(raw)?.message_type {
+        Message::Query {
+            query: Query::AnnouncePeer { port, .. },
+        } => assert_eq!(port, PortType::Missing),
+        other => panic!("expected announce_peer, got {:?}", other),
+    };
+
+    Ok(())
+}
+
+#[test]
+fn announce_peer_request_without_token() -> Result<(), Error> {
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Query {
+            query: Query::AnnouncePeer {
+                id: b"abcdefghij0123456789".into(),
+                port: PortType::Port(6881),
+                info_hash: b"mnopqrstuvwxyz123456".into(),
+                token: None,
+            },
+        },
+        read_only: false,
+    };
+
+    let raw = b"d1:ad2:id20:abcdefghij012345678912:implied_porti0e9:info_hash20:mnopqrstuvwxyz1234564:porti6881ee1:q13:announce_peer1:t2:aa1:y1:qe";
+    test_serialize_deserialize(parsed, raw)
+}
+
 #[test]
 fn get_nodes_response() -> Result<(), Error> {
     let parsed = Envelope {
@@ -125,6 +200,7 @@ fn get_nodes_response() -> Result<(), Error> {
                 id: b"abcdefghij0123456789".into(),
                 token: None,
                 nodes: Vec::new(),
+                nodes6: Vec::new(),
             },
         },
         read_only: false,
@@ -174,74 +250,75 @@ fn get_nodes_response_decode() -> Result<(), Error> {
         version: None,
         message_type: Message::Response {
             response: Response::NextHop {
-                id: b"32f54e697351ff4aec29cdbaabf2fbe3467cc267".into(),
+                id: NodeID::from_hex(b"32f54e697351ff4aec29cdbaabf2fbe3467cc267")?,
                 token: None,
                 nodes: vec![
                     NodeInfo::new(
-                        b"30210b1743281b53c298bd53b8742ce06477e3ac".into(),
+                        NodeID::from_hex(b"30210b1743281b53c298bd53b8742ce06477e3ac")?,
                         "180.211.234.53:1416".parse()?,
                     ),
                     NodeInfo::new(
-                        b"f73704455d85399c681b00e71d9131acacaa3233".into(),
+                        NodeID::from_hex(b"f73704455d85399c681b00e71d9131acacaa3233")?,
                         "36.37.147.240:12664".parse()?,
                     ),
                     NodeInfo::new(
-                        b"cd09f99367ca2f9376f7380e6e17ba35aea5aaba".into(),
+                        NodeID::from_hex(b"cd09f99367ca2f9376f7380e6e17ba35aea5aaba")?,
                         "95.24.216.93:31751".parse()?,
                     ),
                     NodeInfo::new(
-                        b"c07077106a5c3a7089808a8d4f174518b70455a6".into(),
+                        NodeID::from_hex(b"c07077106a5c3a7089808a8d4f174518b70455a6")?,
                         "93.172.43.127:23157".parse()?,
                     ),
                     NodeInfo::new(
-                        b"0c812fdfc50a0fb7d56123f0ebed32fcf9c2e1db".into(),
+                        NodeID::from_hex(b"0c812fdfc50a0fb7d56123f0ebed32fcf9c2e1db")?,
                         "70.124.69.205:50321".parse()?,
                     ),
                     NodeInfo::new(
-                        b"6664faa68068445b8cb636365a1502f1c88d1725".into(),
+                        NodeID::from_hex(b"6664faa68068445b8cb636365a1502f1c88d1725")?,
                         "46.153.74.174:64403".parse()?,
                     ),
                     NodeInfo::new(
-                        b"a54f14554b7d4dce60192063e1e06755f392fab5".into(),
+                        NodeID::from_hex(b"a54f14554b7d4dce60192063e1e06755f392fab5")?,
                         "81.97.116.190:6881".parse()?,
                     ),
                     NodeInfo::new(
-                        b"de9deabf3871737ebc1b9553f09735e24af153e2".into(),
+                        NodeID::from_hex(b"de9deabf3871737ebc1b9553f09735e24af153e2")?,
                         "84.251.160.222:48299".parse()?,
                     ),
                     NodeInfo::new(
-                        b"5628a8ee8d12b8825326762d1c3628299ccad82e".into(),
+                        NodeID::from_hex(b"5628a8ee8d12b8825326762d1c3628299ccad82e")?,
                         "98.13.2.205:6881".parse()?,
                     ),
                     NodeInfo::new(
-                        b"3f9c0cd713b443f3ba136ddd055098f723f3f838".into(),
+                        NodeID::from_hex(b"3f9c0cd713b443f3ba136ddd055098f723f3f838")?,
                         "42.98.51.123:9304".parse()?,
                     ),
                     NodeInfo::new(
-                        b"7465722ad0f14da49e1d48cef1347469bc6e6d75".into(),
+                        NodeID::from_hex(b"7465722ad0f14da49e1d48cef1347469bc6e6d75")?,
                         "79.114.47.76:64186".parse()?,
                     ),
                     NodeInfo::new(
-                        b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7f".into(),
+                        NodeID::from_hex(b"8b9292b2f75d127720ebcd8afe66bfa50c2adc7f")?,
                         "2.87.195.123:62705".parse()?,
                     ),
                     NodeInfo::new(
-                        b"d0fb8538dab41982305879bea3c6176b4a0cbbde".into(),
+                        NodeID::from_hex(b"d0fb8538dab41982305879bea3c6176b4a0cbbde")?,
                         "49.70.2.154:16001".parse()?,
                     ),
                     NodeInfo::new(
-                        b"7f4241a48797f052d0e6e7f9d180627be71cdaf5".into(),
+                        NodeID::from_hex(b"7f4241a48797f052d0e6e7f9d180627be71cdaf5")?,
                         "70.55.32.213:17940".parse()?,
                     ),
                     NodeInfo::new(
-                        b"3426e6d3b38b4b2190decc6c83ccf36685344091".into(),
+                        NodeID::from_hex(b"3426e6d3b38b4b2190decc6c83ccf36685344091")?,
                         "124.77.137.19:16001".parse()?,
                     ),
                     NodeInfo::new(
-                        b"0900ed1818270340e3f629cb13aaae62664221f5".into(),
+                        NodeID::from_hex(b"0900ed1818270340e3f629cb13aaae62664221f5")?,
                         "119.237.152.161:6890".parse()?,
                     ),
                 ],
+                nodes6: Vec::new(),
             },
         },
         read_only: false,
@@ -254,6 +331,97 @@ fn get_nodes_response_decode() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn get_nodes_response_trailing_partial_node() -> Result<(), Error> {
+    let mut raw = b"d1:rd2:id20:abcdefghij01234567895:nodes36:mnopqrstuvwxyz123456".to_vec();
+    raw.extend_from_slice(&[129, 21, 60, 68, 0x0d, 0x7e]);
+    raw.extend_from_slice(&[0u8; 10]);
+    raw.extend_from_slice(b"ee1:t2:aa1:y1:re");
+
+    let decoded = Envelope::decode(&raw)?;
+
+    assert_eq!(
+        decoded.message_type,
+        Message::Response {
+            response: Response::NextHop {
+                id: b"abcdefghij0123456789".into(),
+                token: None,
+                nodes: vec![NodeInfo::new(
+                    b"mnopqrstuvwxyz123456".into(),
+                    SocketAddrV4::from_str("129.21.60.68:3454")?.into(),
+                )],
+                nodes6: Vec::new(),
+            },
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn get_nodes_response_ipv6() -> Result<(), Error> {
+    let mut raw = b"d1:rd2:id20:abcdefghij01234567896:nodes638:mnopqrstuvwxyz123456".to_vec();
+    raw.extend_from_slice(&[
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+    ]);
+    raw.extend_from_slice(&[0x1a, 0xe1]);
+    raw.extend_from_slice(b"e1:t2:aa1:y1:re");
+
+    let decoded = Envelope::decode(&raw)?;
+
+    assert_eq!(
+        decoded.message_type,
+        Message::Response {
+            response: Response::NextHop {
+                id: b"abcdefghij0123456789".into(),
+                token: None,
+                nodes: Vec::new(),
+                nodes6: vec![NodeInfo::new(
+                    b"mnopqrstuvwxyz123456".into(),
+                    "[2001:db8::1]:6881".parse()?,
+                )],
+            },
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn get_nodes_response_mixed_families() -> Result<(), Error> {
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Response {
+            response: Response::NextHop {
+                id: b"abcdefghij0123456789".into(),
+                token: None,
+                nodes: vec![NodeInfo::new(
+                    b"mnopqrstuvwxyz123456".into(),
+                    "129.21.60.68:3454".parse()?,
+                )],
+                nodes6: vec![NodeInfo::new(
+                    b"0123456789abcdefghij".into(),
+                    "[2001:db8::1]:6881".parse()?,
+                )],
+            },
+        },
+        read_only: false,
+    };
+
+    let mut raw = b"d1:rd2:id20:abcdefghij01234567895:nodes26:mnopqrstuvwxyz123456".to_vec();
+    raw.extend_from_slice(&[129, 21, 60, 68, 0x0d, 0x7e]);
+    raw.extend_from_slice(b"6:nodes638:0123456789abcdefghij");
+    raw.extend_from_slice(&[
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+    ]);
+    raw.extend_from_slice(&[0x1a, 0xe1]);
+    raw.extend_from_slice(b"e1:t2:aa1:y1:re");
+
+    test_serialize_deserialize(parsed, &raw)
+}
+
 #[test]
 fn with_version() -> Result<(), Error> {
     let encoded: &[u8] = &[
@@ -269,7 +437,7 @@ fn with_version() -> Result<(), Error> {
         version: Some(vec![85, 84, 174, 88].into()),
         message_type: Message::Response {
             response: Response::OnlyID {
-                id: b"bd5d3cbbe9ebb3a6db3c870c3e99245e0d1c06f1".into(),
+                id: NodeID::from_hex(b"bd5d3cbbe9ebb3a6db3c870c3e99245e0d1c06f1")?,
             },
         },
         read_only: false,
@@ -281,3 +449,171 @@ fn with_version() -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Public key and signature of the mutable item test vector from BEP-0044.
+const BEP44_KEY: &str = "77ff84905a91936367c01360803104f92432fcd904a43511876df5cdf3e7e548";
+const BEP44_SIG: &str = "305ac8aeb6c9c151fa120f120ea2cfb923564e11552d06a5d856091e5e853cff1260d3f39e4999684aa92eb73ffd136e6f4f3ecbfda0ce53a1608ecd7ae21f01";
+
+fn concat(parts: &[&[u8]]) -> Vec<u8> {
+    parts.concat()
+}
+
+#[test]
+fn get_request() -> Result<(), Error> {
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Query {
+            query: Query::Get {
+                id: b"abcdefghij0123456789".into(),
+                target: b"mnopqrstuvwxyz123456".into(),
+            },
+        },
+        read_only: false,
+    };
+
+    let raw =
+        b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e1:q3:get1:t2:aa1:y1:qe";
+    test_serialize_deserialize(parsed, raw)
+}
+
+#[test]
+fn put_immutable_request() -> Result<(), Error> {
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Query {
+            query: Query::Put {
+                id: b"abcdefghij0123456789".into(),
+                token: b"aoeusnth".to_vec(),
+                v: Value::Bytes(b"Hello World!".to_vec()),
+                k: None,
+                sig: None,
+                salt: None,
+                seq: None,
+                cas: None,
+            },
+        },
+        read_only: false,
+    };
+
+    let raw = b"d1:ad2:id20:abcdefghij01234567895:token8:aoeusnth1:v12:Hello World!e1:q3:put1:t2:aa1:y1:qe";
+    test_serialize_deserialize(parsed, raw)
+}
+
+#[test]
+fn put_mutable_request() -> Result<(), Error> {
+    let key = hex::decode(BEP44_KEY)?;
+    let sig = hex::decode(BEP44_SIG)?;
+
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Query {
+            query: Query::Put {
+                id: b"abcdefghij0123456789".into(),
+                token: b"aoeusnth".to_vec(),
+                v: Value::Bytes(b"Hello World!".to_vec()),
+                k: Some(key.clone()),
+                sig: Some(sig.clone()),
+                salt: None,
+                seq: Some(1),
+                cas: Some(0),
+            },
+        },
+        read_only: false,
+    };
+
+    let raw = concat(&[
+        b"d1:ad3:casi0e2:id20:abcdefghij01234567891:k32:",
+        &key,
+        b"3:seqi1e3:sig64:",
+        &sig,
+        b"5:token8:aoeusnth1:v12:Hello World!e1:q3:put1:t2:aa1:y1:qe",
+    ]);
+    test_serialize_deserialize(parsed, &raw)
+}
+
+#[test]
+fn get_data_response() -> Result<(), Error> {
+    let key = hex::decode(BEP44_KEY)?;
+    let sig = hex::decode(BEP44_SIG)?;
+
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Response {
+            response: Response::GetData {
+                id: b"0123456789abcdefghij".into(),
+                token: Some(b"aoeusnth".to_vec()),
+                nodes: Vec::new(),
+                v: Value::Bytes(b"Hello World!".to_vec()),
+                k: Some(key.clone()),
+                sig: Some(sig.clone()),
+                seq: Some(1),
+            },
+        },
+        read_only: false,
+    };
+
+    let raw = concat(&[
+        b"d1:rd2:id20:0123456789abcdefghij1:k32:",
+        &key,
+        b"5:nodes0:3:seqi1e3:sig64:",
+        &sig,
+        b"5:token8:aoeusnth1:v12:Hello World!e1:t2:aa1:y1:re",
+    ]);
+    test_serialize_deserialize(parsed, &raw)
+}
+
+#[test]
+fn samples_response() -> Result<(), Error> {
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Response {
+            response: Response::Samples {
+                id: b"0123456789abcdefghij".into(),
+                interval: Some(21600),
+                nodes: Vec::new(),
+                num: Some(2),
+                samples: vec![
+                    b"abcdefghij0123456789".into(),
+                    b"mnopqrstuvwxyz123456".into(),
+                ],
+            },
+        },
+        read_only: false,
+    };
+
+    let raw = b"d1:rd2:id20:0123456789abcdefghij8:intervali21600e5:nodes0:3:numi2e7:samples40:abcdefghij0123456789mnopqrstuvwxyz123456e1:t2:aa1:y1:re";
+    test_serialize_deserialize(parsed, raw)
+}
+
+#[test]
+fn samples_response_without_optional_fields() -> Result<(), Error> {
+    let parsed = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Response {
+            response: Response::Samples {
+                id: b"0123456789abcdefghij".into(),
+                interval: None,
+                nodes: Vec::new(),
+                num: None,
+                samples: vec![b"abcdefghij0123456789".into()],
+            },
+        },
+        read_only: false,
+    };
+
+    let raw =
+        b"d1:rd2:id20:0123456789abcdefghij5:nodes0:7:samples20:abcdefghij0123456789e1:t2:aa1:y1:re";
+    test_serialize_deserialize(parsed, raw)
+}
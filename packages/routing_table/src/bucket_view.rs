@@ -0,0 +1,75 @@
+use crate::{
+    k_bucket::KBucket,
+    node_contact_state::{
+        NodeContactState,
+        NodeState,
+    },
+};
+use krpc_encoding::NodeID;
+use std::net::SocketAddr;
+
+/// Read-only view of a bucket in a [`RoutingTable`].
+///
+/// [`RoutingTable`]: crate::RoutingTable
+pub struct BucketView<'a> {
+    prefix: Vec<bool>,
+    bucket: &'a KBucket,
+}
+
+impl<'a> BucketView<'a> {
+    pub(crate) fn new(prefix: Vec<bool>, bucket: &'a KBucket) -> BucketView<'a> {
+        BucketView { prefix, bucket }
+    }
+
+    /// Depth of the bucket in the tree, the number of id bits shared by every
+    /// node in the bucket.
+    pub fn depth(&self) -> usize {
+        self.prefix.len()
+    }
+
+    /// Values of [`NodeID::nth_bit`] shared by the ids of every node in the
+    /// bucket, for each bit up to [`BucketView::depth`].
+    pub fn prefix(&self) -> &[bool] {
+        &self.prefix
+    }
+
+    /// Contacts in the bucket, in the order they were added.
+    pub fn contacts(&self) -> impl Iterator<Item = ContactView<'a>> {
+        self.bucket
+            .contacts()
+            .map(|contact| ContactView { contact })
+    }
+
+    /// Number of contacts in the bucket.
+    pub fn len(&self) -> usize {
+        self.bucket.contacts().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of contacts the bucket holds.
+    pub fn capacity(&self) -> usize {
+        self.bucket.k()
+    }
+}
+
+/// Read-only view of a contact in a [`BucketView`].
+pub struct ContactView<'a> {
+    contact: &'a NodeContactState,
+}
+
+impl<'a> ContactView<'a> {
+    pub fn id(&self) -> &'a NodeID {
+        &self.contact.id
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.contact.address
+    }
+
+    pub fn state(&self) -> NodeState {
+        self.contact.state()
+    }
+}
@@ -0,0 +1,118 @@
+use crate::node_contact_state::{
+    NodeContactState,
+    NodeState,
+};
+use futures::channel::mpsc::{
+    unbounded,
+    UnboundedReceiver,
+    UnboundedSender,
+};
+use krpc_encoding::NodeID;
+use std::sync::{
+    Mutex,
+    PoisonError,
+};
+
+/// Something which happened to the routing table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoutingEvent {
+    /// The state of a node changed. Transitions are detected when a node is
+    /// contacted, so a node which silently became questionable is only
+    /// reported the next time it is pinged.
+    NodeStateChanged {
+        id: NodeID,
+        old: NodeState,
+        new: NodeState,
+    },
+}
+
+/// Delivers [`RoutingEvent`]s to every subscriber. A subscriber list left
+/// poisoned by a panicking thread is still used, since it is never left half
+/// updated.
+pub struct RoutingEvents {
+    subscribers: Mutex<Vec<UnboundedSender<RoutingEvent>>>,
+}
+
+impl RoutingEvents {
+    pub fn new() -> RoutingEvents {
+        RoutingEvents {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<RoutingEvent> {
+        let (sender, receiver) = unbounded();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sender);
+
+        receiver
+    }
+
+    /// Sends `event` to all subscribers, forgetting subscribers which have
+    /// gone away.
+    pub fn emit(&self, event: RoutingEvent) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
+    /// Emits [`RoutingEvent::NodeStateChanged`] if the state of `node` changed
+    /// since it was last observed.
+    pub fn observe(&self, node: &mut NodeContactState) {
+        if let Some((old, new)) = node.refresh_state() {
+            self.emit(RoutingEvent::NodeStateChanged {
+                id: node.id.clone(),
+                old,
+                new,
+            });
+        }
+    }
+}
+
+impl Default for RoutingEvents {
+    fn default() -> RoutingEvents {
+        RoutingEvents::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        RoutingEvent,
+        RoutingEvents,
+    };
+    use crate::node_contact_state::{
+        NodeContactState,
+        NodeState,
+    };
+    use krpc_encoding::NodeID;
+    type Error = Box<dyn std::error::Error>;
+
+    #[test]
+    fn observe_emits_transitions() -> Result<(), Error> {
+        let events = RoutingEvents::new();
+        let mut receiver = events.subscribe();
+
+        let mut node = NodeContactState::new(NodeID::random(), "127.0.0.1:3000".parse()?);
+        events.observe(&mut node);
+        assert!(receiver.try_next().is_err());
+
+        node.mark_successful_query();
+        events.observe(&mut node);
+
+        assert_eq!(
+            receiver.try_next()?,
+            Some(RoutingEvent::NodeStateChanged {
+                id: node.id.clone(),
+                old: NodeState::Questionable,
+                new: NodeState::Good,
+            })
+        );
+
+        Ok(())
+    }
+}
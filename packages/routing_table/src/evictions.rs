@@ -0,0 +1,77 @@
+use crate::node_contact_state::NodeContactState;
+use chrono::NaiveDateTime;
+use krpc_encoding::NodeID;
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{
+        Mutex,
+        PoisonError,
+    },
+};
+
+/// Number of evictions remembered. Older evictions are forgotten first.
+const EVICTION_LOG_SIZE: usize = 64;
+
+/// A node removed from the routing table for being bad.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvictionRecord {
+    pub id: NodeID,
+    pub address: SocketAddr,
+
+    /// Time the node was first discovered.
+    pub first_seen: NaiveDateTime,
+
+    /// Last time the node was heard from, if ever.
+    pub last_contacted: Option<NaiveDateTime>,
+
+    /// Number of queries in a row the node failed to respond to.
+    pub failed_queries: u8,
+}
+
+/// A fixed size log of the most recent evictions. Records are pushed whole,
+/// so the log stays usable after a thread panics while holding its lock.
+pub struct EvictionLog {
+    records: Mutex<VecDeque<EvictionRecord>>,
+}
+
+impl EvictionLog {
+    pub fn new() -> EvictionLog {
+        EvictionLog {
+            records: Mutex::new(VecDeque::with_capacity(EVICTION_LOG_SIZE)),
+        }
+    }
+
+    /// Records the eviction of `node`, forgetting the oldest eviction if the
+    /// log is full.
+    pub fn record(&self, node: &NodeContactState) {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        if records.len() >= EVICTION_LOG_SIZE {
+            records.pop_front();
+        }
+
+        records.push_back(EvictionRecord {
+            id: node.id.clone(),
+            address: node.address,
+            first_seen: node.first_seen(),
+            last_contacted: node.last_contacted(),
+            failed_queries: node.failed_queries(),
+        });
+    }
+
+    /// Remembered evictions, oldest first.
+    pub fn recent(&self) -> Vec<EvictionRecord> {
+        self.records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for EvictionLog {
+    fn default() -> EvictionLog {
+        EvictionLog::new()
+    }
+}
@@ -10,3 +10,51 @@ pub struct FullBTreeInnerNode<T> {
     pub left: FullBTreeNode<T>,
     pub right: FullBTreeNode<T>,
 }
+
+impl<T> FullBTreeNode<T> {
+    /// Iterates over the leaves of the tree from left to right.
+    pub fn leaves(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        match self {
+            FullBTreeNode::Leaf(value) => Box::new(std::iter::once(value)),
+            FullBTreeNode::Inner(inner) => {
+                Box::new(inner.left.leaves().chain(inner.right.leaves()))
+            }
+        }
+    }
+
+    /// Like [`FullBTreeNode::leaves`] but borrowing the leaves mutably.
+    pub fn leaves_mut(&mut self) -> Box<dyn Iterator<Item = &mut T> + '_> {
+        match self {
+            FullBTreeNode::Leaf(value) => Box::new(std::iter::once(value)),
+            FullBTreeNode::Inner(inner) => {
+                let inner = &mut **inner;
+                Box::new(inner.left.leaves_mut().chain(inner.right.leaves_mut()))
+            }
+        }
+    }
+
+    /// Iterates over the leaves of the tree from left to right along with the
+    /// path taken to reach each leaf. Taking the left branch is `true`.
+    pub fn leaves_with_path(&self) -> Box<dyn Iterator<Item = (Vec<bool>, &T)> + '_> {
+        match self {
+            FullBTreeNode::Leaf(value) => Box::new(std::iter::once((Vec::new(), value))),
+            FullBTreeNode::Inner(inner) => Box::new(
+                inner
+                    .left
+                    .leaves_with_path()
+                    .map(|(path, value)| (prepend(true, path), value))
+                    .chain(
+                        inner
+                            .right
+                            .leaves_with_path()
+                            .map(|(path, value)| (prepend(false, path), value)),
+                    ),
+            ),
+        }
+    }
+}
+
+fn prepend(bit: bool, mut path: Vec<bool>) -> Vec<bool> {
+    path.insert(0, bit);
+    path
+}
@@ -7,7 +7,15 @@ use crate::{
         NodeContactState,
         NodeState,
     },
-    transport::LivenessTransport,
+    transport::{
+        LivenessTransport,
+        Transport,
+    },
+};
+use chrono::{
+    Duration,
+    NaiveDateTime,
+    Utc,
 };
 use krpc_encoding::{
     NodeID,
@@ -19,22 +27,53 @@ use log::{
 };
 use std::cmp::Ordering;
 
-const K_BUCKET_SIZE: usize = 8;
+/// Default maximum number of nodes in a bucket.
+pub const K_BUCKET_SIZE: usize = 8;
 
 /// A bucket which holds a maximum of `k` nodes.
 pub struct KBucket {
     contacts: Vec<NodeContactState>,
     leaf_type: LeafType,
+    k: usize,
+
+    /// Grace period given to nodes added to the bucket. `None` keeps the
+    /// default of [`NodeContactState`].
+    questionable_after: Option<Duration>,
+
+    /// Last time a node was added to the bucket or the bucket was created by
+    /// a split. Buckets which haven't changed in a while need refreshing.
+    last_changed: NaiveDateTime,
 }
 
 impl KBucket {
-    pub fn initial() -> KBucket {
+    pub fn initial(k: usize) -> KBucket {
         KBucket {
             contacts: Vec::new(),
             leaf_type: LeafType::Near,
+            k,
+            questionable_after: None,
+            last_changed: Utc::now().naive_utc(),
+        }
+    }
+
+    /// Maximum number of nodes in the bucket.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Sets how long after the last contact a good node becomes questionable
+    /// for every node in the bucket and every node added later.
+    pub fn set_questionable_after(&mut self, questionable_after: Duration) {
+        self.questionable_after = Some(questionable_after);
+        for contact in &mut self.contacts {
+            contact.set_questionable_after(questionable_after);
         }
     }
 
+    pub fn last_changed(&self) -> NaiveDateTime {
+        self.last_changed
+    }
+
     pub fn get_node_index(&self, node_id: &NodeID) -> Option<usize> {
         self.contacts
             .iter()
@@ -47,6 +86,10 @@ impl KBucket {
         &mut self.contacts[index]
     }
 
+    pub fn contacts(&self) -> impl Iterator<Item = &NodeContactState> {
+        self.contacts.iter()
+    }
+
     pub fn good_nodes(&self) -> impl Iterator<Item = NodeInfo> + '_ {
         self.contacts
             .iter()
@@ -96,14 +139,18 @@ impl KBucket {
 
     /// Returns true if there definitely is space in the bucket.
     pub fn definitely_has_remaining_space(&self) -> bool {
-        self.contacts.len() < K_BUCKET_SIZE
+        self.contacts.len() < self.k
     }
 
     fn add_node(&mut self, node_info: &NodeInfo) -> usize {
-        let node_contact_state =
+        let mut node_contact_state =
             NodeContactState::new(node_info.node_id.clone(), node_info.address);
+        if let Some(questionable_after) = self.questionable_after {
+            node_contact_state.set_questionable_after(questionable_after);
+        }
 
         self.contacts.push(node_contact_state);
+        self.last_changed = Utc::now().naive_utc();
         let len = self.contacts.len();
         len - 1
     }
@@ -111,10 +158,10 @@ impl KBucket {
     /// Try to add node to this bucket. If the bucket is full, first tries to
     /// evict bad nodes then tries to evict questionable nodes. If all fails,
     /// returns control to the caller to handle splitting the bucket.
-    pub async fn try_add(
+    pub async fn try_add<T: Transport>(
         &mut self,
         node_info: &NodeInfo,
-        transport: &LivenessTransport,
+        transport: &LivenessTransport<T>,
     ) -> Option<usize> {
         // It is necessary to split this into a check and then a separate get
         // which does not borrow self because of limitations in the borrow
@@ -131,7 +178,8 @@ impl KBucket {
         }
 
         // evict a bad node to make space
-        if let Some(_) = self.take_bad_node() {
+        if let Some(bad_node) = self.take_bad_node() {
+            transport.evictions().record(&bad_node);
             return Some(self.add_node(node_info));
         }
 
@@ -154,29 +202,41 @@ impl KBucket {
         None
     }
 
+    /// Splits the bucket in two by the bit at `depth` of each contact's id.
+    ///
+    /// Returns the bucket holding contacts with the bit set followed by the
+    /// bucket holding contacts with the bit unset, matching the order of the
+    /// branches used when looking up ids.
     pub fn split(&mut self, owner_id: &NodeID, depth: usize) -> (KBucket, KBucket) {
-        let (zero_bit_nodes, one_bit_nodes) = self
+        let (one_bit_nodes, zero_bit_nodes) = self
             .contacts
             .drain(..)
-            .partition(|node| !node.id.nth_bit(depth));
+            .partition(|node| node.id.nth_bit(depth));
         let owner_is_one_bit = owner_id.nth_bit(depth);
+        let now = Utc::now().naive_utc();
 
         (
             KBucket {
-                contacts: zero_bit_nodes,
+                contacts: one_bit_nodes,
                 leaf_type: if owner_is_one_bit {
-                    LeafType::Far
-                } else {
                     LeafType::Near
+                } else {
+                    LeafType::Far
                 },
+                k: self.k,
+                questionable_after: self.questionable_after,
+                last_changed: now,
             },
             KBucket {
-                contacts: one_bit_nodes,
+                contacts: zero_bit_nodes,
                 leaf_type: if owner_is_one_bit {
-                    LeafType::Near
-                } else {
                     LeafType::Far
+                } else {
+                    LeafType::Near
                 },
+                k: self.k,
+                questionable_after: self.questionable_after,
+                last_changed: now,
             },
         )
     }
@@ -192,9 +252,9 @@ impl KBucket {
     /// * `Some(true)` if a node was evicted.
     /// * `Some(false)` if pinging the node resulted in the node going from
     ///   questionable to good
-    pub async fn evict_questionable_node(
+    pub async fn evict_questionable_node<T: Transport>(
         &mut self,
-        request_transport: &LivenessTransport,
+        request_transport: &LivenessTransport<T>,
     ) -> Option<bool> {
         let mut questionable_node = self.take_questionable_node()?;
 
@@ -212,7 +272,10 @@ impl KBucket {
                 self.contacts.push(questionable_node);
                 Some(false)
             }
-            NodeState::Bad => Some(true),
+            NodeState::Bad => {
+                request_transport.evictions().record(&questionable_node);
+                Some(true)
+            }
         }
     }
 }
@@ -261,9 +324,11 @@ mod tests {
         k_bucket::{
             KBucket,
             LeafType,
+            K_BUCKET_SIZE,
         },
         node_contact_state::NodeContactState,
     };
+    use chrono::Utc;
     use krpc_encoding::NodeID;
     type Error = Box<dyn std::error::Error>;
 
@@ -287,6 +352,9 @@ mod tests {
         let mut contacts = KBucket {
             contacts: vec![questionable_node, bad_node],
             leaf_type: LeafType::Near,
+            k: K_BUCKET_SIZE,
+            questionable_after: None,
+            last_changed: Utc::now().naive_utc(),
         };
 
         assert_eq!(
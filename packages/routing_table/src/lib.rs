@@ -4,11 +4,42 @@
 #![feature(generators, generator_trait)]
 #![feature(error_generic_member_access, provide_any)]
 
+mod bucket_view;
+mod events;
+mod evictions;
 mod full_b_tree;
 mod generator;
 mod k_bucket;
 mod node_contact_state;
+mod persistence;
 mod routing_table;
+mod snapshot;
+#[cfg(test)]
+mod testing;
 mod transport;
 
-pub use crate::routing_table::RoutingTable;
+pub use crate::{
+    bucket_view::{
+        BucketView,
+        ContactView,
+    },
+    events::RoutingEvent,
+    evictions::EvictionRecord,
+    node_contact_state::NodeState,
+    persistence::errors as persistence_errors,
+    routing_table::{
+        RoutingTable,
+        DEFAULT_BOOTSTRAP_ALPHA,
+    },
+    snapshot::{
+        diff,
+        RoutingDiff,
+        RoutingSnapshot,
+        SnapshotEntry,
+        StateChange,
+    },
+    transport::{
+        errors as transport_errors,
+        Transport,
+    },
+};
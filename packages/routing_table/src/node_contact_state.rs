@@ -1,14 +1,22 @@
 use chrono::{
+    Duration,
     NaiveDateTime,
     Utc,
 };
 use krpc_encoding::NodeID;
-use std::net::SocketAddrV4;
+use std::net::SocketAddr;
+
+/// Minutes without contact before a good node becomes questionable, unless
+/// configured otherwise.
+const DEFAULT_QUESTIONABLE_AFTER: i64 = 15;
 
 pub struct NodeContactState {
     pub id: NodeID,
 
-    pub address: SocketAddrV4,
+    pub address: SocketAddr,
+
+    /// Time this node was first discovered.
+    first_seen: NaiveDateTime,
 
     /// Last time a successful query was made to this node.
     last_successful_query_to: Option<NaiveDateTime>,
@@ -18,23 +26,75 @@ pub struct NodeContactState {
 
     /// Number of failed queries to the node since [`last_successful_query_to`].
     failed_queries: u8,
+
+    /// State of the node as of the last call to [`refresh_state`].
+    last_state: NodeState,
+
+    /// How long after the last contact a good node becomes questionable.
+    questionable_after: Duration,
 }
 
 impl NodeContactState {
-    pub fn new(id: NodeID, address: SocketAddrV4) -> Self {
+    pub fn new(id: NodeID, address: SocketAddr) -> Self {
         NodeContactState {
             id,
             address,
+            first_seen: Utc::now().naive_utc(),
             last_successful_query_to: None,
             last_request_from: None,
             failed_queries: 0,
+            last_state: NodeState::Questionable,
+            questionable_after: Duration::minutes(DEFAULT_QUESTIONABLE_AFTER),
         }
     }
 
+    /// Restores a node from previously recorded timestamps, such as when
+    /// loading a saved routing table.
+    pub(crate) fn from_parts(
+        id: NodeID,
+        address: SocketAddr,
+        first_seen: NaiveDateTime,
+        last_successful_query_to: Option<NaiveDateTime>,
+        last_request_from: Option<NaiveDateTime>,
+        failed_queries: u8,
+    ) -> Self {
+        let mut node = NodeContactState {
+            id,
+            address,
+            first_seen,
+            last_successful_query_to,
+            last_request_from,
+            failed_queries,
+            last_state: NodeState::Questionable,
+            questionable_after: Duration::minutes(DEFAULT_QUESTIONABLE_AFTER),
+        };
+        node.last_state = node.state();
+
+        node
+    }
+
+    pub fn first_seen(&self) -> NaiveDateTime {
+        self.first_seen
+    }
+
     pub fn failed_queries(&self) -> u8 {
         self.failed_queries
     }
 
+    pub fn last_successful_query_to(&self) -> Option<NaiveDateTime> {
+        self.last_successful_query_to
+    }
+
+    pub fn last_request_from(&self) -> Option<NaiveDateTime> {
+        self.last_request_from
+    }
+
+    /// Sets how long after the last contact a good node becomes questionable.
+    /// Defaults to 15 minutes.
+    pub fn set_questionable_after(&mut self, questionable_after: Duration) {
+        self.questionable_after = questionable_after;
+    }
+
     /// Update internal state to reflect a successful query happened.
     pub fn mark_successful_query(&mut self) {
         self.failed_queries = 0;
@@ -61,12 +121,12 @@ impl NodeContactState {
 
         match (self.last_request_from, self.last_successful_query_to) {
             (_, Some(last_request_to))
-                if now.signed_duration_since(last_request_to).num_minutes() < 15 =>
+                if now.signed_duration_since(last_request_to) < self.questionable_after =>
             {
                 NodeState::Good
             }
             (Some(last_request_from), Some(..))
-                if now.signed_duration_since(last_request_from).num_minutes() < 15 =>
+                if now.signed_duration_since(last_request_from) < self.questionable_after =>
             {
                 NodeState::Good
             }
@@ -74,6 +134,19 @@ impl NodeContactState {
         }
     }
 
+    /// Recomputes the state of the node. If the state changed since the last
+    /// call, returns the previous and current state.
+    pub fn refresh_state(&mut self) -> Option<(NodeState, NodeState)> {
+        let state = self.state();
+        if state == self.last_state {
+            return None;
+        }
+
+        let old_state = std::mem::replace(&mut self.last_state, state);
+
+        Some((old_state, state))
+    }
+
     pub fn last_contacted(&self) -> Option<NaiveDateTime> {
         match (self.last_request_from, self.last_successful_query_to) {
             (Some(last_request_from), Some(last_request_to)) => {
@@ -95,11 +168,13 @@ impl NodeContactState {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum NodeState {
     /// A good node is a node has responded to one of our queries within the
     /// last 15 minutes. A node is also good if it has ever responded to one
     /// of our queries and has sent us a query within the last 15 minutes.
+    /// The 15 minutes can be changed with
+    /// [`RoutingTable::with_questionable_after`](crate::RoutingTable::with_questionable_after).
     Good,
 
     /// After 15 minutes of inactivity, a node becomes questionable.
@@ -116,16 +191,18 @@ mod tests {
     use super::{
         NodeContactState,
         NodeState,
+        DEFAULT_QUESTIONABLE_AFTER,
     };
     use chrono::{
         prelude::*,
         Duration,
     };
+    use krpc_encoding::NodeID;
     type Error = Box<dyn std::error::Error>;
 
     fn make_node() -> Result<NodeContactState, Error> {
         Ok(NodeContactState::new(
-            b"0000000000000000000000000000000000000000".into(),
+            NodeID::from_hex(b"0000000000000000000000000000000000000000")?,
             "127.0.0.1:3000".parse()?,
         ))
     }
@@ -139,6 +216,18 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn first_seen_on_creation() -> Result<(), Error> {
+        let before = Utc::now().naive_utc();
+        let node = make_node()?;
+        let after = Utc::now().naive_utc();
+
+        assert!(node.first_seen() >= before);
+        assert!(node.first_seen() <= after);
+
+        Ok(())
+    }
+
     #[test]
     fn good_state_request() -> Result<(), Error> {
         let mut node = make_node()?;
@@ -171,6 +260,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn refresh_state_transitions() -> Result<(), Error> {
+        let mut node = make_node()?;
+        assert_eq!(node.refresh_state(), None);
+
+        node.mark_successful_query();
+        assert_eq!(
+            node.refresh_state(),
+            Some((NodeState::Questionable, NodeState::Good))
+        );
+        assert_eq!(node.refresh_state(), None);
+
+        node.mark_failed_query();
+        node.mark_failed_query();
+        assert_eq!(
+            node.refresh_state(),
+            Some((NodeState::Good, NodeState::Bad))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn request_response_good() -> Result<(), Error> {
         let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)
@@ -179,11 +290,14 @@ mod tests {
             .unwrap();
 
         let node = NodeContactState {
-            id: b"0000000000000000000000000000000000000000".into(),
+            id: NodeID::from_hex(b"0000000000000000000000000000000000000000")?,
             address: "127.0.0.1:3000".parse()?,
+            first_seen: Utc::now().naive_utc(),
             last_successful_query_to: Some(epoch),
             last_request_from: Some(Utc::now().naive_utc() - Duration::minutes(10)),
             failed_queries: 0,
+            last_state: NodeState::Questionable,
+            questionable_after: Duration::minutes(DEFAULT_QUESTIONABLE_AFTER),
         };
 
         assert_eq!(node.state(), NodeState::Good);
@@ -191,14 +305,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn custom_questionable_after() -> Result<(), Error> {
+        let mut node = make_node()?;
+        node.last_successful_query_to = Some(Utc::now().naive_utc() - Duration::minutes(20));
+        assert_eq!(node.state(), NodeState::Questionable);
+
+        node.set_questionable_after(Duration::minutes(30));
+        assert_eq!(node.state(), NodeState::Good);
+
+        node.set_questionable_after(Duration::minutes(10));
+        assert_eq!(node.state(), NodeState::Questionable);
+
+        Ok(())
+    }
+
     #[test]
     fn last_contacted_none() -> Result<(), Error> {
         let node = NodeContactState {
-            id: b"0000000000000000000000000000000000000000".into(),
+            id: NodeID::from_hex(b"0000000000000000000000000000000000000000")?,
             address: "127.0.0.1:3000".parse()?,
+            first_seen: Utc::now().naive_utc(),
             last_successful_query_to: None,
             last_request_from: None,
             failed_queries: 0,
+            last_state: NodeState::Questionable,
+            questionable_after: Duration::minutes(DEFAULT_QUESTIONABLE_AFTER),
         };
 
         assert_eq!(node.last_contacted(), None);
@@ -214,11 +346,14 @@ mod tests {
             .unwrap();
 
         let node = NodeContactState {
-            id: b"0000000000000000000000000000000000000000".into(),
+            id: NodeID::from_hex(b"0000000000000000000000000000000000000000")?,
             address: "127.0.0.1:3000".parse()?,
+            first_seen: Utc::now().naive_utc(),
             last_successful_query_to: Some(epoch),
             last_request_from: None,
             failed_queries: 0,
+            last_state: NodeState::Questionable,
+            questionable_after: Duration::minutes(DEFAULT_QUESTIONABLE_AFTER),
         };
 
         assert_eq!(node.last_contacted(), Some(epoch));
@@ -234,11 +369,14 @@ mod tests {
             .unwrap();
 
         let node = NodeContactState {
-            id: b"0000000000000000000000000000000000000000".into(),
+            id: NodeID::from_hex(b"0000000000000000000000000000000000000000")?,
             address: "127.0.0.1:3000".parse()?,
+            first_seen: Utc::now().naive_utc(),
             last_successful_query_to: None,
             last_request_from: Some(epoch),
             failed_queries: 0,
+            last_state: NodeState::Questionable,
+            questionable_after: Duration::minutes(DEFAULT_QUESTIONABLE_AFTER),
         };
 
         assert_eq!(node.last_contacted(), Some(epoch));
@@ -258,11 +396,14 @@ mod tests {
             .unwrap();
 
         let node = NodeContactState {
-            id: b"0000000000000000000000000000000000000000".into(),
+            id: NodeID::from_hex(b"0000000000000000000000000000000000000000")?,
             address: "127.0.0.1:3000".parse()?,
+            first_seen: Utc::now().naive_utc(),
             last_successful_query_to: Some(earlier),
             last_request_from: Some(later),
             failed_queries: 0,
+            last_state: NodeState::Questionable,
+            questionable_after: Duration::minutes(DEFAULT_QUESTIONABLE_AFTER),
         };
 
         assert_eq!(node.last_contacted(), Some(later));
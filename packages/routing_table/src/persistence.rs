@@ -0,0 +1,224 @@
+//! Compact binary format used by [`RoutingTable::save`] and
+//! [`RoutingTable::load`].
+//!
+//! A header of a magic string, a format version and a big-endian `u32` count
+//! of nodes is followed by one entry per node:
+//!
+//! * the 20 byte node id
+//! * the address family, `4` or `6`, then the 4 or 16 byte IP and a 2 byte port
+//! * a byte of flags marking which of the optional timestamps follow
+//! * the time the node was first seen, then the time of the last successful
+//!   query to it and the last request from it when present, each as big-endian
+//!   `i64` milliseconds since the unix epoch
+//! * the number of failed queries since the last successful one
+//!
+//! [`RoutingTable::save`]: crate::RoutingTable::save
+//! [`RoutingTable::load`]: crate::RoutingTable::load
+
+use self::errors::{
+    ErrorKind,
+    Result,
+};
+use crate::node_contact_state::NodeContactState;
+use chrono::NaiveDateTime;
+use krpc_encoding::NodeID;
+use std::{
+    io::{
+        Read,
+        Write,
+    },
+    net::{
+        IpAddr,
+        Ipv4Addr,
+        Ipv6Addr,
+        SocketAddr,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"DHRT";
+
+const VERSION: u8 = 1;
+
+const HAS_LAST_SUCCESSFUL_QUERY_TO: u8 = 0b01;
+const HAS_LAST_REQUEST_FROM: u8 = 0b10;
+
+pub fn write_header<W: Write>(mut w: W, count: u32) -> Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&[VERSION])?;
+    w.write_all(&count.to_be_bytes())?;
+
+    Ok(())
+}
+
+/// Reads the header, returning the number of entries which follow it.
+pub fn read_header<R: Read>(mut r: R) -> Result<u32> {
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(ErrorKind::InvalidMagic)?;
+    }
+
+    let version = read_u8(&mut r)?;
+    if version != VERSION {
+        return Err(ErrorKind::UnsupportedVersion { version })?;
+    }
+
+    let mut count = [0u8; 4];
+    r.read_exact(&mut count)?;
+
+    Ok(u32::from_be_bytes(count))
+}
+
+pub fn write_contact<W: Write>(mut w: W, contact: &NodeContactState) -> Result<()> {
+    w.write_all(&contact.id.as_bytes())?;
+
+    match contact.address {
+        SocketAddr::V4(addr) => {
+            w.write_all(&[4])?;
+            w.write_all(&addr.ip().octets())?;
+        }
+        SocketAddr::V6(addr) => {
+            w.write_all(&[6])?;
+            w.write_all(&addr.ip().octets())?;
+        }
+    };
+    w.write_all(&contact.address.port().to_be_bytes())?;
+
+    let last_successful_query_to = contact.last_successful_query_to();
+    let last_request_from = contact.last_request_from();
+
+    let mut flags = 0;
+    if last_successful_query_to.is_some() {
+        flags |= HAS_LAST_SUCCESSFUL_QUERY_TO;
+    }
+    if last_request_from.is_some() {
+        flags |= HAS_LAST_REQUEST_FROM;
+    }
+    w.write_all(&[flags])?;
+
+    let timestamps = Some(contact.first_seen())
+        .into_iter()
+        .chain(last_successful_query_to)
+        .chain(last_request_from);
+    for timestamp in timestamps {
+        w.write_all(&timestamp.timestamp_millis().to_be_bytes())?;
+    }
+
+    w.write_all(&[contact.failed_queries()])?;
+
+    Ok(())
+}
+
+pub fn read_contact<R: Read>(mut r: R) -> Result<NodeContactState> {
+    let mut id = [0u8; 20];
+    r.read_exact(&mut id)?;
+
+    let ip: IpAddr = match read_u8(&mut r)? {
+        4 => {
+            let mut octets = [0u8; 4];
+            r.read_exact(&mut octets)?;
+            Ipv4Addr::from(octets).into()
+        }
+        6 => {
+            let mut octets = [0u8; 16];
+            r.read_exact(&mut octets)?;
+            Ipv6Addr::from(octets).into()
+        }
+        family => return Err(ErrorKind::InvalidAddressFamily { family })?,
+    };
+
+    let mut port = [0u8; 2];
+    r.read_exact(&mut port)?;
+
+    let flags = read_u8(&mut r)?;
+    let first_seen = read_timestamp(&mut r)?;
+    let last_successful_query_to = if flags & HAS_LAST_SUCCESSFUL_QUERY_TO != 0 {
+        Some(read_timestamp(&mut r)?)
+    } else {
+        None
+    };
+    let last_request_from = if flags & HAS_LAST_REQUEST_FROM != 0 {
+        Some(read_timestamp(&mut r)?)
+    } else {
+        None
+    };
+
+    let failed_queries = read_u8(&mut r)?;
+
+    Ok(NodeContactState::from_parts(
+        NodeID::from(id),
+        SocketAddr::new(ip, u16::from_be_bytes(port)),
+        first_seen,
+        last_successful_query_to,
+        last_request_from,
+        failed_queries,
+    ))
+}
+
+fn read_u8<R: Read>(mut r: R) -> Result<u8> {
+    let mut byte = [0u8; 1];
+    r.read_exact(&mut byte)?;
+
+    Ok(byte[0])
+}
+
+fn read_timestamp<R: Read>(mut r: R) -> Result<NaiveDateTime> {
+    let mut millis = [0u8; 8];
+    r.read_exact(&mut millis)?;
+    let millis = i64::from_be_bytes(millis);
+
+    Ok(NaiveDateTime::from_timestamp_millis(millis)
+        .ok_or(ErrorKind::InvalidTimestamp { millis })?)
+}
+
+pub mod errors {
+    use std::{
+        backtrace::Backtrace,
+        io,
+    };
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum ErrorKind {
+        #[error("failed to read or write routing table")]
+        Io {
+            #[from]
+            cause: io::Error,
+        },
+
+        #[error("not a saved routing table")]
+        InvalidMagic,
+
+        #[error("unsupported routing table format version {version}")]
+        UnsupportedVersion { version: u8 },
+
+        #[error("invalid address family {family}")]
+        InvalidAddressFamily { family: u8 },
+
+        #[error("timestamp {millis} out of range")]
+        InvalidTimestamp { millis: i64 },
+    }
+
+    pub type Result<T> = std::result::Result<T, Error>;
+
+    #[derive(Error, Debug)]
+    #[error("{}", inner)]
+    pub struct Error {
+        #[from]
+        inner: ErrorKind,
+
+        backtrace: Backtrace,
+    }
+
+    impl Error {
+        pub fn kind(&self) -> &ErrorKind {
+            &self.inner
+        }
+    }
+
+    impl From<io::Error> for Error {
+        fn from(cause: io::Error) -> Self {
+            ErrorKind::Io { cause }.into()
+        }
+    }
+}
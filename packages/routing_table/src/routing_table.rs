@@ -1,84 +1,219 @@
 use crate::{
+    bucket_view::BucketView,
+    events::RoutingEvent,
+    evictions::EvictionRecord,
     full_b_tree::FullBTreeNode,
     generator::GeneratorExt,
-    k_bucket::KBucket,
+    k_bucket::{
+        KBucket,
+        K_BUCKET_SIZE,
+    },
     node_contact_state::NodeContactState,
-    transport::LivenessTransport,
+    persistence::{
+        self,
+        errors::Result as PersistenceResult,
+    },
+    snapshot::{
+        RoutingSnapshot,
+        SnapshotEntry,
+    },
+    transport::{
+        LivenessTransport,
+        Transport,
+    },
 };
 use async_recursion::async_recursion;
+use chrono::{
+    Duration,
+    NaiveDateTime,
+    Utc,
+};
+use futures::{
+    future,
+    stream::FuturesUnordered,
+    Stream,
+    StreamExt,
+};
 use krpc_encoding::{
     NodeID,
     NodeInfo,
     NODE_ID_SIZE_BITS,
 };
 use log::{
+    as_display,
     as_error,
     debug,
 };
+use num_bigint::BigUint;
 use std::{
-    collections::{
-        HashSet,
-        VecDeque,
+    collections::HashSet,
+    io::{
+        Read,
+        Write,
+    },
+    net::{
+        SocketAddr,
+        SocketAddrV4,
     },
-    net::SocketAddrV4,
+    ops::Deref,
 };
-use tokio_krpc::RequestTransport;
+use tokio_krpc::{
+    responses::FindNodeResponse,
+    RequestTransport,
+};
+
+/// Minutes a bucket can go without changing before it needs refreshing.
+const BUCKET_REFRESH_INTERVAL: i64 = 15;
+
+/// Number of `find_node` queries in flight at once while bootstrapping, the
+/// alpha parameter from Kademlia.
+pub const DEFAULT_BOOTSTRAP_ALPHA: usize = 3;
 
 /// A routing table which holds information about nodes in the network.
-pub struct RoutingTable {
+/// Queries other nodes through `T`, the real network by default.
+pub struct RoutingTable<T = RequestTransport> {
     id: NodeID,
     root: FullBTreeNode<KBucket>,
-    transport: LivenessTransport,
+    transport: LivenessTransport<T>,
+
+    /// Maximum number of nodes in each bucket and number of nodes returned
+    /// by [`RoutingTable::find_node`].
+    k: usize,
 }
 
-impl RoutingTable {
-    pub fn new(id: NodeID, request_transport: RequestTransport) -> RoutingTable {
+impl<T: Transport> RoutingTable<T> {
+    pub fn new(id: NodeID, transport: T) -> RoutingTable<T> {
+        Self::with_k(id, transport, K_BUCKET_SIZE)
+    }
+
+    /// Like [`RoutingTable::new`] but holding up to `k` nodes in each bucket
+    /// instead of the usual 8.
+    pub fn with_k(id: NodeID, transport: T, k: usize) -> RoutingTable<T> {
         RoutingTable {
             id,
-            root: FullBTreeNode::Leaf(KBucket::initial()),
-            transport: LivenessTransport::new(request_transport),
+            root: FullBTreeNode::Leaf(KBucket::initial(k)),
+            transport: LivenessTransport::new(transport),
+            k,
         }
     }
 
+    /// Re-sends pings to nodes up to `retries` times before counting them as
+    /// failed. Zero, the default, gives up after the first failure.
+    pub fn with_ping_retries(mut self, retries: usize) -> RoutingTable<T> {
+        self.transport = self.transport.with_retries(retries);
+        self
+    }
+
+    /// Keeps nodes good for `questionable_after` since they were last heard
+    /// from instead of the usual 15 minutes. A longer grace period means
+    /// fewer liveness pings to stable nodes at the cost of noticing nodes
+    /// going offline later.
+    pub fn with_questionable_after(mut self, questionable_after: Duration) -> RoutingTable<T> {
+        for bucket in self.root.leaves_mut() {
+            bucket.set_questionable_after(questionable_after);
+        }
+
+        self
+    }
+
+    /// Fills the routing table starting from the node at `address`, querying
+    /// up to [`DEFAULT_BOOTSTRAP_ALPHA`] nodes at once. See
+    /// [`RoutingTable::bootstrap_with_alpha`].
     pub async fn bootstrap(&mut self, address: SocketAddrV4) {
-        let mut nodes = VecDeque::from([address]);
-        let mut visited = HashSet::new();
-        visited.insert(address);
+        self.bootstrap_with_alpha(address, DEFAULT_BOOTSTRAP_ALPHA)
+            .await
+    }
 
-        while let Some(next_node) = nodes.pop_front() {
-            let result = self.transport.find_node(next_node.clone(), self.id.clone()).await;
+    /// Fills the routing table with an iterative Kademlia lookup of our own
+    /// id starting from the node at `address`.
+    ///
+    /// Each round sends `find_node` queries at once to the `alpha` closest
+    /// nodes seen so far which haven't been queried yet, out of the `k`
+    /// closest. The lookup stops once the `k` closest nodes seen have all been
+    /// queried, which happens once rounds stop turning up closer nodes. Nodes
+    /// which respond are added to the routing table. Values of `alpha` below
+    /// one are treated as one.
+    pub async fn bootstrap_with_alpha(&mut self, address: SocketAddrV4, alpha: usize) {
+        let alpha = alpha.max(1);
+        let address = SocketAddr::from(address);
+        let mut candidates: Vec<NodeInfo> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queried = HashSet::new();
+        seen.insert(address);
 
-            match result {
-                Err(err) => {
-                    debug!(err = as_error!(err); "find_node failed during bootstrap");
-                }
-                Ok(response) => {
-                    // add to routing table
-                    match self
-                        .add_node(&NodeInfo {
-                            address,
-                            node_id: response.id.clone(),
-                        })
-                        .await
-                    {
-                        Some(it) => it.mark_successful_query(),
-                        None => {
-                            // todo: how to know when to stop
-                            return;
-                        }
+        let mut round = vec![address];
+        while !round.is_empty() {
+            queried.extend(round.iter().cloned());
+            let responses = self.find_node_round(round).await;
+
+            for (address, response) in responses {
+                let transport = &self.transport;
+                match Self::add_node_rec(
+                    &self.id,
+                    transport,
+                    &mut self.root,
+                    &NodeInfo::new(response.id, address),
+                    0,
+                )
+                .await
+                {
+                    Some(it) => {
+                        it.mark_successful_query();
+                        transport.events().observe(it);
                     }
+                    None => {
+                        debug!(address = as_display!(address); "no space for node during bootstrap");
+                    }
+                }
 
-                    for node in response.nodes {
-                        if !visited.contains(&node.address) {
-                            visited.insert(node.address.clone());
-                            nodes.push_back(node.address);
-                        }
+                for node in response.nodes {
+                    if seen.insert(node.address) {
+                        candidates.push(node);
                     }
                 }
             }
+
+            let id = &self.id;
+            candidates.sort_by_cached_key(|node| node.node_id.deref() ^ id.deref());
+
+            round = candidates
+                .iter()
+                .take(self.k)
+                .filter(|node| !queried.contains(&node.address))
+                .take(alpha)
+                .map(|node| node.address)
+                .collect();
         }
     }
 
+    /// Sends `find_node` queries for our own id to every one of `addresses`
+    /// at once. Returns the responses of the nodes which answered.
+    async fn find_node_round(
+        &self,
+        addresses: Vec<SocketAddr>,
+    ) -> Vec<(SocketAddr, FindNodeResponse)> {
+        addresses
+            .into_iter()
+            .map(|address| async move {
+                (
+                    address,
+                    self.transport.find_node(address, self.id.clone()).await,
+                )
+            })
+            .collect::<FuturesUnordered<_>>()
+            .filter_map(|(address, result)| {
+                future::ready(match result {
+                    Ok(response) => Some((address, response)),
+                    Err(err) => {
+                        debug!(err = as_error!(err); "find_node failed during bootstrap");
+                        None
+                    }
+                })
+            })
+            .collect()
+            .await
+    }
+
     /// Tries to add a node to the routing table, evicting nodes which have
     /// gone offline and growing the routing table as needed.
     ///
@@ -87,6 +222,119 @@ impl RoutingTable {
         Self::add_node_rec(&self.id, &self.transport, &mut self.root, node_info, 0).await
     }
 
+    /// Subscribes to changes in the routing table from this point on.
+    pub fn events(&self) -> impl Stream<Item = RoutingEvent> {
+        self.transport.events().subscribe()
+    }
+
+    /// Nodes most recently evicted for being bad, oldest first. Only a fixed
+    /// number of evictions are remembered.
+    pub fn recent_evictions(&self) -> Vec<EvictionRecord> {
+        self.transport.evictions().recent()
+    }
+
+    /// Writes the id, address and contact timestamps of every node in the
+    /// routing table to `w` in a compact binary format, to be restored with
+    /// [`RoutingTable::load`] instead of bootstrapping from scratch.
+    pub fn save<W: Write>(&self, mut w: W) -> PersistenceResult<()> {
+        let contacts = self
+            .root
+            .leaves()
+            .flat_map(|bucket| bucket.contacts())
+            .collect::<Vec<&NodeContactState>>();
+
+        persistence::write_header(&mut w, contacts.len() as u32)?;
+        for contact in contacts {
+            persistence::write_contact(&mut w, contact)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a routing table written by [`RoutingTable::save`]. The tree is
+    /// rebuilt by adding the saved nodes one at a time. Nodes keep their
+    /// saved timestamps, so their states are worked out from when they were
+    /// last contacted rather than starting over as questionable.
+    pub async fn load<R: Read>(
+        mut r: R,
+        id: NodeID,
+        transport: T,
+    ) -> PersistenceResult<RoutingTable<T>> {
+        let mut routing_table = RoutingTable::new(id, transport);
+
+        let count = persistence::read_header(&mut r)?;
+        for _ in 0..count {
+            let contact = persistence::read_contact(&mut r)?;
+            let node_info = NodeInfo::new(contact.id.clone(), contact.address);
+
+            match routing_table.add_node(&node_info).await {
+                Some(slot) => *slot = contact,
+                None => debug!(id = as_display!(contact.id); "no space for saved node"),
+            }
+        }
+
+        Ok(routing_table)
+    }
+
+    /// Captures the id, address and state of every node in the routing table.
+    pub fn snapshot(&self) -> RoutingSnapshot {
+        self.root
+            .leaves()
+            .flat_map(|bucket| bucket.contacts())
+            .map(|contact| SnapshotEntry {
+                id: contact.id.clone(),
+                address: contact.address,
+                state: contact.state(),
+            })
+            .collect()
+    }
+
+    /// Read-only views of every bucket, ordered by the path to the bucket in
+    /// the tree. Useful for building custom maintenance on top of the table.
+    pub fn buckets(&self) -> impl Iterator<Item = BucketView<'_>> {
+        self.root
+            .leaves_with_path()
+            .map(|(prefix, bucket)| BucketView::new(prefix, bucket))
+    }
+
+    /// Picks a random id in the range of each bucket which hasn't changed in
+    /// [`BUCKET_REFRESH_INTERVAL`] minutes. Looking up these ids with
+    /// `find_node` refreshes the buckets, as described in [BEP-0005].
+    ///
+    /// [BEP-0005]: http://www.bittorrent.org/beps/bep_0005.html
+    pub fn buckets_needing_refresh(&self) -> Vec<NodeID> {
+        self.buckets_needing_refresh_at(Utc::now().naive_utc())
+    }
+
+    fn buckets_needing_refresh_at(&self, now: NaiveDateTime) -> Vec<NodeID> {
+        self.root
+            .leaves_with_path()
+            .filter(|(_, bucket)| {
+                now.signed_duration_since(bucket.last_changed())
+                    .num_minutes()
+                    >= BUCKET_REFRESH_INTERVAL
+            })
+            .map(|(prefix, _)| random_id_with_prefix(&prefix))
+            .collect()
+    }
+
+    /// Splits buckets along the path of our own id, regardless of how full
+    /// they are, until the bucket holding our own id is `prefix_bits` deep.
+    /// Contacts are redistributed into the new buckets by their ids.
+    ///
+    /// Lets tests build specific tree shapes without filling buckets.
+    #[cfg(test)]
+    pub fn force_split(&mut self, prefix_bits: usize) {
+        loop {
+            let (leaf, depth) = Self::find_bucket_mut_recursive(&mut self.root, &self.id, 0);
+            if depth >= prefix_bits {
+                return;
+            }
+
+            leaf.split(&self.id, depth);
+        }
+    }
+
     fn find_nodes_generator_rec(
         root: &FullBTreeNode<KBucket>,
         node_id: NodeID,
@@ -132,7 +380,7 @@ impl RoutingTable {
         let closest_nodes = self
             .find_nodes_generator(id.clone())
             .into_iter()
-            .take(8)
+            .take(self.k)
             .collect::<Vec<NodeInfo>>();
 
         match closest_nodes
@@ -169,7 +417,7 @@ impl RoutingTable {
     #[async_recursion(?Send)]
     async fn add_node_rec<'a>(
         owner_id: &NodeID,
-        transport: &LivenessTransport,
+        transport: &LivenessTransport<T>,
         root_node: &'a mut FullBTreeNode<KBucket>,
         node_info: &NodeInfo,
         starting_depth: usize,
@@ -211,7 +459,582 @@ impl RoutingTable {
     }
 }
 
+/// Picks a random id in the bucket at `prefix`, the path of bits taken to
+/// reach the bucket.
+fn random_id_with_prefix(prefix: &[bool]) -> NodeID {
+    let mut id = NodeID::random();
+    for (depth, bit) in prefix.iter().enumerate() {
+        if id.nth_bit(depth) != *bit {
+            id = NodeID::new(id.deref() ^ &(BigUint::from(1u8) << depth));
+        }
+    }
+
+    id
+}
+
 pub enum FindNodeResult {
     Node(NodeInfo),
     Nodes(Vec<NodeInfo>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FindNodeResult,
+        RoutingTable,
+    };
+    use crate::{
+        k_bucket::K_BUCKET_SIZE,
+        node_contact_state::NodeContactState,
+        testing::MockTransport,
+        NodeState,
+    };
+    use chrono::{
+        Duration,
+        Utc,
+    };
+    use futures::StreamExt;
+    use krpc_encoding::{
+        Envelope,
+        Message,
+        NodeID,
+        NodeInfo,
+        Response,
+    };
+    use num_bigint::BigUint;
+    use std::{
+        collections::HashSet,
+        net::{
+            Ipv4Addr,
+            Ipv6Addr,
+            SocketAddr,
+            SocketAddrV4,
+        },
+        ops::Deref,
+        sync::{
+            Arc,
+            Mutex,
+        },
+    };
+    use tokio::{
+        net::UdpSocket,
+        spawn,
+        time::timeout,
+    };
+    use tokio_krpc::{
+        KRPCNode,
+        RequestTransport,
+    };
+    type Error = Box<dyn std::error::Error>;
+
+    async fn make_transport(owner_id: NodeID) -> Result<RequestTransport, Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let (send_transport, _, _) = KRPCNode::new(socket).serve();
+
+        Ok(RequestTransport::new(owner_id, send_transport))
+    }
+
+    async fn make_routing_table(owner_id: NodeID) -> Result<RoutingTable, Error> {
+        Ok(RoutingTable::new(
+            owner_id.clone(),
+            make_transport(owner_id).await?,
+        ))
+    }
+
+    /// Ids of the fake nodes which received a query.
+    type Queried = Arc<Mutex<HashSet<NodeID>>>;
+
+    /// Answers every query to `socket` with `nodes` as the nodes closer to the
+    /// target, or ignores queries when `nodes` is `None`.
+    fn serve_fake_node(
+        socket: UdpSocket,
+        id: NodeID,
+        nodes: Option<Vec<NodeInfo>>,
+        queried: Queried,
+    ) {
+        let (send_transport, queries, _) = KRPCNode::new(socket).serve();
+
+        spawn(async move {
+            let mut queries = Box::pin(queries);
+
+            while let Some(Ok((query, from))) = queries.next().await {
+                queried.lock().unwrap().insert(id.clone());
+
+                let nodes = match &nodes {
+                    Some(nodes) => nodes.clone(),
+                    None => continue,
+                };
+
+                let envelope = Envelope {
+                    ip: None,
+                    transaction_id: query.transaction_id,
+                    version: None,
+                    message_type: Message::Response {
+                        response: Response::NextHop {
+                            id: id.clone(),
+                            token: None,
+                            nodes,
+                            nodes6: Vec::new(),
+                        },
+                    },
+                    read_only: false,
+                };
+
+                let _ = send_transport.send(from, envelope).await;
+            }
+        });
+    }
+
+    /// Like `make_routing_table` but processing responses, so queries sent by
+    /// the routing table get answered.
+    async fn make_serving_routing_table(owner_id: NodeID) -> Result<RoutingTable, Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let (send_transport, queries, _) = KRPCNode::new(socket).serve();
+        spawn(queries.for_each(|_| futures::future::ready(())));
+
+        Ok(RoutingTable::new(
+            owner_id.clone(),
+            RequestTransport::new(owner_id, send_transport),
+        ))
+    }
+
+    fn node(id: u8) -> Result<NodeInfo, Error> {
+        Ok(NodeInfo::new(
+            NodeID::new(BigUint::from(id)),
+            "127.0.0.1:3000".parse()?,
+        ))
+    }
+
+    #[tokio::test]
+    async fn force_split() -> Result<(), Error> {
+        let mut routing_table = make_routing_table(NodeID::new(BigUint::from(0u8))).await?;
+
+        routing_table.force_split(2);
+        assert_eq!(routing_table.root.leaves().count(), 3);
+
+        // one node in each bucket: bit 0 set, bit 1 set, neither set
+        for id in &[1, 2, 4] {
+            routing_table
+                .add_node(&node(*id)?)
+                .await
+                .unwrap()
+                .mark_successful_query();
+        }
+
+        let bucket_sizes = routing_table
+            .root
+            .leaves()
+            .map(|bucket| bucket.contacts().count())
+            .collect::<Vec<usize>>();
+        assert_eq!(bucket_sizes, vec![1, 1, 1]);
+
+        match routing_table.find_node(NodeID::new(BigUint::from(8u8))) {
+            FindNodeResult::Nodes(nodes) => assert_eq!(nodes, vec![node(4)?, node(2)?, node(1)?]),
+            FindNodeResult::Node(node) => panic!("unexpected exact match {:?}", node),
+        };
+
+        match routing_table.find_node(NodeID::new(BigUint::from(2u8))) {
+            FindNodeResult::Node(found) => assert_eq!(found, node(2)?),
+            FindNodeResult::Nodes(nodes) => panic!("expected exact match, got {:?}", nodes),
+        };
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn split_keeps_nodes_in_their_lookup_bucket() -> Result<(), Error> {
+        let mut routing_table = make_routing_table(NodeID::new(BigUint::from(0u8))).await?;
+
+        // one more node than fits, so the root bucket splits with nodes in it
+        let ids = (1..=K_BUCKET_SIZE as u8 + 1).collect::<Vec<u8>>();
+        for id in &ids {
+            routing_table
+                .add_node(&node(*id)?)
+                .await
+                .unwrap()
+                .mark_successful_query();
+        }
+        assert_eq!(routing_table.root.leaves().count(), 2);
+
+        for id in &ids {
+            let id = NodeID::new(BigUint::from(*id));
+            let (bucket, _) =
+                <RoutingTable>::find_bucket_mut_recursive(&mut routing_table.root, &id, 0);
+
+            assert!(
+                bucket
+                    .unwrap_as_leaf()
+                    .contacts()
+                    .any(|contact| contact.id == id),
+                "{:?} not in the bucket it's looked up in",
+                id
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn buckets_describe_populated_table() -> Result<(), Error> {
+        let mut routing_table = make_routing_table(NodeID::new(BigUint::from(0u8))).await?;
+        routing_table.force_split(2);
+
+        for id in &[1, 2, 6] {
+            routing_table
+                .add_node(&node(*id)?)
+                .await
+                .unwrap()
+                .mark_successful_query();
+        }
+
+        let buckets = routing_table.buckets().collect::<Vec<_>>();
+
+        let prefixes = buckets
+            .iter()
+            .map(|bucket| bucket.prefix().to_vec())
+            .collect::<Vec<Vec<bool>>>();
+        assert_eq!(
+            prefixes,
+            vec![vec![true], vec![false, true], vec![false, false]]
+        );
+        assert_eq!(
+            buckets.iter().map(|it| it.depth()).collect::<Vec<_>>(),
+            vec![1, 2, 2]
+        );
+        assert_eq!(
+            buckets.iter().map(|it| it.len()).collect::<Vec<_>>(),
+            vec![1, 2, 0]
+        );
+        assert!(buckets[2].is_empty());
+        assert_eq!(buckets[0].capacity(), 8);
+
+        let contact = buckets[0].contacts().next().unwrap();
+        assert_eq!(contact.id(), &node(1)?.node_id);
+        assert_eq!(contact.address(), node(1)?.address);
+        assert_eq!(contact.state(), NodeState::Good);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn evicting_bad_node_recorded() -> Result<(), Error> {
+        let mut routing_table = make_routing_table(NodeID::new(BigUint::from(0u8))).await?;
+
+        let bad_node = NodeInfo::new(NodeID::random(), "127.0.0.1:3001".parse()?);
+        let bad_contact = routing_table.add_node(&bad_node).await.unwrap();
+        bad_contact.mark_failed_query();
+        bad_contact.mark_failed_query();
+
+        for _ in 0..7 {
+            routing_table
+                .add_node(&NodeInfo::new(NodeID::random(), "127.0.0.1:3000".parse()?))
+                .await
+                .unwrap()
+                .mark_successful_query();
+        }
+        assert!(routing_table.recent_evictions().is_empty());
+
+        routing_table
+            .add_node(&NodeInfo::new(NodeID::random(), "127.0.0.1:3002".parse()?))
+            .await
+            .unwrap();
+
+        let evictions = routing_table.recent_evictions();
+        assert_eq!(evictions.len(), 1);
+        assert_eq!(evictions[0].id, bad_node.node_id);
+        assert_eq!(evictions[0].address, bad_node.address);
+        assert_eq!(evictions[0].failed_queries, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn save_and_load_preserves_states() -> Result<(), Error> {
+        let owner_id = NodeID::new(BigUint::from(0u8));
+        let mut routing_table = make_routing_table(owner_id.clone()).await?;
+        routing_table.force_split(100);
+
+        let long_ago = Utc::now().naive_utc() - Duration::minutes(20);
+
+        // Three nodes in each of the first 100 far buckets, so no bucket fills
+        // up and nothing gets pinged. The lowest set bit picks the bucket.
+        for depth in 0..100usize {
+            for (n, multiple) in [1u8, 3, 5].iter().enumerate() {
+                let id = NodeID::new(BigUint::from(*multiple) << depth);
+                let address = match n {
+                    2 => SocketAddr::new(
+                        Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, depth as u16, 1).into(),
+                        6881,
+                    ),
+                    _ => SocketAddr::new(Ipv4Addr::new(10, 0, depth as u8, n as u8).into(), 6881),
+                };
+
+                let contact = routing_table
+                    .add_node(&NodeInfo::new(id.clone(), address))
+                    .await
+                    .unwrap();
+
+                match n {
+                    0 => contact.mark_successful_query(),
+                    1 => {
+                        *contact = NodeContactState::from_parts(
+                            id,
+                            address,
+                            long_ago,
+                            Some(long_ago),
+                            Some(long_ago),
+                            1,
+                        )
+                    }
+                    _ => contact.mark_successful_request(),
+                }
+            }
+        }
+
+        let mut saved = Vec::new();
+        routing_table.save(&mut saved)?;
+
+        let loaded = RoutingTable::load(
+            saved.as_slice(),
+            owner_id.clone(),
+            make_transport(owner_id).await?,
+        )
+        .await?;
+
+        let snapshot = loaded.snapshot();
+        assert_eq!(snapshot.len(), 300);
+        assert_eq!(snapshot, routing_table.snapshot());
+        assert_eq!(
+            snapshot
+                .iter()
+                .filter(|entry| entry.state == NodeState::Good)
+                .count(),
+            100
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stale_buckets_need_refresh() -> Result<(), Error> {
+        let mut routing_table = make_routing_table(NodeID::new(BigUint::from(0u8))).await?;
+        routing_table.force_split(2);
+        routing_table.add_node(&node(1)?).await.unwrap();
+
+        let now = Utc::now().naive_utc();
+        assert!(routing_table.buckets_needing_refresh_at(now).is_empty());
+
+        let later = now + Duration::minutes(16);
+        let targets = routing_table.buckets_needing_refresh_at(later);
+        let prefixes = routing_table
+            .buckets()
+            .map(|bucket| bucket.prefix().to_vec())
+            .collect::<Vec<Vec<bool>>>();
+        assert_eq!(targets.len(), prefixes.len());
+
+        for (target, prefix) in targets.iter().zip(&prefixes) {
+            for (depth, bit) in prefix.iter().enumerate() {
+                assert_eq!(target.nth_bit(depth), *bit);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn longer_questionable_after_keeps_nodes_good() -> Result<(), Error> {
+        let owner_id = NodeID::new(BigUint::from(0u8));
+        let mut routing_table = make_routing_table(owner_id).await?;
+
+        let twenty_minutes_ago = Utc::now().naive_utc() - Duration::minutes(20);
+        let info = node(1)?;
+        let contact = routing_table.add_node(&info).await.unwrap();
+        *contact = NodeContactState::from_parts(
+            info.node_id,
+            info.address,
+            twenty_minutes_ago,
+            Some(twenty_minutes_ago),
+            None,
+            0,
+        );
+        assert_eq!(routing_table.snapshot()[0].state, NodeState::Questionable);
+
+        let routing_table = routing_table.with_questionable_after(Duration::minutes(30));
+        assert_eq!(routing_table.snapshot()[0].state, NodeState::Good);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_converges_to_closest() -> Result<(), Error> {
+        let owner_id = NodeID::new(BigUint::from(0u8));
+        let mut routing_table = make_serving_routing_table(owner_id).await?;
+
+        // Node n is n away from our id. Each node knows of a handful of
+        // nodes, mostly closer than itself, like a Kademlia routing table.
+        let count = 32usize;
+        let mut sockets = Vec::new();
+        let mut infos = Vec::new();
+        for n in 1..=count {
+            let socket = UdpSocket::bind("127.0.0.1:0").await?;
+            infos.push(NodeInfo::new(
+                NodeID::new(BigUint::from(n)),
+                socket.local_addr()?,
+            ));
+            sockets.push(socket);
+        }
+
+        let queried = Queried::default();
+        for (idx, socket) in sockets.into_iter().enumerate() {
+            let n = idx + 1;
+            let known = [
+                n.checked_sub(1),
+                n.checked_sub(2),
+                n.checked_sub(4),
+                n.checked_sub(8),
+                Some(n + 1),
+            ]
+            .iter()
+            .flatten()
+            .filter(|&&known| known >= 1 && known <= count)
+            .map(|known| infos[known - 1].clone())
+            .collect();
+
+            serve_fake_node(
+                socket,
+                infos[idx].node_id.clone(),
+                Some(known),
+                queried.clone(),
+            );
+        }
+
+        let seed = match infos[count - 1].address {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+        routing_table.bootstrap(seed).await;
+
+        let queried = queried.lock().unwrap();
+        for closest in &infos[..8] {
+            assert!(queried.contains(&closest.node_id));
+        }
+        assert!(queried.len() < count);
+
+        let snapshot = routing_table.snapshot();
+        assert!(!snapshot.is_empty());
+        assert!(snapshot.iter().all(|entry| entry.state == NodeState::Good));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_at_most_alpha_queries_in_flight() -> Result<(), Error> {
+        let owner_id = NodeID::new(BigUint::from(0u8));
+        let mut routing_table = make_serving_routing_table(owner_id).await?;
+        let queried = Queried::default();
+
+        let mut silent = Vec::new();
+        for n in 1..=6u8 {
+            let socket = UdpSocket::bind("127.0.0.1:0").await?;
+            let info = NodeInfo::new(NodeID::new(BigUint::from(n)), socket.local_addr()?);
+            serve_fake_node(socket, info.node_id.clone(), None, queried.clone());
+            silent.push(info);
+        }
+
+        let seed_socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let seed: SocketAddrV4 = match seed_socket.local_addr()? {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+        let seed_id = NodeID::new(BigUint::from(100u8));
+        serve_fake_node(seed_socket, seed_id.clone(), Some(silent), queried.clone());
+
+        // Nobody but the seed answers, so the second round is still waiting
+        // on its queries.
+        let result = timeout(
+            std::time::Duration::from_millis(500),
+            routing_table.bootstrap_with_alpha(seed, 2),
+        )
+        .await;
+        assert!(result.is_err());
+
+        let queried = queried.lock().unwrap();
+        assert_eq!(queried.len(), 3);
+        assert!(queried.contains(&seed_id));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bootstrap_over_mock_transport() -> Result<(), Error> {
+        let owner_id = NodeID::random();
+        let network = (1..=64u8)
+            .map(|n| {
+                NodeInfo::new(
+                    NodeID::random(),
+                    SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, n), 6881).into(),
+                )
+            })
+            .collect::<Vec<NodeInfo>>();
+        let transport = MockTransport::new(network.clone(), K_BUCKET_SIZE);
+        let mut routing_table = RoutingTable::new(owner_id.clone(), transport.clone());
+
+        let seed = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 6881);
+        routing_table.bootstrap(seed).await;
+
+        // The seed answers with the closest nodes to our id and none of them
+        // know of any closer ones, so each of them is queried exactly once.
+        let mut others = network[1..].to_vec();
+        others.sort_by_cached_key(|node| node.node_id.deref() ^ owner_id.deref());
+        let expected = others
+            .iter()
+            .take(K_BUCKET_SIZE)
+            .map(|node| node.address)
+            .chain(Some(seed.into()))
+            .collect::<HashSet<SocketAddr>>();
+
+        let queried = transport.queried();
+        assert_eq!(queried.len(), expected.len());
+        assert_eq!(queried.into_iter().collect::<HashSet<_>>(), expected);
+
+        let snapshot = routing_table.snapshot();
+        assert!(!snapshot.is_empty());
+        assert!(snapshot.iter().all(|entry| entry.state == NodeState::Good));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn larger_k_fills_bucket_before_splitting() -> Result<(), Error> {
+        let owner_id = NodeID::new(BigUint::from(0u8));
+        let mut routing_table =
+            RoutingTable::with_k(owner_id.clone(), make_transport(owner_id).await?, 16);
+
+        for id in 1..=16 {
+            routing_table
+                .add_node(&node(id)?)
+                .await
+                .unwrap()
+                .mark_successful_query();
+        }
+
+        let buckets = routing_table.buckets().collect::<Vec<_>>();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].len(), 16);
+        assert_eq!(buckets[0].capacity(), 16);
+
+        routing_table
+            .add_node(&node(17)?)
+            .await
+            .unwrap()
+            .mark_successful_query();
+        assert!(routing_table.buckets().count() > 1);
+
+        match routing_table.find_node(NodeID::random()) {
+            FindNodeResult::Nodes(nodes) => assert_eq!(nodes.len(), 16),
+            FindNodeResult::Node(node) => panic!("unexpected exact match {:?}", node),
+        };
+
+        Ok(())
+    }
+}
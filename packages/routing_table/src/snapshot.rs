@@ -0,0 +1,194 @@
+use crate::node_contact_state::NodeState;
+use krpc_encoding::NodeID;
+use std::{
+    collections::HashMap,
+    iter::FromIterator,
+    net::SocketAddr,
+};
+
+/// A node in a [`RoutingSnapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    pub id: NodeID,
+    pub address: SocketAddr,
+    pub state: NodeState,
+}
+
+/// An owned, point in time copy of the nodes in a routing table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutingSnapshot {
+    entries: HashMap<NodeID, SnapshotEntry>,
+}
+
+impl RoutingSnapshot {
+    pub fn get(&self, id: &NodeID) -> Option<&SnapshotEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SnapshotEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl FromIterator<SnapshotEntry> for RoutingSnapshot {
+    fn from_iter<I: IntoIterator<Item = SnapshotEntry>>(iter: I) -> Self {
+        RoutingSnapshot {
+            entries: iter
+                .into_iter()
+                .map(|entry| (entry.id.clone(), entry))
+                .collect(),
+        }
+    }
+}
+
+/// A node whose state changed between two snapshots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateChange {
+    pub id: NodeID,
+    pub old: NodeState,
+    pub new: NodeState,
+}
+
+/// Changes between two [`RoutingSnapshot`]s. Each list is ordered by node id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutingDiff {
+    /// Nodes only present in the newer snapshot.
+    pub added: Vec<SnapshotEntry>,
+
+    /// Nodes only present in the older snapshot.
+    pub removed: Vec<SnapshotEntry>,
+
+    /// Nodes present in both snapshots with different states. Address changes
+    /// aren't reported.
+    pub state_changed: Vec<StateChange>,
+}
+
+/// Computes the nodes added, removed and changed going from `old` to `new`.
+pub fn diff(old: &RoutingSnapshot, new: &RoutingSnapshot) -> RoutingDiff {
+    let mut added = new
+        .iter()
+        .filter(|entry| old.get(&entry.id).is_none())
+        .cloned()
+        .collect::<Vec<SnapshotEntry>>();
+
+    let mut removed = old
+        .iter()
+        .filter(|entry| new.get(&entry.id).is_none())
+        .cloned()
+        .collect::<Vec<SnapshotEntry>>();
+
+    let mut state_changed = new
+        .iter()
+        .filter_map(|entry| {
+            let old_entry = old.get(&entry.id)?;
+            if old_entry.state == entry.state {
+                return None;
+            }
+
+            Some(StateChange {
+                id: entry.id.clone(),
+                old: old_entry.state,
+                new: entry.state,
+            })
+        })
+        .collect::<Vec<StateChange>>();
+
+    added.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+    removed.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+    state_changed.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+
+    RoutingDiff {
+        added,
+        removed,
+        state_changed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        diff,
+        RoutingSnapshot,
+        SnapshotEntry,
+        StateChange,
+    };
+    use crate::node_contact_state::NodeState;
+    use krpc_encoding::NodeID;
+    type Error = Box<dyn std::error::Error>;
+
+    fn make_entry(id: &[u8; 40], state: NodeState) -> Result<SnapshotEntry, Error> {
+        Ok(SnapshotEntry {
+            id: NodeID::from_hex(id)?,
+            address: "127.0.0.1:3000".parse()?,
+            state,
+        })
+    }
+
+    #[test]
+    fn diff_empty() -> Result<(), Error> {
+        let snapshot = vec![make_entry(
+            b"0000000000000000000000000000000000000001",
+            NodeState::Good,
+        )?]
+        .into_iter()
+        .collect::<RoutingSnapshot>();
+
+        let result = diff(&snapshot, &snapshot);
+
+        assert!(result.added.is_empty());
+        assert!(result.removed.is_empty());
+        assert!(result.state_changed.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_changes() -> Result<(), Error> {
+        let unchanged = make_entry(
+            b"0000000000000000000000000000000000000001",
+            NodeState::Good,
+        )?;
+        let removed = make_entry(
+            b"0000000000000000000000000000000000000002",
+            NodeState::Bad,
+        )?;
+        let changed_before = make_entry(
+            b"0000000000000000000000000000000000000003",
+            NodeState::Good,
+        )?;
+        let changed_after = make_entry(
+            b"0000000000000000000000000000000000000003",
+            NodeState::Questionable,
+        )?;
+        let added = make_entry(
+            b"0000000000000000000000000000000000000004",
+            NodeState::Questionable,
+        )?;
+
+        let old = vec![unchanged.clone(), removed.clone(), changed_before]
+            .into_iter()
+            .collect::<RoutingSnapshot>();
+        let new = vec![unchanged, changed_after, added.clone()]
+            .into_iter()
+            .collect::<RoutingSnapshot>();
+
+        let result = diff(&old, &new);
+
+        assert_eq!(result.added, vec![added]);
+        assert_eq!(result.removed, vec![removed]);
+        assert_eq!(
+            result.state_changed,
+            vec![StateChange {
+                id: NodeID::from_hex(b"0000000000000000000000000000000000000003")?,
+                old: NodeState::Good,
+                new: NodeState::Questionable,
+            }]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! In-memory stand-in for the network, for tests which shouldn't depend on
+//! real sockets.
+
+use crate::transport::{
+    errors::{
+        ErrorKind,
+        Result,
+    },
+    Transport,
+};
+use futures::{
+    future::{
+        self,
+        LocalBoxFuture,
+    },
+    FutureExt,
+};
+use krpc_encoding::{
+    NodeID,
+    NodeInfo,
+};
+use std::{
+    net::SocketAddr,
+    ops::Deref,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+use tokio_krpc::responses::FindNodeResponse;
+
+/// A network of nodes which answer instantly. Each node answers pings with its
+/// id and `find_node` queries with the `k` other nodes in the network closest
+/// to the target. Queries to addresses outside the network time out right
+/// away.
+///
+/// Clones share the record of queried addresses.
+#[derive(Clone)]
+pub struct MockTransport {
+    nodes: Vec<NodeInfo>,
+    k: usize,
+    queried: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl MockTransport {
+    pub fn new(nodes: Vec<NodeInfo>, k: usize) -> MockTransport {
+        MockTransport {
+            nodes,
+            k,
+            queried: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Addresses queried so far, in the order the queries were sent.
+    pub fn queried(&self) -> Vec<SocketAddr> {
+        self.queried.lock().unwrap().clone()
+    }
+
+    fn node_at(&self, address: SocketAddr) -> Result<&NodeInfo> {
+        self.queried.lock().unwrap().push(address);
+
+        self.nodes
+            .iter()
+            .find(|node| node.address == address)
+            .ok_or_else(|| ErrorKind::Timeout.into())
+    }
+
+    fn closest_to(&self, target: &NodeID, excluding: SocketAddr) -> Vec<NodeInfo> {
+        let mut nodes = self
+            .nodes
+            .iter()
+            .filter(|node| node.address != excluding)
+            .cloned()
+            .collect::<Vec<NodeInfo>>();
+        nodes.sort_by_cached_key(|node| node.node_id.deref() ^ target.deref());
+        nodes.truncate(self.k);
+
+        nodes
+    }
+}
+
+impl Transport for MockTransport {
+    fn find_node(
+        &self,
+        address: SocketAddr,
+        target: NodeID,
+    ) -> LocalBoxFuture<'_, Result<FindNodeResponse>> {
+        let response = self.node_at(address).map(|node| FindNodeResponse {
+            id: node.node_id.clone(),
+            nodes: self.closest_to(&target, address),
+        });
+
+        future::ready(response).boxed_local()
+    }
+
+    fn ping(&self, address: SocketAddr) -> LocalBoxFuture<'_, Result<NodeID>> {
+        let response = self.node_at(address).map(|node| node.node_id.clone());
+
+        future::ready(response).boxed_local()
+    }
+}
@@ -1,5 +1,7 @@
 use self::errors::Result;
 use crate::{
+    events::RoutingEvents,
+    evictions::EvictionLog,
     node_contact_state::NodeContactState,
     transport::errors::{
         Error,
@@ -7,57 +9,153 @@ use crate::{
         TimeoutExt,
     },
 };
+use futures::{
+    future::LocalBoxFuture,
+    FutureExt,
+};
 use krpc_encoding::NodeID;
-use std::net::SocketAddrV4;
+use log::{
+    as_error,
+    debug,
+};
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use tokio::time::sleep;
 use tokio_krpc::{
     responses::FindNodeResponse,
     RequestTransport,
 };
 
+/// Time waited before the first retry of a failed ping. Each following retry
+/// waits this much longer than the one before.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Sends the queries the routing table needs to other nodes. Implemented by
+/// [`RequestTransport`] for the real network. Tests can implement it to answer
+/// from memory instead.
+pub trait Transport {
+    /// Asks the node at `address` for the nodes it knows closest to `target`.
+    fn find_node(
+        &self,
+        address: SocketAddr,
+        target: NodeID,
+    ) -> LocalBoxFuture<'_, Result<FindNodeResponse>>;
+
+    /// Pings the node at `address`, resolving to the id it responds with.
+    fn ping(&self, address: SocketAddr) -> LocalBoxFuture<'_, Result<NodeID>>;
+}
+
+impl Transport for RequestTransport {
+    fn find_node(
+        &self,
+        address: SocketAddr,
+        target: NodeID,
+    ) -> LocalBoxFuture<'_, Result<FindNodeResponse>> {
+        RequestTransport::find_node(self, address, target)
+            .timeout()
+            .boxed_local()
+    }
+
+    fn ping(&self, address: SocketAddr) -> LocalBoxFuture<'_, Result<NodeID>> {
+        RequestTransport::ping(self, address)
+            .timeout()
+            .boxed_local()
+    }
+}
+
 /// A transport used for communicating with other nodes which keeps liveness
 /// information up to date.
-pub struct LivenessTransport {
-    request_transport: RequestTransport,
+pub struct LivenessTransport<T = RequestTransport> {
+    transport: T,
+    events: RoutingEvents,
+    evictions: EvictionLog,
+
+    /// Number of times a ping is re-sent before the node is marked as having
+    /// failed a query.
+    retries: usize,
 }
 
-impl LivenessTransport {
-    pub fn new(request_transport: RequestTransport) -> LivenessTransport {
-        LivenessTransport { request_transport }
+impl<T: Transport> LivenessTransport<T> {
+    pub fn new(transport: T) -> LivenessTransport<T> {
+        LivenessTransport {
+            transport,
+            events: RoutingEvents::new(),
+            evictions: EvictionLog::new(),
+            retries: 0,
+        }
     }
 
-    pub async fn find_node(
-        &self,
-        address: SocketAddrV4,
-        target: NodeID,
-    ) -> Result<FindNodeResponse> {
-        Ok(self
-            .request_transport
-            .find_node(address, target)
-            .timeout()
-            .await?)
+    /// Re-sends pings which time out or fail to send up to `retries` times,
+    /// backing off a little more before each retry, before counting the ping
+    /// against the node. Zero, the default, gives up after the first failure.
+    pub fn with_retries(mut self, retries: usize) -> LivenessTransport<T> {
+        self.retries = retries;
+        self
+    }
+
+    /// Events for changes in liveness of nodes contacted through this
+    /// transport.
+    pub fn events(&self) -> &RoutingEvents {
+        &self.events
+    }
+
+    /// Nodes recently evicted from buckets sharing this transport.
+    pub fn evictions(&self) -> &EvictionLog {
+        &self.evictions
+    }
+
+    pub async fn find_node(&self, address: SocketAddr, target: NodeID) -> Result<FindNodeResponse> {
+        self.transport.find_node(address, target).await
     }
 
     pub async fn ping(&self, node: &mut NodeContactState) -> Result<()> {
-        Ok(node.update_from_result(
-            self.request_transport
-                .ping(node.address)
-                .timeout()
-                .await
-                .and_then(|node_id| {
-                    if node_id != node.id {
-                        Err(Error::from(ErrorKind::PingIdMismatch {
-                            got: node_id,
-                            expected: node.id.clone(),
-                        }))
-                    } else {
-                        Ok(())
-                    }
-                }),
-        )?)
+        let result = node.update_from_result(self.ping_with_retries(node).await);
+
+        self.events.observe(node);
+
+        Ok(result?)
+    }
+
+    async fn ping_with_retries(&self, node: &NodeContactState) -> Result<()> {
+        let mut attempt = 0;
+
+        loop {
+            let result = self.ping_once(node).await;
+            let retryable = match &result {
+                Ok(()) => false,
+                Err(err) => !matches!(err.kind(), ErrorKind::PingIdMismatch { .. }),
+            };
+
+            if !retryable || attempt >= self.retries {
+                return result;
+            }
+
+            if let Err(err) = &result {
+                debug!(err = as_error!(err), attempt = attempt; "retrying ping");
+            }
+
+            attempt += 1;
+            sleep(RETRY_BACKOFF * attempt as u32).await;
+        }
+    }
+
+    async fn ping_once(&self, node: &NodeContactState) -> Result<()> {
+        let node_id = self.transport.ping(node.address).await?;
+
+        if node_id != node.id {
+            return Err(Error::from(ErrorKind::PingIdMismatch {
+                got: node_id,
+                expected: node.id.clone(),
+            }));
+        }
+
+        Ok(())
     }
 }
 
-mod errors {
+pub mod errors {
     use futures_util::{
         future::Map,
         FutureExt,
@@ -97,7 +195,7 @@ mod errors {
         })
     }
 
-    pub trait TimeoutExt<T>: Future<Output = TokioKrpcSendResult<T>> + Sized {
+    pub(crate) trait TimeoutExt<T>: Future<Output = TokioKrpcSendResult<T>> + Sized {
         fn timeout(self) -> WithTimeoutFuture<T, Self>;
     }
 
@@ -137,4 +235,96 @@ mod errors {
 
         backtrace: Backtrace,
     }
+
+    impl Error {
+        pub fn kind(&self) -> &ErrorKind {
+            &self.inner
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LivenessTransport;
+    use crate::node_contact_state::{
+        NodeContactState,
+        NodeState,
+    };
+    use futures::StreamExt;
+    use krpc_encoding::{
+        Envelope,
+        Message,
+        NodeID,
+        Response,
+    };
+    use tokio::{
+        net::UdpSocket,
+        spawn,
+    };
+    use tokio_krpc::{
+        KRPCNode,
+        RequestTransport,
+    };
+    type Error = Box<dyn std::error::Error>;
+
+    /// Binds a node which ignores the first `dropped` queries it receives
+    /// and answers the rest with `id`.
+    async fn flaky_node(id: NodeID, dropped: usize) -> Result<NodeContactState, Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let address = socket.local_addr()?;
+        let (send_transport, queries, _) = KRPCNode::new(socket).serve();
+        let node = NodeContactState::new(id.clone(), address);
+
+        spawn(async move {
+            let mut queries = Box::pin(queries).skip(dropped);
+
+            while let Some(Ok((query, from))) = queries.next().await {
+                let envelope = Envelope {
+                    ip: None,
+                    transaction_id: query.transaction_id,
+                    version: None,
+                    message_type: Message::Response {
+                        response: Response::OnlyID { id: id.clone() },
+                    },
+                    read_only: false,
+                };
+
+                let _ = send_transport.send(from, envelope).await;
+            }
+        });
+
+        Ok(node)
+    }
+
+    async fn make_transport() -> Result<RequestTransport, Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let (send_transport, queries, _) = KRPCNode::new(socket).serve();
+        spawn(queries.for_each(|_| futures::future::ready(())));
+
+        Ok(RequestTransport::new(NodeID::random(), send_transport))
+    }
+
+    #[tokio::test]
+    async fn retried_ping_succeeds() -> Result<(), Error> {
+        let mut node = flaky_node(NodeID::random(), 1).await?;
+        let transport = LivenessTransport::new(make_transport().await?).with_retries(1);
+
+        transport.ping(&mut node).await?;
+
+        assert_eq!(node.state(), NodeState::Good);
+        assert_eq!(node.failed_queries(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn no_retries_by_default() -> Result<(), Error> {
+        let mut node = flaky_node(NodeID::random(), 1).await?;
+        let transport = LivenessTransport::new(make_transport().await?);
+
+        assert!(transport.ping(&mut node).await.is_err());
+        assert_eq!(node.failed_queries(), 1);
+
+        Ok(())
+    }
 }
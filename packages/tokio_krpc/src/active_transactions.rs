@@ -5,12 +5,23 @@ use crate::{
     transaction_id::{
         parse_originating_transaction_id,
         TransactionId,
+        TransactionIdGenerator,
+        TransactionIdWidth,
     },
 };
 
 use std::{
-    collections::HashMap,
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    net::SocketAddr,
     sync::{
+        atomic::{
+            AtomicBool,
+            AtomicU64,
+            Ordering,
+        },
         Arc,
         Mutex,
     },
@@ -18,13 +29,51 @@ use std::{
         Poll,
         Waker,
     },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
+/// Number of ids asked of the generator for a single transaction before
+/// falling back to the first free id after the last one generated.
+const MAX_GENERATED_ATTEMPTS: usize = 16;
+
+/// Time a transaction is remembered after its response was read or dropped,
+/// so retransmitted responses arriving later are still counted as duplicates.
+const COMPLETED_RETENTION: Duration = Duration::from_secs(30);
+
+/// Maximum number of completed transactions remembered at once. The oldest
+/// are forgotten first.
+const MAX_COMPLETED: usize = 1024;
+
 /// A thread-safe container for information about active transactions. Shared
 /// between many [`ResponseFuture`]s and a single [`RecvTransport`].
 #[derive(Clone)]
 pub struct ActiveTransactions {
-    transactions: Arc<Mutex<HashMap<TransactionId, TxState>>>,
+    transactions: Arc<Mutex<HashMap<TransactionId, Transaction>>>,
+
+    /// Transactions whose response was read or dropped recently, oldest
+    /// first, with the address the response came from.
+    completed: Arc<Mutex<VecDeque<(TransactionId, SocketAddr, Instant)>>>,
+    width: TransactionIdWidth,
+    generator: Arc<dyn TransactionIdGenerator>,
+
+    /// Number of responses received for transactions which already had a
+    /// response, whether or not it was read yet.
+    duplicate_responses: Arc<AtomicU64>,
+
+    /// Whether duplicate responses are logged at debug level.
+    log_duplicates: bool,
+
+    /// Set once the node is shut down. No more responses will be received.
+    shut_down: Arc<AtomicBool>,
+}
+
+struct Transaction {
+    /// Address the request was sent to. Responses must come from here.
+    address: SocketAddr,
+    state: TxState,
 }
 
 enum TxState {
@@ -39,23 +88,144 @@ enum TxState {
 }
 
 impl ActiveTransactions {
-    pub fn new() -> ActiveTransactions {
+    pub fn new(
+        width: TransactionIdWidth,
+        generator: Arc<dyn TransactionIdGenerator>,
+        log_duplicates: bool,
+    ) -> ActiveTransactions {
         let transactions = Arc::new(Mutex::new(HashMap::new()));
 
-        ActiveTransactions { transactions }
+        ActiveTransactions {
+            transactions,
+            completed: Arc::new(Mutex::new(VecDeque::new())),
+            width,
+            generator,
+            duplicate_responses: Arc::new(AtomicU64::new(0)),
+            log_duplicates,
+            shut_down: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Width of transaction ids for requests originating from this node.
+    pub fn width(&self) -> TransactionIdWidth {
+        self.width
     }
 
-    /// Adds an un-polled pending transaction to the set of active transactions.
-    pub fn add_transaction(&self, transaction_id: TransactionId) {
-        let mut map = self.transactions.lock().unwrap();
-        map.insert(transaction_id, TxState::AwaitingResponse { waker: None });
+    /// Number of responses received for a transaction which already had a
+    /// response. Responses arriving up to `COMPLETED_RETENTION` after the
+    /// first was read are counted too.
+    pub fn duplicate_responses(&self) -> u64 {
+        self.duplicate_responses.load(Ordering::Relaxed)
+    }
+
+    /// Number of transactions waiting for or holding an unread response.
+    pub fn len(&self) -> send_errors::Result<usize> {
+        Ok(self.transactions.lock()?.len())
+    }
+
+    /// Adds an un-polled pending transaction for a request sent to `address`
+    /// to the set of active transactions, asking the generator for ids until
+    /// one isn't used by any other active transaction. After a few collisions
+    /// the ids following the last generated one are tried in order instead.
+    ///
+    /// # Errors
+    ///
+    /// Fails when every transaction id of the configured width is in use,
+    /// when the node was shut down, or when the lock was poisoned by a panic
+    /// on another task.
+    pub fn allocate(&self, address: SocketAddr) -> send_errors::Result<TransactionId> {
+        let mut map = self.transactions.lock()?;
+        if self.shut_down.load(Ordering::SeqCst) {
+            return Err(send_errors::ErrorKind::Shutdown)?;
+        }
+
+        if map.len() as u64 >= self.width.capacity() {
+            return Err(send_errors::ErrorKind::TransactionIdsExhausted)?;
+        }
+
+        let mut transaction_id = self.width.truncate(self.generator.next());
+        for _ in 1..MAX_GENERATED_ATTEMPTS {
+            if !map.contains_key(&transaction_id) {
+                break;
+            }
+
+            transaction_id = self.width.truncate(self.generator.next());
+        }
+
+        // Some id is free, so this stops within `map.len()` steps.
+        while map.contains_key(&transaction_id) {
+            transaction_id = self.width.truncate(transaction_id.wrapping_add(1));
+        }
+
+        map.insert(
+            transaction_id,
+            Transaction {
+                address,
+                state: TxState::AwaitingResponse { waker: None },
+            },
+        );
+
+        Ok(transaction_id)
     }
 
     /// Stops tracking a transaction. Subsequent calls to [`handle_response`],
     /// [`poll_response`]  with `transaction_id` will now fail.
-    pub fn drop_transaction(&self, transaction_id: TransactionId) {
-        let mut map = self.transactions.lock().unwrap();
-        map.remove(&transaction_id);
+    pub fn drop_transaction(&self, transaction_id: TransactionId) -> send_errors::Result<()> {
+        let mut map = self.transactions.lock()?;
+        if let Some(Transaction {
+            address,
+            state: TxState::GotResponse { .. },
+        }) = map.remove(&transaction_id)
+        {
+            self.complete(transaction_id, address)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remembers that the response to `transaction_id` from `address` was
+    /// read or dropped, forgetting transactions completed too long ago.
+    fn complete(
+        &self,
+        transaction_id: TransactionId,
+        address: SocketAddr,
+    ) -> send_errors::Result<()> {
+        let mut completed = self.completed.lock()?;
+        let now = Instant::now();
+        forget_expired(&mut completed, now);
+
+        if completed.len() >= MAX_COMPLETED {
+            completed.pop_front();
+        }
+        completed.push_back((transaction_id, address, now));
+
+        Ok(())
+    }
+
+    /// Counts a response for a transaction which already had one.
+    fn record_duplicate(&self, transaction_id: TransactionId, from: SocketAddr) {
+        self.duplicate_responses.fetch_add(1, Ordering::Relaxed);
+
+        if self.log_duplicates {
+            tracing::debug!(transaction_id, %from, "duplicate response");
+        }
+    }
+
+    /// Fails every transaction still waiting for a response and any allocated
+    /// afterwards. Responses already received can still be read.
+    pub fn shutdown(&self) -> send_errors::Result<()> {
+        let mut map = self.transactions.lock()?;
+        self.shut_down.store(true, Ordering::SeqCst);
+
+        for transaction in map.values_mut() {
+            if let TxState::AwaitingResponse { waker } = &mut transaction.state {
+                if let Some(waker) = waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+
+        Ok(())
     }
 
     /// Updates transaction associated with `message` such that the next call to
@@ -65,22 +235,55 @@ impl ActiveTransactions {
     /// # Errors
     ///
     /// If the transaction id associated with `message` isn't known, returns
-    /// failure.
-    pub fn handle_response(&self, message: InboundResponseEnvelope) -> recv_errors::Result<()> {
-        let transaction_id = parse_originating_transaction_id(&message.transaction_id)?;
-        let mut map = self.transactions.lock().unwrap();
+    /// failure unless its response was read or dropped recently, in which case
+    /// the message is counted as a duplicate. If `from` isn't the address the
+    /// request was sent to, returns failure and leaves the transaction
+    /// waiting for a response from the right address. Fails with
+    /// [`recv_errors::ErrorKind::LockPoisoned`] when a panic on another
+    /// task poisoned the lock.
+    pub fn handle_response(
+        &self,
+        message: InboundResponseEnvelope,
+        from: SocketAddr,
+    ) -> recv_errors::Result<()> {
+        let transaction_id = parse_originating_transaction_id(&message.transaction_id, self.width)?;
+        let mut map = self.transactions.lock()?;
 
-        let current_tx_state = map
-            .remove(&transaction_id)
-            .ok_or_else(|| recv_errors::ErrorKind::UnknownTransactionReceived { transaction_id })?;
+        let transaction = match map.get_mut(&transaction_id) {
+            Some(transaction) => transaction,
+            None => {
+                let mut completed = self.completed.lock()?;
+                forget_expired(&mut completed, Instant::now());
+
+                if completed.iter().any(|(completed_id, address, _)| {
+                    *completed_id == transaction_id && *address == from
+                }) {
+                    self.record_duplicate(transaction_id, from);
+                    return Ok(());
+                }
+
+                return Err(recv_errors::ErrorKind::UnknownTransactionReceived { transaction_id })?;
+            }
+        };
+
+        if transaction.address != from {
+            return Err(recv_errors::ErrorKind::ResponseAddressMismatch {
+                transaction_id,
+                expected: transaction.address,
+                got: from,
+            })?;
+        }
 
-        match current_tx_state {
+        match &mut transaction.state {
             TxState::GotResponse { .. } => {
-                // Multiple responses received for a single transaction. This shouldn't happen.
-                map.insert(transaction_id, current_tx_state);
+                // Multiple responses received for a single transaction. Either
+                // the node retransmitted, someone is spoofing responses or we
+                // processed the same packet twice. The first response wins.
+                self.record_duplicate(transaction_id, from);
             }
             TxState::AwaitingResponse { waker } => {
-                map.insert(transaction_id, TxState::GotResponse { response: message });
+                let waker = waker.take();
+                transaction.state = TxState::GotResponse { response: message };
                 waker.map(|waker| waker.wake());
             }
         };
@@ -96,24 +299,34 @@ impl ActiveTransactions {
         transaction_id: TransactionId,
         waker: &Waker,
     ) -> Poll<send_errors::Result<InboundResponseEnvelope>> {
-        let mut map = self.transactions.lock().unwrap();
+        let mut map = self.transactions.lock()?;
 
-        let tx_state = map
+        let Transaction { address, state } = map
             .remove(&transaction_id)
             .ok_or_else(|| send_errors::ErrorKind::UnknownTransactionPolled { transaction_id })?;
 
-        match tx_state {
-            TxState::GotResponse { response } => Poll::Ready(Ok(response)),
+        match state {
+            TxState::GotResponse { response } => {
+                self.complete(transaction_id, address)?;
+
+                Poll::Ready(Ok(response))
+            }
+            TxState::AwaitingResponse { .. } if self.shut_down.load(Ordering::SeqCst) => {
+                Poll::Ready(Err(send_errors::ErrorKind::Shutdown.into()))
+            }
             TxState::AwaitingResponse { waker: Some(..) } => {
-                map.insert(transaction_id, tx_state);
+                map.insert(transaction_id, Transaction { address, state });
 
                 Poll::Pending
             }
             TxState::AwaitingResponse { waker: None } => {
                 map.insert(
                     transaction_id,
-                    TxState::AwaitingResponse {
-                        waker: Some(waker.clone()),
+                    Transaction {
+                        address,
+                        state: TxState::AwaitingResponse {
+                            waker: Some(waker.clone()),
+                        },
                     },
                 );
 
@@ -122,3 +335,15 @@ impl ActiveTransactions {
         }
     }
 }
+
+/// Drops transactions completed more than `COMPLETED_RETENTION` before `now`
+/// from the front of `completed`.
+fn forget_expired(completed: &mut VecDeque<(TransactionId, SocketAddr, Instant)>, now: Instant) {
+    while let Some((_, _, at)) = completed.front() {
+        if now.saturating_duration_since(*at) < COMPLETED_RETENTION {
+            break;
+        }
+
+        completed.pop_front();
+    }
+}
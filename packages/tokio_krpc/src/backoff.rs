@@ -0,0 +1,112 @@
+use crate::send_errors::Result;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+/// Destinations tracked before those no longer backed off are forgotten.
+const MAX_TRACKED_DESTINATIONS: usize = 65536;
+
+/// Parameters of the backoff applied to destinations which keep failing to
+/// respond to queries.
+///
+/// Once a destination fails `failure_threshold` queries in a row, queries to
+/// it are refused for `initial` after the last failure. Each further failure
+/// doubles the wait, up to `max`. A response resets the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackoffConfig {
+    pub failure_threshold: u32,
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> BackoffConfig {
+        BackoffConfig {
+            failure_threshold: 2,
+            initial: Duration::from_secs(5),
+            max: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+struct Failures {
+    consecutive: u32,
+    last_failure: Instant,
+}
+
+/// Tracks consecutive failures of queries to each destination.
+pub struct Backoff {
+    config: BackoffConfig,
+    destinations: Mutex<HashMap<SocketAddr, Failures>>,
+}
+
+impl Backoff {
+    pub fn new(config: BackoffConfig) -> Backoff {
+        Backoff {
+            config,
+            destinations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns how much longer queries to `address` are refused, or `None`
+    /// when they are allowed.
+    pub fn remaining(&self, address: &SocketAddr) -> Result<Option<Duration>> {
+        let destinations = self.destinations.lock()?;
+        let failures = match destinations.get(address) {
+            Some(failures) => failures,
+            None => return Ok(None),
+        };
+        let delay = match self.delay(failures.consecutive) {
+            Some(delay) => delay,
+            None => return Ok(None),
+        };
+
+        Ok((failures.last_failure + delay).checked_duration_since(Instant::now()))
+    }
+
+    pub fn record_success(&self, address: &SocketAddr) -> Result<()> {
+        self.destinations.lock()?.remove(address);
+
+        Ok(())
+    }
+
+    pub fn record_failure(&self, address: SocketAddr) -> Result<()> {
+        let now = Instant::now();
+        let mut destinations = self.destinations.lock()?;
+
+        if destinations.len() >= MAX_TRACKED_DESTINATIONS && !destinations.contains_key(&address) {
+            destinations.retain(|_, failures| match self.delay(failures.consecutive) {
+                Some(delay) => failures.last_failure + delay > now,
+                None => false,
+            });
+        }
+
+        let failures = destinations.entry(address).or_insert(Failures {
+            consecutive: 0,
+            last_failure: now,
+        });
+        failures.consecutive = failures.consecutive.saturating_add(1);
+        failures.last_failure = now;
+
+        Ok(())
+    }
+
+    /// Time queries are refused for after `consecutive` failures in a row.
+    fn delay(&self, consecutive: u32) -> Option<Duration> {
+        let doublings = consecutive.checked_sub(self.config.failure_threshold)?;
+        let factor = 2u32.checked_pow(doublings).unwrap_or(u32::MAX);
+
+        Some(
+            self.config
+                .initial
+                .checked_mul(factor)
+                .map_or(self.config.max, |delay| delay.min(self.config.max)),
+        )
+    }
+}
@@ -1,9 +1,16 @@
 //! Handle incoming responses and queries from other nodes.
 
-use crate::recv_errors::{
-    Error,
-    ErrorKind,
-    Result,
+use crate::{
+    message_observer::{
+        MessageDirection,
+        MessageObserver,
+    },
+    metrics::MetricsCounters,
+    recv_errors::{
+        Error,
+        ErrorKind,
+        Result,
+    },
 };
 use futures::{
     stream,
@@ -20,32 +27,70 @@ use tokio::{
     net::UdpSocket,
 };
 
+/// Receives messages of up to `recv_buffer_size` bytes along with the bytes
+/// they were decoded from. Larger messages fail with
+/// [`ErrorKind::MessageTruncated`]. Messages received are counted in
+/// `metrics` and passed to `observer` when present.
 pub fn receive_inbound_messages(
     recv_socket: Arc<UdpSocket>,
-) -> impl TryStream<Ok = (Envelope, SocketAddr), Error = Error> {
-    let recv_buffer = [0 as u8; 1024];
+    recv_buffer_size: usize,
+    metrics: Option<Arc<MetricsCounters>>,
+    observer: Option<MessageObserver>,
+) -> impl TryStream<Ok = (Envelope, SocketAddr, Vec<u8>), Error = Error> {
+    // One extra byte to tell a message which exactly fits apart from one which
+    // was cut off.
+    let recv_buffer = vec![0 as u8; recv_buffer_size + 1];
 
     stream::unfold(
-        (recv_socket, recv_buffer),
-        |(recv_socket, mut recv_buffer)| async move {
-            let result = receive_inbound_message(recv_socket.clone(), &mut recv_buffer).await;
+        (recv_socket, recv_buffer, metrics, observer),
+        |(recv_socket, mut recv_buffer, metrics, observer)| async move {
+            let result = receive_inbound_message(
+                recv_socket.clone(),
+                &mut recv_buffer,
+                metrics.as_deref(),
+                observer.as_ref(),
+            )
+            .await;
 
-            Some((result, (recv_socket, recv_buffer)))
+            Some((result, (recv_socket, recv_buffer, metrics, observer)))
         },
     )
 }
 
 async fn receive_inbound_message(
     recv_socket: Arc<UdpSocket>,
-    recv_buffer: &mut [u8; 1024],
-) -> Result<(Envelope, SocketAddr)> {
+    recv_buffer: &mut [u8],
+    metrics: Option<&MetricsCounters>,
+    observer: Option<&MessageObserver>,
+) -> Result<(Envelope, SocketAddr, Vec<u8>)> {
     let (size, from_addr) = recv_socket
         .recv_from(recv_buffer)
         .await
         .map_err(|cause| ErrorKind::FailedToReceiveMessage { cause })?;
 
-    let envelope = Envelope::decode(&recv_buffer[..size])
-        .map_err(|cause| ErrorKind::ParseInboundMessageError { cause })?;
+    if let Some(metrics) = metrics {
+        metrics.received(size);
+    }
 
-    Ok((envelope, from_addr))
+    let max_size = recv_buffer.len() - 1;
+    if size > max_size {
+        return Err(ErrorKind::MessageTruncated {
+            from: from_addr,
+            max_size,
+        })?;
+    }
+
+    if let Some(observe) = observer {
+        observe(MessageDirection::Received, from_addr, &recv_buffer[..size]);
+    }
+
+    let envelope = Envelope::decode(&recv_buffer[..size]).map_err(|cause| {
+        if let Some(metrics) = metrics {
+            metrics.decode_failed();
+        }
+
+        ErrorKind::ParseInboundMessageError { cause }
+    })?;
+
+    Ok((envelope, from_addr, recv_buffer[..size].to_vec()))
 }
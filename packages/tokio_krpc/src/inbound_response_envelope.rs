@@ -1,3 +1,7 @@
+use crate::send_errors::{
+    ErrorKind,
+    Result,
+};
 use krpc_encoding as proto;
 
 /// Inbound response sent from another node associated with an earlier query
@@ -5,9 +9,22 @@ use krpc_encoding as proto;
 pub struct InboundResponseEnvelope {
     pub transaction_id: Vec<u8>,
     pub response: ResponseType,
+
+    /// The message exactly as it arrived.
+    pub raw: Vec<u8>,
 }
 
 pub enum ResponseType {
     Error { error: proto::KRPCError },
     Response { response: proto::Response },
 }
+
+impl InboundResponseEnvelope {
+    /// The response, or the error the node responded with.
+    pub fn into_response(self) -> Result<proto::Response> {
+        match self.response {
+            ResponseType::Response { response } => Ok(response),
+            ResponseType::Error { error } => Err(ErrorKind::ReceivedKRPCError { error })?,
+        }
+    }
+}
@@ -1,23 +1,33 @@
 use crate::{
     active_transactions::ActiveTransactions,
+    backoff::Backoff,
     inbound::receive_inbound_messages,
     inbound_response_envelope::{
         InboundResponseEnvelope,
         ResponseType,
     },
+    message_observer::MessageObserver,
+    metrics::MetricsCounters,
+    rate_limiter::RateLimiter,
     recv_errors::Error,
     InboundQuery,
+    KRPCNodeBuilder,
     SendTransport,
+    ShutdownHandle,
 };
 use futures::{
     future,
+    stream,
     Stream,
     TryStreamExt,
 };
 use krpc_encoding::Message;
 use std::{
     self,
-    net::SocketAddr,
+    net::{
+        SocketAddr,
+        SocketAddrV4,
+    },
     sync::Arc,
 };
 use tokio::{
@@ -25,20 +35,52 @@ use tokio::{
     net::UdpSocket,
 };
 
+/// Called with the address of a responder and the address it reports seeing
+/// us at.
+pub(crate) type ExternalAddrReport = Arc<dyn Fn(SocketAddr, SocketAddrV4) + Send + Sync>;
+
 /// Handles making queries to other nodes, receiving responses and processing
 /// queries from other nodes
 pub struct KRPCNode {
     socket: Arc<UdpSocket>,
     transactions: ActiveTransactions,
+    query_limiter: Option<RateLimiter>,
+    backoff: Option<Backoff>,
+    recv_buffer_size: usize,
+    external_addr_report: Option<ExternalAddrReport>,
+    metrics: Option<Arc<MetricsCounters>>,
+    message_observer: Option<MessageObserver>,
 }
 
 impl KRPCNode {
     pub fn new(socket: UdpSocket) -> KRPCNode {
-        let transactions = ActiveTransactions::new();
+        KRPCNode::builder().build(socket)
+    }
+
+    /// Starts building a node with non-default settings.
+    pub fn builder() -> KRPCNodeBuilder {
+        KRPCNodeBuilder::new()
+    }
 
+    pub(crate) fn from_parts(
+        socket: UdpSocket,
+        transactions: ActiveTransactions,
+        query_limiter: Option<RateLimiter>,
+        backoff: Option<Backoff>,
+        recv_buffer_size: usize,
+        external_addr_report: Option<ExternalAddrReport>,
+        metrics: Option<Arc<MetricsCounters>>,
+        message_observer: Option<MessageObserver>,
+    ) -> KRPCNode {
         KRPCNode {
             socket: Arc::new(socket),
             transactions,
+            query_limiter,
+            backoff,
+            recv_buffer_size,
+            external_addr_report,
+            metrics,
+            message_observer,
         }
     }
 
@@ -48,35 +90,65 @@ impl KRPCNode {
     /// be polled to process responses to outbound requests.
     ///
     /// # Returns
-    /// A handle to send messages to other nodes and a stream of inbound
-    /// requests. Errors occur on the stream whenever an error occurs while
-    /// processing an inbound message.
+    /// A handle to send messages to other nodes, a stream of inbound requests
+    /// and a handle to stop the node. Errors occur on the stream whenever an
+    /// error occurs while processing an inbound message. The stream ends once
+    /// the node is shut down.
     pub fn serve(
         self,
     ) -> (
         SendTransport,
         impl Stream<Item = Result<(InboundQuery, SocketAddr), Error>>,
+        ShutdownHandle,
     ) {
         let transactions = self.transactions.clone();
+        let external_addr_report = self.external_addr_report;
+        let metrics = self.metrics.clone();
 
         let recv_half = self.socket.clone();
         let send_half = self.socket;
 
-        let query_stream = receive_inbound_messages(recv_half)
-            .map_ok(move |(envelope, from_addr)| match envelope.message_type {
+        let inbound = receive_inbound_messages(
+            recv_half,
+            self.recv_buffer_size,
+            self.metrics.clone(),
+            self.message_observer.clone(),
+        );
+        let query_stream = inbound
+            .map_ok(move |(envelope, from_addr, raw)| match envelope.message_type {
                 Message::Response { response } => {
-                    transactions.handle_response(InboundResponseEnvelope {
-                        transaction_id: envelope.transaction_id,
-                        response: ResponseType::Response { response },
-                    })?;
+                    if let Some(metrics) = &metrics {
+                        metrics.response_received();
+                    }
+
+                    transactions.handle_response(
+                        InboundResponseEnvelope {
+                            transaction_id: envelope.transaction_id,
+                            response: ResponseType::Response { response },
+                            raw,
+                        },
+                        from_addr,
+                    )?;
+
+                    if let (Some(report), Some(ip)) = (&external_addr_report, envelope.ip) {
+                        report(from_addr, ip.into());
+                    }
 
                     Ok(None)
                 }
                 Message::Error { error } => {
-                    transactions.handle_response(InboundResponseEnvelope {
-                        transaction_id: envelope.transaction_id,
-                        response: ResponseType::Error { error },
-                    })?;
+                    if let Some(metrics) = &metrics {
+                        metrics.response_received();
+                    }
+
+                    transactions.handle_response(
+                        InboundResponseEnvelope {
+                            transaction_id: envelope.transaction_id,
+                            response: ResponseType::Error { error },
+                            raw,
+                        },
+                        from_addr,
+                    )?;
 
                     Ok(None)
                 }
@@ -86,10 +158,20 @@ impl KRPCNode {
                 ))),
             })
             .try_filter_map(|result| future::ready(result));
+        let (query_stream, abort_handle) = stream::abortable(query_stream);
+        let shutdown_handle = ShutdownHandle::new(abort_handle, self.transactions.clone());
 
         (
-            SendTransport::new(send_half, self.transactions),
+            SendTransport::new(
+                send_half,
+                self.transactions,
+                self.query_limiter,
+                self.backoff,
+                self.metrics,
+                self.message_observer,
+            ),
             query_stream,
+            shutdown_handle,
         )
     }
 }
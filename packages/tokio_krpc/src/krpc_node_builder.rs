@@ -0,0 +1,193 @@
+use crate::{
+    active_transactions::ActiveTransactions,
+    backoff::{
+        Backoff,
+        BackoffConfig,
+    },
+    krpc_node::ExternalAddrReport,
+    message_observer::{
+        MessageDirection,
+        MessageObserver,
+    },
+    metrics::MetricsCounters,
+    rate_limiter::RateLimiter,
+    transaction_id::{
+        RandomTransactionIds,
+        TransactionIdGenerator,
+        TransactionIdWidth,
+    },
+    KRPCNode,
+};
+use std::{
+    net::{
+        SocketAddr,
+        SocketAddrV4,
+    },
+    sync::Arc,
+};
+use tokio::net::UdpSocket;
+
+/// Size of the buffer inbound messages are received into unless configured
+/// with [`KRPCNodeBuilder::recv_buffer_size`].
+pub const DEFAULT_RECV_BUFFER_SIZE: usize = 4096;
+
+/// Builds a [`KRPCNode`] with non-default settings.
+///
+/// ```
+/// # use std::error::Error;
+/// # use tokio::net::UdpSocket;
+/// use tokio_krpc::{KRPCNode, TransactionIdWidth};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn Error>> {
+/// let socket = UdpSocket::bind("0.0.0.0:0").await?;
+/// let node = KRPCNode::builder()
+///     .transaction_id_width(TransactionIdWidth::Two)
+///     .build(socket);
+/// # Ok(())
+/// # }
+/// ```
+pub struct KRPCNodeBuilder {
+    transaction_id_width: TransactionIdWidth,
+    transaction_id_generator: Arc<dyn TransactionIdGenerator>,
+    max_queries_per_second: Option<u32>,
+    log_duplicate_responses: bool,
+    backoff: Option<BackoffConfig>,
+    recv_buffer_size: usize,
+    external_addr_report: Option<ExternalAddrReport>,
+    collect_metrics: bool,
+    message_observer: Option<MessageObserver>,
+}
+
+impl KRPCNodeBuilder {
+    pub(crate) fn new() -> KRPCNodeBuilder {
+        KRPCNodeBuilder {
+            transaction_id_width: TransactionIdWidth::default(),
+            transaction_id_generator: Arc::new(RandomTransactionIds),
+            max_queries_per_second: None,
+            log_duplicate_responses: false,
+            backoff: None,
+            recv_buffer_size: DEFAULT_RECV_BUFFER_SIZE,
+            external_addr_report: None,
+            collect_metrics: false,
+            message_observer: None,
+        }
+    }
+
+    /// Sets the number of bytes in transaction ids of queries sent by the
+    /// node. Defaults to four bytes.
+    pub fn transaction_id_width(mut self, width: TransactionIdWidth) -> KRPCNodeBuilder {
+        self.transaction_id_width = width;
+        self
+    }
+
+    /// Picks transaction ids with `generator` instead of at random.
+    pub fn transaction_id_generator<G>(mut self, generator: G) -> KRPCNodeBuilder
+    where
+        G: TransactionIdGenerator + 'static,
+    {
+        self.transaction_id_generator = Arc::new(generator);
+        self
+    }
+
+    /// Caps the number of queries sent per second across all destinations.
+    /// A second's worth of queries can go out at once, after which queries
+    /// over the limit wait for their turn instead of failing. Responses to
+    /// other nodes aren't limited. Unlimited by default.
+    ///
+    /// Queries to destinations being backed off are refused before waiting,
+    /// so they don't use up the budget. Time spent waiting for a turn doesn't
+    /// count towards the timeouts of
+    /// [`RequestTransport`](crate::RequestTransport).
+    pub fn max_queries_per_second(mut self, limit: u32) -> KRPCNodeBuilder {
+        self.max_queries_per_second = Some(limit);
+        self
+    }
+
+    /// Logs responses received for a transaction which already has a
+    /// response at debug level. Duplicates are counted either way, see
+    /// [`SendTransport::duplicate_responses`](crate::SendTransport::duplicate_responses).
+    pub fn log_duplicate_responses(mut self, log: bool) -> KRPCNodeBuilder {
+        self.log_duplicate_responses = log;
+        self
+    }
+
+    /// Refuses queries to destinations which keep failing to respond, as
+    /// described by `config`, failing them with
+    /// [`ErrorKind::BackingOff`](crate::send_errors::ErrorKind::BackingOff).
+    /// Only queries which time out or can't be sent count as failures,
+    /// queries dropped by the caller before a response arrives aren't counted
+    /// at all. Off by default.
+    pub fn backoff(mut self, config: BackoffConfig) -> KRPCNodeBuilder {
+        self.backoff = Some(config);
+        self
+    }
+
+    /// Sets the largest inbound message, in bytes, which can be received.
+    /// Larger messages fail with
+    /// [`ErrorKind::MessageTruncated`](crate::recv_errors::ErrorKind::MessageTruncated)
+    /// on the inbound stream. Defaults to [`DEFAULT_RECV_BUFFER_SIZE`].
+    pub fn recv_buffer_size(mut self, size: usize) -> KRPCNodeBuilder {
+        self.recv_buffer_size = size;
+        self
+    }
+
+    /// Calls `report` with the address of each node which responds to one of
+    /// our queries along with the address it says it sees us at, from the `ip`
+    /// field of the response ([BEP-0042]). Responses without the field and
+    /// responses to unknown transactions aren't reported.
+    ///
+    /// [BEP-0042]: http://www.bittorrent.org/beps/bep_0042.html
+    pub fn on_external_addr_report<F>(mut self, report: F) -> KRPCNodeBuilder
+    where
+        F: Fn(SocketAddr, SocketAddrV4) + Send + Sync + 'static,
+    {
+        self.external_addr_report = Some(Arc::new(report));
+        self
+    }
+
+    /// Counts messages and bytes sent and received, read with
+    /// [`SendTransport::metrics`](crate::SendTransport::metrics). Off by
+    /// default.
+    pub fn collect_metrics(mut self, collect: bool) -> KRPCNodeBuilder {
+        self.collect_metrics = collect;
+        self
+    }
+
+    /// Calls `observe` with every message sent or received by the node, along
+    /// with the address of the other node and the message's raw bencoded
+    /// bytes. Received messages are observed before they are decoded, so ones
+    /// which fail to decode are included. Messages too large for the receive
+    /// buffer and messages which failed to send aren't observed.
+    ///
+    /// `observe` runs inline on every message so it should return quickly.
+    pub fn on_message<F>(mut self, observe: F) -> KRPCNodeBuilder
+    where
+        F: Fn(MessageDirection, SocketAddr, &[u8]) + Send + Sync + 'static,
+    {
+        self.message_observer = Some(Arc::new(observe));
+        self
+    }
+
+    /// Builds a node receiving and sending messages on `socket`.
+    pub fn build(self, socket: UdpSocket) -> KRPCNode {
+        KRPCNode::from_parts(
+            socket,
+            ActiveTransactions::new(
+                self.transaction_id_width,
+                self.transaction_id_generator,
+                self.log_duplicate_responses,
+            ),
+            self.max_queries_per_second.map(RateLimiter::new),
+            self.backoff.map(Backoff::new),
+            self.recv_buffer_size,
+            self.external_addr_report,
+            if self.collect_metrics {
+                Some(Arc::new(MetricsCounters::default()))
+            } else {
+                None
+            },
+            self.message_observer,
+        )
+    }
+}
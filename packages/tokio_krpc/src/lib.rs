@@ -20,7 +20,7 @@
 //!     let socket = UdpSocket::bind::<SocketAddrV4>(bind_addr).await?;
 //!     let node = KRPCNode::new(socket);
 //!     let node_id = NodeID::random();
-//!     let (send_transport, inbound_requests) = node.serve();
+//!     let (send_transport, inbound_requests, _shutdown) = node.serve();
 //!     let request_transport = RequestTransport::new(node_id, send_transport);
 //!
 //!     spawn(
@@ -45,23 +45,50 @@
 // TODO: Write Docs for responses module
 
 mod active_transactions;
+mod backoff;
 mod inbound;
 mod inbound_query;
 mod inbound_response_envelope;
 mod krpc_node;
-mod port_type;
+mod krpc_node_builder;
+mod message_observer;
+mod metrics;
+mod query_timeouts;
+mod query_trace;
+mod rate_limiter;
 pub mod recv_errors;
 mod request_transport;
 mod response_future;
 pub mod responses;
 pub mod send_errors;
 mod send_transport;
+mod shutdown;
 mod transaction_id;
 
+pub use krpc_encoding::PortType;
+
 pub use self::{
+    backoff::BackoffConfig,
     inbound_query::InboundQuery,
     krpc_node::KRPCNode,
-    port_type::PortType,
+    krpc_node_builder::{
+        KRPCNodeBuilder,
+        DEFAULT_RECV_BUFFER_SIZE,
+    },
+    message_observer::MessageDirection,
+    metrics::TransportMetrics,
+    query_timeouts::{
+        QueryTimeouts,
+        DEFAULT_QUERY_TIMEOUT,
+    },
+    query_trace::QueryTrace,
     request_transport::RequestTransport,
     send_transport::SendTransport,
+    shutdown::ShutdownHandle,
+    transaction_id::{
+        RandomTransactionIds,
+        TransactionId,
+        TransactionIdGenerator,
+        TransactionIdWidth,
+    },
 };
@@ -0,0 +1,16 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+};
+
+/// Whether an observed message was sent by this node or received from
+/// another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageDirection {
+    Sent,
+    Received,
+}
+
+/// Called with every message sent or received along with the address of the
+/// other node and the message's raw bencoded bytes.
+pub(crate) type MessageObserver = Arc<dyn Fn(MessageDirection, SocketAddr, &[u8]) + Send + Sync>;
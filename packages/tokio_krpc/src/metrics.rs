@@ -0,0 +1,79 @@
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+/// Counts of what a transport has sent and received since it was built. See
+/// [`SendTransport::metrics`](crate::SendTransport::metrics).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TransportMetrics {
+    /// Queries sent which expect a response.
+    pub requests_sent: u64,
+
+    /// Responses and errors received from other nodes, including responses to
+    /// unknown transactions.
+    pub responses_received: u64,
+
+    /// Requests which gave up waiting for a response.
+    pub timeouts: u64,
+
+    /// Inbound messages which couldn't be decoded.
+    pub decode_failures: u64,
+
+    /// Bytes of every message sent, queries and responses.
+    pub bytes_sent: u64,
+
+    /// Bytes of every message received, including ones which couldn't be
+    /// decoded.
+    pub bytes_received: u64,
+}
+
+/// Counters behind [`TransportMetrics`], shared between the sending and
+/// receiving halves of a node.
+#[derive(Default)]
+pub(crate) struct MetricsCounters {
+    requests_sent: AtomicU64,
+    responses_received: AtomicU64,
+    timeouts: AtomicU64,
+    decode_failures: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl MetricsCounters {
+    pub fn request_sent(&self) {
+        self.requests_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn response_received(&self) {
+        self.responses_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn timed_out(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn decode_failed(&self) {
+        self.decode_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TransportMetrics {
+        TransportMetrics {
+            requests_sent: self.requests_sent.load(Ordering::Relaxed),
+            responses_received: self.responses_received.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            decode_failures: self.decode_failures.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
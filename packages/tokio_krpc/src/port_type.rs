@@ -1,4 +0,0 @@
-pub enum PortType {
-    Implied,
-    Port(u16),
-}
@@ -0,0 +1,52 @@
+use krpc_encoding::Query;
+use std::time::Duration;
+
+/// Time waited for a response to a query before failing it unless configured
+/// with [`RequestTransport::with_timeouts`].
+///
+/// [`RequestTransport::with_timeouts`]: crate::RequestTransport::with_timeouts
+pub const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time waited for a response to each type of query sent by a
+/// [`RequestTransport`](crate::RequestTransport) before failing it with
+/// [`ErrorKind::Timeout`](crate::send_errors::ErrorKind::Timeout).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryTimeouts {
+    pub ping: Duration,
+    pub find_node: Duration,
+    pub get_peers: Duration,
+    pub announce_peer: Duration,
+    pub sample_infohashes: Duration,
+}
+
+impl QueryTimeouts {
+    /// Waits `timeout` for a response to every type of query.
+    pub fn uniform(timeout: Duration) -> QueryTimeouts {
+        QueryTimeouts {
+            ping: timeout,
+            find_node: timeout,
+            get_peers: timeout,
+            announce_peer: timeout,
+            sample_infohashes: timeout,
+        }
+    }
+
+    /// Time waited for a response to `query`. `get` and `put` queries use the
+    /// timeouts of the queries they resemble, `get_peers` and
+    /// `announce_peer`.
+    pub fn for_query(&self, query: &Query) -> Duration {
+        match query {
+            Query::Ping { .. } => self.ping,
+            Query::FindNode { .. } => self.find_node,
+            Query::GetPeers { .. } | Query::Get { .. } => self.get_peers,
+            Query::AnnouncePeer { .. } | Query::Put { .. } => self.announce_peer,
+            Query::SampleInfoHashes { .. } => self.sample_infohashes,
+        }
+    }
+}
+
+impl Default for QueryTimeouts {
+    fn default() -> QueryTimeouts {
+        QueryTimeouts::uniform(DEFAULT_QUERY_TIMEOUT)
+    }
+}
@@ -0,0 +1,46 @@
+use crate::transaction_id::TransactionId;
+use std::{
+    net::SocketAddr,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+/// What went over the wire for a single query sent with
+/// [`RequestTransport::query_traced`].
+///
+/// [`RequestTransport::query_traced`]: crate::RequestTransport::query_traced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryTrace {
+    pub address: SocketAddr,
+
+    /// `None` if the query was refused before a transaction was started.
+    pub transaction_id: Option<TransactionId>,
+
+    /// The encoded query. Empty if it was never encoded.
+    pub sent: Vec<u8>,
+
+    /// The response or error message exactly as received. `None` if nothing
+    /// arrived.
+    pub received: Option<Vec<u8>>,
+
+    /// When the query was sent.
+    pub sent_at: Option<Instant>,
+
+    /// Time from sending the query until the response arrived.
+    pub elapsed: Option<Duration>,
+}
+
+impl QueryTrace {
+    pub(crate) fn new(address: SocketAddr) -> QueryTrace {
+        QueryTrace {
+            address,
+            transaction_id: None,
+            sent: Vec::new(),
+            received: None,
+            sent_at: None,
+            elapsed: None,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use futures::lock::Mutex;
+use std::time::Duration;
+use tokio::time::{
+    sleep,
+    Instant,
+};
+
+/// Token bucket keeping events to a fixed number each second on average.
+/// Holds up to a second's worth of events, so after a quiet period that many
+/// can happen at once before the rest are spaced out.
+pub struct RateLimiter {
+    per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Events which may happen right away. Fractional between refills.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32) -> RateLimiter {
+        let per_second = f64::from(per_second.max(1));
+
+        RateLimiter {
+            per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: per_second,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until the next event is allowed. The token is only taken once
+    /// the wait is over, so callers which stop waiting don't use up tokens.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                let elapsed = now.saturating_duration_since(bucket.refilled_at);
+                bucket.tokens =
+                    (bucket.tokens + elapsed.as_secs_f64() * self.per_second).min(self.per_second);
+                bucket.refilled_at = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second)
+            };
+
+            sleep(wait).await;
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
     backtrace::Backtrace,
     io,
+    net::SocketAddr,
+    sync::PoisonError,
 };
 use thiserror::Error;
 
@@ -16,6 +18,13 @@ pub enum ErrorKind {
     #[error("invalid transaction id")]
     InvalidResponseTransactionId,
 
+    #[error(
+        "message from {} didn't fit in the {} byte receive buffer",
+        from,
+        max_size
+    )]
+    MessageTruncated { from: SocketAddr, max_size: usize },
+
     #[error("failed to parse inbound message")]
     ParseInboundMessageError {
         #[source]
@@ -27,6 +36,21 @@ pub enum ErrorKind {
         transaction_id
     )]
     UnknownTransactionReceived { transaction_id: u32 },
+
+    #[error(
+        "received response for transaction_id={} from {} but the request was sent to {}",
+        transaction_id,
+        got,
+        expected
+    )]
+    ResponseAddressMismatch {
+        transaction_id: u32,
+        expected: SocketAddr,
+        got: SocketAddr,
+    },
+
+    #[error("lock poisoned")]
+    LockPoisoned,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -38,3 +62,15 @@ pub struct Error {
     inner: ErrorKind,
     backtrace: Backtrace,
 }
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner
+    }
+}
+
+impl<Guard> From<PoisonError<Guard>> for Error {
+    fn from(_err: PoisonError<Guard>) -> Error {
+        ErrorKind::LockPoisoned.into()
+    }
+}
@@ -1,27 +1,41 @@
 use crate::{
+    query_timeouts::QueryTimeouts,
+    query_trace::QueryTrace,
     responses::{
         FindNodeResponse,
         GetPeersResponse,
         NodeIDResponse,
+        SamplesResponse,
+    },
+    send_errors::{
+        ErrorKind,
+        Result,
     },
-    send_errors::Result,
-    PortType,
     SendTransport,
 };
 use krpc_encoding::{
     NodeID,
+    NodeInfo,
+    PortType,
     Query,
+    Response,
 };
 use std::{
     borrow::Borrow,
-    net::SocketAddrV4,
+    net::SocketAddr,
+    time::Duration,
 };
 
+/// Upper bound on the number of nodes queried by
+/// [`RequestTransport::locate_greedy`].
+const MAX_GREEDY_HOPS: usize = 32;
+
 /// High level wrapper around a UDP socket for sending typed queries and
 /// receiving typed responses.
 pub struct RequestTransport {
     id: NodeID,
     send_transport: Box<dyn Borrow<SendTransport>>,
+    timeouts: QueryTimeouts,
 }
 
 impl RequestTransport {
@@ -32,17 +46,62 @@ impl RequestTransport {
         RequestTransport {
             id,
             send_transport: Box::new(send_transport),
+            timeouts: QueryTimeouts::default(),
         }
     }
 
-    pub async fn ping(&self, address: SocketAddrV4) -> Result<NodeID> {
-        let response = (*self.send_transport)
+    /// Sets how long [`RequestTransport::query`] and the typed helpers wait
+    /// for a response to each type of query.
+    pub fn with_timeouts(mut self, timeouts: QueryTimeouts) -> RequestTransport {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Like [`RequestTransport::with_timeouts`] but waits `timeout` for a
+    /// response to every type of query.
+    pub fn with_timeout(self, timeout: Duration) -> RequestTransport {
+        self.with_timeouts(QueryTimeouts::uniform(timeout))
+    }
+
+    /// Sends `query` to `address` and returns the response without checking
+    /// that it is the kind of response expected for the query. Useful for
+    /// debugging tools which want to see exactly what a node sent back.
+    /// Fails with [`ErrorKind::Timeout`] when no response arrives within the
+    /// timeout configured for the type of query.
+    pub async fn query(&self, address: impl Into<SocketAddr>, query: Query) -> Result<Response> {
+        let limit = self.timeouts.for_query(&query);
+
+        self.query_within(address, query, limit).await
+    }
+
+    /// Like [`RequestTransport::query`] but also returns a trace of the bytes
+    /// sent and received, the transaction id and how long the response took.
+    /// For diagnosing a node which behaves oddly.
+    pub async fn query_traced(
+        &self,
+        address: impl Into<SocketAddr>,
+        query: Query,
+    ) -> (Result<Response>, QueryTrace) {
+        let address = address.into();
+        let limit = self.timeouts.for_query(&query);
+        let mut trace = QueryTrace::new(address);
+
+        let result = (*self.send_transport)
             .borrow()
-            .request(
-                address.into(),
+            .request_within(address, query, limit, Some(&mut trace))
+            .await;
+
+        (result, trace)
+    }
+
+    pub async fn ping(&self, address: impl Into<SocketAddr>) -> Result<NodeID> {
+        let response = self
+            .query_within(
+                address,
                 Query::Ping {
                     id: self.id.clone(),
                 },
+                self.timeouts.ping,
             )
             .await?;
 
@@ -51,68 +110,172 @@ impl RequestTransport {
 
     pub async fn find_node(
         &self,
-        address: SocketAddrV4,
+        address: impl Into<SocketAddr>,
         target: NodeID,
     ) -> Result<FindNodeResponse> {
-        let response = (*self.send_transport)
-            .borrow()
-            .request(
-                address.into(),
+        let response = self
+            .query_within(
+                address,
                 Query::FindNode {
                     id: self.id.clone(),
                     target,
                 },
+                self.timeouts.find_node,
             )
             .await?;
 
         Ok(FindNodeResponse::from_response(response)?)
     }
 
+    /// Asks the node at `address` for peers of `info_hash`. Fails with
+    /// [`ErrorKind::InvalidInfoHash`] without sending anything unless
+    /// `info_hash` is exactly 20 bytes.
     pub async fn get_peers(
         &self,
-        address: SocketAddrV4,
-        info_hash: NodeID,
+        address: impl Into<SocketAddr>,
+        info_hash: impl AsRef<[u8]>,
     ) -> Result<GetPeersResponse> {
-        let response = (*self.send_transport)
-            .borrow()
-            .request(
-                address.into(),
+        let info_hash = parse_info_hash(info_hash.as_ref())?;
+
+        let response = self
+            .query_within(
+                address,
                 Query::GetPeers {
                     id: self.id.clone(),
                     info_hash,
                 },
+                self.timeouts.get_peers,
             )
             .await?;
 
         Ok(GetPeersResponse::from_response(response)?)
     }
 
+    /// Announces to the node at `address` that we are a peer of `info_hash`.
+    /// Fails with [`ErrorKind::InvalidInfoHash`] without sending anything
+    /// unless `info_hash` is exactly 20 bytes.
     pub async fn announce_peer(
         &self,
         token: Vec<u8>,
-        address: SocketAddrV4,
-        info_hash: NodeID,
+        address: impl Into<SocketAddr>,
+        info_hash: impl AsRef<[u8]>,
         port_type: PortType,
     ) -> Result<NodeID> {
-        let (port, implied_port) = match port_type {
-            PortType::Implied => (None, true),
-            PortType::Port(port) => (Some(port), false),
-        };
+        let info_hash = parse_info_hash(info_hash.as_ref())?;
+        if let PortType::Missing = port_type {
+            return Err(ErrorKind::MissingAnnouncePort)?;
+        }
 
-        let response = (*self.send_transport)
-            .borrow()
-            .request(
-                address.into(),
+        let response = self
+            .query_within(
+                address,
                 Query::AnnouncePeer {
                     id: self.id.clone(),
-                    token,
+                    token: Some(token),
                     info_hash,
-                    port,
-                    implied_port,
+                    port: port_type,
                 },
+                self.timeouts.announce_peer,
             )
             .await?;
 
         Ok(NodeIDResponse::from_response(response)?)
     }
+
+    /// Asks the node at `address` for a sample of the info hashes it knows
+    /// about along with the nodes closest to `target` ([BEP-0051]). Walking
+    /// the keyspace with this query harvests info hashes much faster than
+    /// waiting for announces.
+    ///
+    /// [BEP-0051]: http://www.bittorrent.org/beps/bep_0051.html
+    pub async fn sample_infohashes(
+        &self,
+        address: impl Into<SocketAddr>,
+        target: NodeID,
+    ) -> Result<SamplesResponse> {
+        let response = self
+            .query_within(
+                address,
+                Query::SampleInfoHashes {
+                    id: self.id.clone(),
+                    target,
+                },
+                self.timeouts.sample_infohashes,
+            )
+            .await?;
+
+        Ok(SamplesResponse::from_response(response)?)
+    }
+
+    /// Like [`RequestTransport::query`] but fails with
+    /// [`ErrorKind::Timeout`] unless a response arrives within `limit`. Time
+    /// spent waiting for the queries per second limit isn't counted.
+    async fn query_within(
+        &self,
+        address: impl Into<SocketAddr>,
+        query: Query,
+        limit: Duration,
+    ) -> Result<Response> {
+        (*self.send_transport)
+            .borrow()
+            .request_within(address.into(), query, limit, None)
+            .await
+    }
+
+    /// Locates the node closest to `target` reachable from `seed` by
+    /// repeatedly querying only the single closest node returned by the
+    /// previously queried node.
+    ///
+    /// Sends far fewer queries than an iterative lookup at the cost of
+    /// possibly stopping at a node which isn't the closest in the network.
+    /// Stops when the closest returned node is no closer than the last queried
+    /// node or when querying it fails.
+    ///
+    /// # Returns
+    /// The closest node which responded.
+    pub async fn locate_greedy(
+        &self,
+        target: NodeID,
+        seed: impl Into<SocketAddr>,
+    ) -> Result<NodeInfo> {
+        let seed = seed.into();
+        let response = self.find_node(seed, target.clone()).await?;
+        let mut closest = NodeInfo::new(response.id, seed);
+        let mut next_nodes = response.nodes;
+
+        for _ in 0..MAX_GREEDY_HOPS {
+            let next = match next_nodes
+                .into_iter()
+                .min_by_key(|node| &*node.node_id ^ &*target)
+            {
+                Some(next) => next,
+                None => break,
+            };
+
+            if &*next.node_id ^ &*target >= &*closest.node_id ^ &*target {
+                break;
+            }
+
+            let response = match self.find_node(next.address, target.clone()).await {
+                Ok(response) => response,
+                Err(_) => break,
+            };
+
+            closest = NodeInfo::new(response.id, next.address);
+            next_nodes = response.nodes;
+        }
+
+        Ok(closest)
+    }
+}
+
+/// Refuses to build a query for an info hash which isn't exactly 20 bytes.
+/// Checked on the bytes since a [`NodeID`] doesn't keep leading zeros.
+fn parse_info_hash(info_hash: &[u8]) -> Result<NodeID> {
+    NodeID::try_from_bytes(info_hash).map_err(|_| {
+        ErrorKind::InvalidInfoHash {
+            len: info_hash.len(),
+        }
+        .into()
+    })
 }
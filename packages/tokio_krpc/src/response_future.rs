@@ -1,17 +1,14 @@
 use crate::{
     active_transactions::ActiveTransactions,
-    inbound_response_envelope::{
-        InboundResponseEnvelope,
-        ResponseType,
-    },
-    send_errors::{
-        ErrorKind,
-        Result,
-    },
+    inbound_response_envelope::InboundResponseEnvelope,
+    send_errors::Result,
     transaction_id::TransactionId,
 };
 use futures::TryFutureExt;
-use std::future::Future;
+use std::{
+    future::Future,
+    net::SocketAddr,
+};
 
 use krpc_encoding as proto;
 use std::{
@@ -30,19 +27,25 @@ pub struct ResponseFuture {
 }
 
 impl ResponseFuture {
-    pub async fn wait_for_tx(
-        transaction_id: TransactionId,
+    /// Allocates a transaction id not used by any other in-flight request and
+    /// starts tracking a request to `address` with it. The transaction is
+    /// dropped along with the returned future.
+    pub fn allocate(
+        address: SocketAddr,
         transactions: ActiveTransactions,
-    ) -> Result<proto::Response> {
-        transactions.add_transaction(transaction_id);
-        let envelope = ResponseFuture::new(transaction_id, transactions)
-            .into_future()
-            .await?;
+    ) -> Result<ResponseFuture> {
+        let transaction_id = transactions.allocate(address)?;
 
-        match envelope.response {
-            ResponseType::Response { response } => Ok(response),
-            ResponseType::Error { error } => Err(ErrorKind::ReceivedKRPCError { error })?,
-        }
+        Ok(ResponseFuture::new(transaction_id, transactions))
+    }
+
+    pub fn transaction_id(&self) -> TransactionId {
+        self.transaction_id
+    }
+
+    /// Waits for the response to the transaction.
+    pub async fn wait(self) -> Result<proto::Response> {
+        self.into_future().await?.into_response()
     }
 
     fn new(transaction_id: TransactionId, transactions: ActiveTransactions) -> ResponseFuture {
@@ -64,6 +67,8 @@ impl Future for ResponseFuture {
 
 impl Drop for ResponseFuture {
     fn drop(&mut self) {
-        self.transactions.drop_transaction(self.transaction_id);
+        // Nothing to do about a poisoned lock while dropping, and panicking
+        // here would only spread the panic further.
+        let _ = self.transactions.drop_transaction(self.transaction_id);
     }
 }
@@ -1,6 +1,9 @@
-use crate::send_errors::{
-    ErrorKind,
-    Result,
+use crate::{
+    responses::merge_node_families,
+    send_errors::{
+        ErrorKind,
+        Result,
+    },
 };
 
 use krpc_encoding::{
@@ -11,13 +14,21 @@ use krpc_encoding::{
 
 pub struct FindNodeResponse {
     pub id: NodeID,
+
+    /// Nodes from both `nodes` and `nodes6`. Nodes with an address of the
+    /// wrong family for their field are dropped.
     pub nodes: Vec<NodeInfo>,
 }
 
 impl FindNodeResponse {
     pub fn from_response(response: proto::Response) -> Result<FindNodeResponse> {
         Ok(match response {
-            proto::Response::NextHop { id, nodes, .. } => FindNodeResponse { id, nodes },
+            proto::Response::NextHop {
+                id, nodes, nodes6, ..
+            } => FindNodeResponse {
+                id,
+                nodes: merge_node_families(nodes, nodes6),
+            },
             got => Err(ErrorKind::InvalidResponseType {
                 expected: "FindNodeResponse (NextHop)",
                 got,
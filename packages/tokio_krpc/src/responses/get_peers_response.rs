@@ -1,6 +1,9 @@
-use crate::send_errors::{
-    ErrorKind,
-    Result,
+use crate::{
+    responses::merge_node_families,
+    send_errors::{
+        ErrorKind,
+        Result,
+    },
 };
 
 use krpc_encoding::{
@@ -9,7 +12,10 @@ use krpc_encoding::{
     NodeID,
     NodeInfo,
 };
-use std::net::SocketAddrV4;
+use std::{
+    collections::HashSet,
+    net::SocketAddrV4,
+};
 
 pub struct GetPeersResponse {
     pub id: NodeID,
@@ -18,19 +24,25 @@ pub struct GetPeersResponse {
 }
 
 impl GetPeersResponse {
+    /// Peers repeated in the `values` list are only kept the first time they
+    /// appear. Nodes with an address of the wrong family for their field are
+    /// dropped.
     pub fn from_response(response: proto::Response) -> Result<GetPeersResponse> {
         Ok(match response {
             proto::Response::GetPeers { id, token, peers } => GetPeersResponse {
                 id,
                 token,
-                message_type: GetPeersResponseType::Peers(
-                    peers.into_iter().map(Addr::into).collect(),
-                ),
+                message_type: GetPeersResponseType::Peers(dedup_peers(peers)),
             },
-            proto::Response::NextHop { id, token, nodes } => GetPeersResponse {
+            proto::Response::NextHop {
+                id,
+                token,
+                nodes,
+                nodes6,
+            } => GetPeersResponse {
                 id,
                 token,
-                message_type: GetPeersResponseType::NextHop(nodes),
+                message_type: GetPeersResponseType::NextHop(merge_node_families(nodes, nodes6)),
             },
             got => Err(ErrorKind::InvalidResponseType {
                 expected: "GetPeersResponse (GetPeers or NextHop)",
@@ -40,6 +52,16 @@ impl GetPeersResponse {
     }
 }
 
+fn dedup_peers(peers: Vec<Addr>) -> Vec<SocketAddrV4> {
+    let mut seen = HashSet::new();
+
+    peers
+        .into_iter()
+        .map(Addr::into)
+        .filter(|peer: &SocketAddrV4| seen.insert(*peer))
+        .collect()
+}
+
 pub enum GetPeersResponseType {
     Peers(Vec<SocketAddrV4>),
     NextHop(Vec<NodeInfo>),
@@ -1,7 +1,37 @@
 mod find_node_response;
 mod get_peers_response;
 mod node_id_response;
+mod samples_response;
 
 pub use find_node_response::FindNodeResponse;
-pub use get_peers_response::GetPeersResponse;
+pub use get_peers_response::{
+    GetPeersResponse,
+    GetPeersResponseType,
+};
 pub use node_id_response::NodeIDResponse;
+pub use samples_response::SamplesResponse;
+
+use krpc_encoding::NodeInfo;
+
+/// Combines the nodes from the `nodes` and `nodes6` fields of a response,
+/// dropping any whose address belongs to the other field's family. Those can
+/// only come from a malformed response and would put unreachable addresses in
+/// the routing table.
+pub(crate) fn merge_node_families(nodes: Vec<NodeInfo>, nodes6: Vec<NodeInfo>) -> Vec<NodeInfo> {
+    let v4 = nodes.into_iter().filter(|node| expect_family(node, false));
+    let v6 = nodes6.into_iter().filter(|node| expect_family(node, true));
+
+    v4.chain(v6).collect()
+}
+
+/// Whether `node` has an IPv6 address exactly when `v6` is set. Logs a warning
+/// when it doesn't.
+fn expect_family(node: &NodeInfo, v6: bool) -> bool {
+    let matches = node.address.is_ipv6() == v6;
+    if !matches {
+        let field = if v6 { "nodes6" } else { "nodes" };
+        tracing::warn!(address = %node.address, field, "dropping node with wrong address family");
+    }
+
+    matches
+}
@@ -0,0 +1,58 @@
+use crate::{
+    responses::merge_node_families,
+    send_errors::{
+        ErrorKind,
+        Result,
+    },
+};
+
+use krpc_encoding::{
+    self as proto,
+    NodeID,
+    NodeInfo,
+};
+use std::time::Duration;
+
+/// Response to a `sample_infohashes` query from [BEP-0051].
+///
+/// [BEP-0051]: http://www.bittorrent.org/beps/bep_0051.html
+pub struct SamplesResponse {
+    pub id: NodeID,
+
+    /// How long to wait before querying the node again. `None` when the node
+    /// didn't say.
+    pub interval: Option<Duration>,
+
+    /// Nodes close to the target of the query.
+    pub nodes: Vec<NodeInfo>,
+
+    /// Number of info hashes the node has, of which `samples` is a sample.
+    /// `None` when the node didn't say.
+    pub num: Option<u32>,
+
+    pub samples: Vec<NodeID>,
+}
+
+impl SamplesResponse {
+    pub fn from_response(response: proto::Response) -> Result<SamplesResponse> {
+        Ok(match response {
+            proto::Response::Samples {
+                id,
+                interval,
+                nodes,
+                num,
+                samples,
+            } => SamplesResponse {
+                id,
+                interval: interval.map(|seconds| Duration::from_secs(seconds.into())),
+                nodes: merge_node_families(nodes, Vec::new()),
+                num,
+                samples,
+            },
+            got => Err(ErrorKind::InvalidResponseType {
+                expected: "SamplesResponse (Samples)",
+                got,
+            })?,
+        })
+    }
+}
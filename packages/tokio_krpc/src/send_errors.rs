@@ -2,6 +2,9 @@ use krpc_encoding as proto;
 use std::{
     backtrace::Backtrace,
     io,
+    net::SocketAddr,
+    sync::PoisonError,
+    time::Duration,
 };
 use thiserror::Error;
 
@@ -29,8 +32,42 @@ pub enum ErrorKind {
         cause: krpc_encoding::errors::Error,
     },
 
+    #[error("info hash is {} bytes instead of 20", len)]
+    InvalidInfoHash { len: usize },
+
+    #[error("announce_peer needs either a port or implied_port")]
+    MissingAnnouncePort,
+
+    #[error("refusing to send to non-routable address {}", address)]
+    NonRoutableDestination { address: SocketAddr },
+
+    #[error(
+        "backing off from {} which keeps failing, retry in {:?}",
+        address,
+        retry_in
+    )]
+    BackingOff {
+        address: SocketAddr,
+        retry_in: Duration,
+    },
+
+    #[error("no response from {} within {:?}", address, timeout)]
+    Timeout {
+        address: SocketAddr,
+        timeout: Duration,
+    },
+
     #[error("transaction state missing for transaction_id={}", transaction_id)]
     UnknownTransactionPolled { transaction_id: u32 },
+
+    #[error("every transaction id is used by an in-flight request")]
+    TransactionIdsExhausted,
+
+    #[error("node was shut down")]
+    Shutdown,
+
+    #[error("lock poisoned")]
+    LockPoisoned,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -42,3 +79,15 @@ pub struct Error {
     inner: ErrorKind,
     backtrace: Backtrace,
 }
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner
+    }
+}
+
+impl<Guard> From<PoisonError<Guard>> for Error {
+    fn from(_err: PoisonError<Guard>) -> Error {
+        ErrorKind::LockPoisoned.into()
+    }
+}
@@ -1,11 +1,21 @@
 use crate::{
     active_transactions::ActiveTransactions,
+    backoff::Backoff,
+    message_observer::{
+        MessageDirection,
+        MessageObserver,
+    },
+    metrics::{
+        MetricsCounters,
+        TransportMetrics,
+    },
+    query_trace::QueryTrace,
+    rate_limiter::RateLimiter,
     response_future::ResponseFuture,
     send_errors::{
         ErrorKind,
         Result,
     },
-    transaction_id::TransactionId,
 };
 use futures::lock::Mutex;
 use krpc_encoding::{
@@ -15,59 +25,343 @@ use krpc_encoding::{
     Query,
 };
 use std::{
-    net::SocketAddr,
-    sync::Arc,
+    net::{
+        IpAddr,
+        SocketAddr,
+    },
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use tokio::{
+    net::UdpSocket,
+    time::timeout,
 };
-use tokio::net::UdpSocket;
 
 /// Low-level wrapper around a UDP socket for sending KRPC queries and
 /// responses.
 pub struct SendTransport {
     socket: Mutex<Arc<UdpSocket>>,
     transactions: ActiveTransactions,
+
+    /// Limits the rate of queries sent across all destinations.
+    query_limiter: Option<RateLimiter>,
+
+    /// Refuses queries to destinations which keep failing to respond.
+    backoff: Option<Backoff>,
+
+    /// Whether queries may be sent to loopback addresses.
+    allow_loopback: AtomicBool,
+
+    /// Whether queries are marked read-only ([BEP-0043]).
+    ///
+    /// [BEP-0043]: http://www.bittorrent.org/beps/bep_0043.html
+    read_only: AtomicBool,
+
+    /// Counts of messages sent and received, when enabled.
+    metrics: Option<Arc<MetricsCounters>>,
+
+    /// Called with every message sent, when set.
+    message_observer: Option<MessageObserver>,
 }
 
 impl SendTransport {
-    pub(crate) fn new(socket: Arc<UdpSocket>, transactions: ActiveTransactions) -> SendTransport {
+    pub(crate) fn new(
+        socket: Arc<UdpSocket>,
+        transactions: ActiveTransactions,
+        query_limiter: Option<RateLimiter>,
+        backoff: Option<Backoff>,
+        metrics: Option<Arc<MetricsCounters>>,
+        message_observer: Option<MessageObserver>,
+    ) -> SendTransport {
+        let bound_to_loopback = socket
+            .local_addr()
+            .map(|addr| addr.ip().is_loopback())
+            .unwrap_or(false);
+
         SendTransport {
             socket: Mutex::new(socket),
             transactions,
+            query_limiter,
+            backoff,
+            allow_loopback: AtomicBool::new(bound_to_loopback),
+            read_only: AtomicBool::new(false),
+            metrics,
+            message_observer,
         }
     }
 
+    /// Caps the number of queries sent per second across all destinations,
+    /// replacing any limit set with
+    /// [`KRPCNodeBuilder::max_queries_per_second`](crate::KRPCNodeBuilder::max_queries_per_second).
+    /// A second's worth of queries can go out at once, after which queries
+    /// over the limit wait for their turn instead of failing. Responses to
+    /// other nodes aren't limited.
+    pub fn with_rate_limit(mut self, queries_per_second: u32) -> SendTransport {
+        self.query_limiter = Some(RateLimiter::new(queries_per_second));
+        self
+    }
+
+    /// Allows or blocks sending queries to loopback addresses. Loopback
+    /// destinations are only allowed by default when the socket is bound to a
+    /// loopback address. Responses are sent regardless.
+    pub fn set_allow_loopback(&self, allow: bool) {
+        self.allow_loopback.store(allow, Ordering::Relaxed);
+    }
+
+    /// Marks queries sent from now on as read-only, or stops marking them.
+    /// Nodes don't add read-only queriers to their routing tables, so a
+    /// crawler can switch between participating in the network and passively
+    /// observing it without rebuilding the transport. Off by default.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// Whether queries are currently marked read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
     /// Encodes and sends `message` to `address` without waiting for a response.
+    /// Used for responses, which go back to wherever the query came from.
     pub async fn send(&self, address: SocketAddr, message: Envelope) -> Result<()> {
         let encoded = message
             .encode()
             .map_err(|cause| ErrorKind::SendEncodingError { cause })?;
 
+        self.send_encoded(address, &encoded).await
+    }
+
+    async fn send_encoded(&self, address: SocketAddr, encoded: &[u8]) -> Result<()> {
         let socket = self.socket.lock().await;
 
         socket
-            .send_to(&encoded, &address)
+            .send_to(encoded, &address)
             .await
             .map_err(|cause| ErrorKind::SendError { cause })?;
 
+        if let Some(metrics) = &self.metrics {
+            metrics.sent(encoded.len());
+        }
+
+        if let Some(observe) = &self.message_observer {
+            observe(MessageDirection::Sent, address, encoded);
+        }
+
         Ok(())
     }
 
+    /// Sends `query` to `address` and waits for the response. Waits before
+    /// sending if the node's queries per second limit has been reached.
+    ///
+    /// Fails with [`ErrorKind::NonRoutableDestination`] without sending
+    /// anything when `address` can't be reached over the internet, so other
+    /// nodes can't trick us into querying local services. Fails with
+    /// [`ErrorKind::BackingOff`] without sending anything when backoff is
+    /// enabled and `address` has failed to respond to too many queries in a
+    /// row.
     pub async fn request(&self, address: SocketAddr, query: Query) -> Result<proto::Response> {
-        let transaction_id = Self::random_transaction_id();
+        self.request_recording(address, query, None, None).await
+    }
+
+    /// Like [`SendTransport::request`] but records what is sent and received
+    /// in `trace` as the request progresses.
+    pub async fn request_traced(
+        &self,
+        address: SocketAddr,
+        query: Query,
+        trace: &mut QueryTrace,
+    ) -> Result<proto::Response> {
+        self.request_recording(address, query, None, Some(trace))
+            .await
+    }
+
+    /// Like [`SendTransport::request`] but fails with [`ErrorKind::Timeout`]
+    /// unless a response arrives within `limit` of the query being sent. Time
+    /// spent waiting for the queries per second limit isn't counted.
+    pub(crate) async fn request_within(
+        &self,
+        address: SocketAddr,
+        query: Query,
+        limit: Duration,
+        trace: Option<&mut QueryTrace>,
+    ) -> Result<proto::Response> {
+        self.request_recording(address, query, Some(limit), trace)
+            .await
+    }
+
+    async fn request_recording(
+        &self,
+        address: SocketAddr,
+        query: Query,
+        limit: Option<Duration>,
+        trace: Option<&mut QueryTrace>,
+    ) -> Result<proto::Response> {
+        if !self.is_routable(&address) {
+            return Err(ErrorKind::NonRoutableDestination { address }.into());
+        }
+
+        if let Some(backoff) = &self.backoff {
+            if let Some(retry_in) = backoff.remaining(&address)? {
+                return Err(ErrorKind::BackingOff { address, retry_in }.into());
+            }
+        }
+
+        if let Some(limiter) = &self.query_limiter {
+            limiter.acquire().await;
+        }
 
-        let envelope = Envelope {
+        let exchange = self.exchange(address, query, trace);
+        let result = match limit {
+            None => exchange.await,
+            Some(limit) => match timeout(limit, exchange).await {
+                Ok(result) => result,
+                Err(_) => {
+                    self.record_timeout();
+
+                    Err(ErrorKind::Timeout {
+                        address,
+                        timeout: limit,
+                    }
+                    .into())
+                }
+            },
+        };
+        // The outcome of the request doesn't depend on the bookkeeping, so a
+        // failure to record it isn't returned to the caller.
+        if let Err(err) = self.record_outcome(address, &result) {
+            tracing::warn!(%address, %err, "failed to record request outcome");
+        }
+
+        result
+    }
+
+    /// Records the outcome of a request to `address` with the backoff. Only
+    /// timeouts and failures to send count as failures, requests abandoned by
+    /// the caller aren't recorded at all.
+    fn record_outcome(&self, address: SocketAddr, result: &Result<proto::Response>) -> Result<()> {
+        let backoff = match &self.backoff {
+            Some(backoff) => backoff,
+            None => return Ok(()),
+        };
+
+        match result.as_ref().map_err(|err| err.kind()) {
+            Ok(_) => backoff.record_success(&address),
+            Err(ErrorKind::Timeout { .. }) | Err(ErrorKind::SendError { .. }) => {
+                backoff.record_failure(address)
+            }
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Sends `query` to `address` and waits for the response.
+    async fn exchange(
+        &self,
+        address: SocketAddr,
+        query: Query,
+        mut trace: Option<&mut QueryTrace>,
+    ) -> Result<proto::Response> {
+        // Registered before sending so a fast response can't arrive before
+        // the transaction is known. Dropped if sending fails.
+        let pending = ResponseFuture::allocate(address, self.transactions.clone())?;
+        let transaction_id = self.transactions.width().encode(pending.transaction_id());
+        let encoded = self
+            .build_request(transaction_id, query)
+            .encode()
+            .map_err(|cause| ErrorKind::SendEncodingError { cause })?;
+
+        if let Some(trace) = trace.as_mut() {
+            trace.transaction_id = Some(pending.transaction_id());
+            trace.sent = encoded.clone();
+            trace.sent_at = Some(Instant::now());
+        }
+
+        self.send_encoded(address, &encoded).await?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.request_sent();
+        }
+
+        let response = match trace {
+            None => pending.wait().await,
+            Some(trace) => match pending.await {
+                Ok(envelope) => {
+                    trace.received = Some(envelope.raw.clone());
+                    trace.elapsed = trace.sent_at.map(|sent_at| sent_at.elapsed());
+
+                    envelope.into_response()
+                }
+                Err(err) => Err(err),
+            },
+        };
+
+        Ok(response?)
+    }
+
+    /// Number of responses received for a request which already had a
+    /// response, including ones arriving shortly after the first was read.
+    /// Duplicates are dropped.
+    pub fn duplicate_responses(&self) -> u64 {
+        self.transactions.duplicate_responses()
+    }
+
+    /// Counts of messages and bytes sent and received so far. All zero unless
+    /// enabled with
+    /// [`KRPCNodeBuilder::collect_metrics`](crate::KRPCNodeBuilder::collect_metrics).
+    pub fn metrics(&self) -> TransportMetrics {
+        self.metrics
+            .as_ref()
+            .map(|metrics| metrics.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Records a request which gave up waiting for a response.
+    fn record_timeout(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.timed_out();
+        }
+    }
+
+    /// Number of requests sent which are still waiting for a response.
+    pub fn pending_requests(&self) -> Result<usize> {
+        self.transactions.len()
+    }
+
+    fn build_request(&self, transaction_id: Vec<u8>, query: Query) -> Envelope {
+        Envelope {
             ip: None,
-            transaction_id: transaction_id.to_be_bytes().to_vec(),
+            transaction_id,
             version: None,
             message_type: Message::Query { query },
-            read_only: false,
+            read_only: self.is_read_only(),
+        }
+    }
+
+    fn is_routable(&self, address: &SocketAddr) -> bool {
+        // IPv4-mapped addresses reach IPv4 hosts, so they get the IPv4 rules.
+        let ip = match address.ip() {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4),
+            ip => ip,
         };
 
-        self.send(address, envelope).await?;
+        if address.port() == 0 || ip.is_unspecified() || ip.is_multicast() {
+            return false;
+        }
 
-        Ok(ResponseFuture::wait_for_tx(transaction_id, self.transactions.clone()).await?)
-    }
+        if let IpAddr::V4(v4) = ip {
+            if v4.is_broadcast() {
+                return false;
+            }
+        }
 
-    fn random_transaction_id() -> TransactionId {
-        rand::random::<TransactionId>()
+        !ip.is_loopback() || self.allow_loopback.load(Ordering::Relaxed)
     }
 }
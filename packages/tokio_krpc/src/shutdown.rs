@@ -0,0 +1,37 @@
+use crate::{
+    active_transactions::ActiveTransactions,
+    send_errors::Result,
+};
+use futures::future::AbortHandle;
+
+/// Stops a node started with [`KRPCNode::serve`](crate::KRPCNode::serve).
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    inbound: AbortHandle,
+    transactions: ActiveTransactions,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(inbound: AbortHandle, transactions: ActiveTransactions) -> ShutdownHandle {
+        ShutdownHandle {
+            inbound,
+            transactions,
+        }
+    }
+
+    /// Ends the stream of inbound queries and fails every request waiting for
+    /// a response, and any sent afterwards, with
+    /// [`ErrorKind::Shutdown`](crate::send_errors::ErrorKind::Shutdown).
+    ///
+    /// The inbound stream is ended even when waiting requests can't be failed
+    /// because a panic on another task poisoned the transactions lock.
+    pub fn shutdown(&self) -> Result<()> {
+        self.inbound.abort();
+        self.transactions.shutdown()
+    }
+
+    /// Whether [`ShutdownHandle::shutdown`] was called.
+    pub fn is_shutdown(&self) -> bool {
+        self.inbound.is_aborted()
+    }
+}
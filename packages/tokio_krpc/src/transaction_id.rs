@@ -12,14 +12,84 @@ use byteorder::{
 /// transaction id.
 pub type TransactionId = u32;
 
+/// Number of bytes in the transaction ids of requests originating from this
+/// client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionIdWidth {
+    Two,
+    Four,
+}
+
+impl TransactionIdWidth {
+    pub fn num_bytes(self) -> usize {
+        match self {
+            TransactionIdWidth::Two => 2,
+            TransactionIdWidth::Four => 4,
+        }
+    }
+
+    /// Number of distinct transaction ids which fit in this width.
+    pub(crate) fn capacity(self) -> u64 {
+        1 << (8 * self.num_bytes())
+    }
+
+    /// Drops the bits of `transaction_id` which don't fit in this width.
+    pub(crate) fn truncate(self, transaction_id: TransactionId) -> TransactionId {
+        (u64::from(transaction_id) % self.capacity()) as TransactionId
+    }
+
+    /// Encodes `transaction_id` using this width. Bits which don't fit are
+    /// dropped.
+    pub(crate) fn encode(self, transaction_id: TransactionId) -> Vec<u8> {
+        let bytes = transaction_id.to_be_bytes();
+
+        bytes[bytes.len() - self.num_bytes()..].to_vec()
+    }
+}
+
+impl Default for TransactionIdWidth {
+    fn default() -> TransactionIdWidth {
+        TransactionIdWidth::Four
+    }
+}
+
+/// Picks transaction ids for requests originating from this client. Ids can
+/// encode hints such as the type of query for demultiplexing responses.
+///
+/// Only the low bytes of each id which fit in the configured
+/// [`TransactionIdWidth`] are used. Ids still waiting for a response are
+/// skipped, so generators don't need to track which ids are in use. After a
+/// few ids in a row are in use, the next free id after the last one is used
+/// instead.
+pub trait TransactionIdGenerator: Send + Sync {
+    fn next(&self) -> TransactionId;
+}
+
+/// Picks uniformly random transaction ids. Used unless another generator is
+/// configured.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomTransactionIds;
+
+impl TransactionIdGenerator for RandomTransactionIds {
+    fn next(&self) -> TransactionId {
+        rand::random::<TransactionId>()
+    }
+}
+
 /// Extracts a [TransactionId] from a response to a request originating from
 /// this client. If the transaction id is malformed, returns an error.
-pub fn parse_originating_transaction_id(mut bytes: &[u8]) -> Result<TransactionId> {
-    if bytes.len() != 4 {
+pub fn parse_originating_transaction_id(
+    mut bytes: &[u8],
+    width: TransactionIdWidth,
+) -> Result<TransactionId> {
+    if bytes.len() != width.num_bytes() {
         Err(ErrorKind::InvalidResponseTransactionId)?;
     }
 
-    Ok(bytes
-        .read_u32::<NetworkEndian>()
-        .map_err(|_cause| ErrorKind::InvalidResponseTransactionId)?)
+    let transaction_id = match width {
+        TransactionIdWidth::Two => bytes.read_u16::<NetworkEndian>().map(TransactionId::from),
+        TransactionIdWidth::Four => bytes.read_u32::<NetworkEndian>(),
+    };
+
+    Ok(transaction_id.map_err(|_cause| ErrorKind::InvalidResponseTransactionId)?)
 }
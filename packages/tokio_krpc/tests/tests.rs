@@ -1,23 +1,62 @@
 use futures::{
-    future,
+    channel::mpsc,
+    future::{
+        self,
+        Either,
+    },
     StreamExt,
     TryStreamExt,
 };
-use krpc_encoding::NodeID;
+use krpc_encoding::{
+    Envelope,
+    Message,
+    NodeID,
+    NodeInfo,
+    Query,
+    Response,
+};
 use std::{
     net::{
         SocketAddr,
+        SocketAddrV4,
         ToSocketAddrs,
     },
     str::FromStr,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 use tokio::{
     net::UdpSocket,
     spawn,
+    time::{
+        pause,
+        timeout,
+    },
 };
 use tokio_krpc::{
+    recv_errors::ErrorKind as RecvErrorKind,
+    responses::{
+        FindNodeResponse,
+        GetPeersResponse,
+        GetPeersResponseType,
+    },
+    send_errors::ErrorKind,
+    BackoffConfig,
     KRPCNode,
+    PortType,
+    QueryTimeouts,
     RequestTransport,
+    SendTransport,
+    TransactionId,
+    TransactionIdGenerator,
+    TransactionIdWidth,
+    TransportMetrics,
 };
 
 type Error = Box<dyn std::error::Error>;
@@ -39,7 +78,7 @@ async fn ping() -> Result<(), Error> {
     let id = NodeID::random();
     let socket = UdpSocket::bind(&bind).await?;
     let recv_transport = KRPCNode::new(socket);
-    let (send_transport, request_stream) = recv_transport.serve();
+    let (send_transport, request_stream, _) = recv_transport.serve();
     let request_transport = RequestTransport::new(id, send_transport);
 
     spawn(
@@ -50,7 +89,1469 @@ async fn ping() -> Result<(), Error> {
 
     let response = request_transport.ping(remote_v4).await?;
 
-    assert_ne!(response, b"0000000000000000000000000000000000000000".into());
+    assert_ne!(
+        response,
+        NodeID::from_hex(b"0000000000000000000000000000000000000000")?
+    );
+
+    Ok(())
+}
+
+/// A node on localhost responding to every query with a fixed response.
+struct FakeNode {
+    id: NodeID,
+    address: SocketAddrV4,
+    socket: UdpSocket,
+}
+
+impl FakeNode {
+    async fn bind(id: NodeID) -> Result<FakeNode, Error> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let address = match socket.local_addr()? {
+            SocketAddr::V4(v4) => v4,
+            SocketAddr::V6(_) => panic!("not v4"),
+        };
+
+        Ok(FakeNode {
+            id,
+            address,
+            socket,
+        })
+    }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new(self.id.clone(), self.address.into())
+    }
+
+    /// Responds to every query with `nodes`.
+    fn serve_nodes(self, nodes: Vec<NodeInfo>) {
+        let id = self.id.clone();
+
+        self.serve(move || Response::NextHop {
+            id: id.clone(),
+            token: None,
+            nodes: nodes.clone(),
+            nodes6: Vec::new(),
+        })
+    }
+
+    fn serve<F: Fn() -> Response + Send + 'static>(self, make_response: F) {
+        let (send_transport, queries, _) = KRPCNode::new(self.socket).serve();
+
+        spawn(async move {
+            let mut queries = Box::pin(queries);
+
+            while let Some(Ok((query, from))) = queries.next().await {
+                let envelope = Envelope {
+                    ip: None,
+                    transaction_id: query.transaction_id,
+                    version: None,
+                    message_type: Message::Response {
+                        response: make_response(),
+                    },
+                    read_only: false,
+                };
+
+                let _ = send_transport.send(from, envelope).await;
+            }
+        });
+    }
+}
+
+fn serve_client(socket: UdpSocket) -> SendTransport {
+    let (send_transport, request_stream, _) = KRPCNode::new(socket).serve();
+
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+
+    send_transport
+}
+
+fn make_client(socket: UdpSocket) -> RequestTransport {
+    RequestTransport::new(NodeID::random(), serve_client(socket))
+}
+
+#[tokio::test]
+async fn locate_greedy() -> Result<(), Error> {
+    let target = NodeID::from_hex(b"0000000000000000000000000000000000000000")?;
+
+    let a = FakeNode::bind(NodeID::from_hex(
+        b"ffffffffffffffffffffffffffffffffffffffff",
+    )?)
+    .await?;
+    let b = FakeNode::bind(NodeID::from_hex(
+        b"0fffffffffffffffffffffffffffffffffffffff",
+    )?)
+    .await?;
+    let c = FakeNode::bind(NodeID::from_hex(
+        b"f0ffffffffffffffffffffffffffffffffffffff",
+    )?)
+    .await?;
+    let d = FakeNode::bind(NodeID::from_hex(
+        b"00ffffffffffffffffffffffffffffffffffffff",
+    )?)
+    .await?;
+
+    let seed = a.address;
+    let (a_info, b_info, c_info, d_info) = (a.info(), b.info(), c.info(), d.info());
+
+    a.serve_nodes(vec![c_info.clone(), b_info.clone()]);
+    b.serve_nodes(vec![d_info.clone(), a_info]);
+    c.serve_nodes(vec![]);
+    d.serve_nodes(vec![b_info, c_info]);
+
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+    let closest = client.locate_greedy(target, seed).await?;
+
+    assert_eq!(closest, d_info);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn non_routable_destinations_blocked() -> Result<(), Error> {
+    let send_transport = serve_client(UdpSocket::bind("0.0.0.0:0").await?);
+
+    for address in &[
+        "127.0.0.1:6881",
+        "0.0.0.0:6881",
+        "224.0.0.1:6881",
+        "255.255.255.255:6881",
+        "1.2.3.4:0",
+        "[::ffff:127.0.0.1]:6881",
+        "[::ffff:0.0.0.0]:6881",
+        "[::ffff:224.0.0.1]:6881",
+        "[::ffff:255.255.255.255]:6881",
+    ] {
+        let address: SocketAddr = address.parse()?;
+        let err = send_transport
+            .request(
+                address,
+                Query::Ping {
+                    id: NodeID::random(),
+                },
+            )
+            .await
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::NonRoutableDestination { address: blocked } => {
+                assert_eq!(*blocked, address)
+            }
+            kind => panic!("unexpected error {}", kind),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn responses_to_loopback_sent() -> Result<(), Error> {
+    let send_transport = serve_client(UdpSocket::bind("0.0.0.0:0").await?);
+    let querier = UdpSocket::bind("127.0.0.1:0").await?;
+    let id = NodeID::random();
+
+    let envelope = Envelope {
+        ip: None,
+        transaction_id: vec![0, 0],
+        version: None,
+        message_type: Message::Response {
+            response: Response::OnlyID { id: id.clone() },
+        },
+        read_only: false,
+    };
+    send_transport.send(querier.local_addr()?, envelope).await?;
+
+    let mut buffer = [0u8; 1024];
+    let (size, _) = querier.recv_from(&mut buffer).await?;
+    assert_eq!(
+        Envelope::decode(&buffer[..size])?.message_type,
+        Message::Response {
+            response: Response::OnlyID { id }
+        }
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn loopback_allowed_when_configured() -> Result<(), Error> {
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let (id, address) = (node.id.clone(), node.address);
+    let response_id = id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let send_transport = serve_client(UdpSocket::bind("0.0.0.0:0").await?);
+    send_transport.set_allow_loopback(true);
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    assert_eq!(client.ping(address).await?, id);
+
+    Ok(())
+}
+
+/// Pings a node using transaction ids of `width` and returns the length of the
+/// transaction id seen by the node.
+async fn ping_with_transaction_id_width(width: TransactionIdWidth) -> Result<usize, Error> {
+    let server_id = NodeID::random();
+    let server_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let server_address = match server_socket.local_addr()? {
+        SocketAddr::V4(v4) => v4,
+        SocketAddr::V6(_) => panic!("not v4"),
+    };
+
+    let (lengths_tx, mut lengths_rx) = mpsc::unbounded();
+    let (server_transport, queries, _) = KRPCNode::new(server_socket).serve();
+    let response_id = server_id.clone();
+    spawn(async move {
+        let mut queries = Box::pin(queries);
+
+        while let Some(Ok((query, from))) = queries.next().await {
+            let _ = lengths_tx.unbounded_send(query.transaction_id.len());
+
+            let envelope = Envelope {
+                ip: None,
+                transaction_id: query.transaction_id,
+                version: None,
+                message_type: Message::Response {
+                    response: Response::OnlyID {
+                        id: response_id.clone(),
+                    },
+                },
+                read_only: false,
+            };
+
+            let _ = server_transport.send(from, envelope).await;
+        }
+    });
+
+    let client_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let node = KRPCNode::builder()
+        .transaction_id_width(width)
+        .build(client_socket);
+    let (send_transport, request_stream, _) = node.serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    assert_eq!(client.ping(server_address).await?, server_id);
+
+    Ok(lengths_rx.next().await.unwrap())
+}
+
+#[tokio::test]
+async fn two_byte_transaction_ids() -> Result<(), Error> {
+    assert_eq!(
+        ping_with_transaction_id_width(TransactionIdWidth::Two).await?,
+        2
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn four_byte_transaction_ids() -> Result<(), Error> {
+    assert_eq!(
+        ping_with_transaction_id_width(TransactionIdWidth::Four).await?,
+        4
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn response_from_wrong_address_rejected() -> Result<(), Error> {
+    let server_id = NodeID::random();
+    let server_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let server_address = match server_socket.local_addr()? {
+        SocketAddr::V4(v4) => v4,
+        SocketAddr::V6(_) => panic!("not v4"),
+    };
+    let forger_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let forger_address = forger_socket.local_addr()?;
+
+    let (errors_tx, mut errors_rx) = mpsc::unbounded();
+    let (send_transport, request_stream, _) =
+        KRPCNode::new(UdpSocket::bind("127.0.0.1:0").await?).serve();
+    spawn(
+        request_stream
+            .map_err(move |err| {
+                let _ = errors_tx.unbounded_send(err);
+            })
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    let respond = async {
+        let mut buffer = [0u8; 1024];
+        let (size, from) = server_socket.recv_from(&mut buffer).await?;
+        let query = Envelope::decode(&buffer[..size])?;
+
+        let response = |id: NodeID| Envelope {
+            ip: None,
+            transaction_id: query.transaction_id.clone(),
+            version: None,
+            message_type: Message::Response {
+                response: Response::OnlyID { id },
+            },
+            read_only: false,
+        };
+
+        forger_socket
+            .send_to(&response(NodeID::random()).encode()?, from)
+            .await?;
+
+        let err = errors_rx.next().await.unwrap();
+        match err.kind() {
+            RecvErrorKind::ResponseAddressMismatch { expected, got, .. } => {
+                assert_eq!(*expected, SocketAddr::V4(server_address));
+                assert_eq!(*got, forger_address);
+            }
+            kind => panic!("unexpected error {}", kind),
+        };
+
+        server_socket
+            .send_to(&response(server_id.clone()).encode()?, from)
+            .await?;
+
+        Ok::<(), Error>(())
+    };
+
+    let (response, responded) = future::join(client.ping(server_address), respond).await;
+    responded?;
+
+    assert_eq!(response?, server_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn oversized_info_hash_not_sent() -> Result<(), Error> {
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+
+    let err = client
+        .get_peers("127.0.0.1:6881".parse::<SocketAddr>()?, [1u8; 21])
+        .await
+        .unwrap_err();
+
+    match err.kind() {
+        ErrorKind::InvalidInfoHash { len } => assert_eq!(*len, 21),
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn short_info_hash_not_sent() -> Result<(), Error> {
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+    let address = "127.0.0.1:6881".parse::<SocketAddr>()?;
+
+    let err = client.get_peers(address, [1u8; 19]).await.unwrap_err();
+    match err.kind() {
+        ErrorKind::InvalidInfoHash { len } => assert_eq!(*len, 19),
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    let err = client
+        .announce_peer(
+            b"token".to_vec(),
+            address,
+            [1u8; 19],
+            PortType::Implied(6881),
+        )
+        .await
+        .unwrap_err();
+    match err.kind() {
+        ErrorKind::InvalidInfoHash { len } => assert_eq!(*len, 19),
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn duplicate_peers_removed() -> Result<(), Error> {
+    let first: SocketAddrV4 = "10.0.0.1:6881".parse()?;
+    let second: SocketAddrV4 = "10.0.0.2:6881".parse()?;
+
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let (node_id, node_address) = (node.id.clone(), node.address);
+    node.serve(move || Response::GetPeers {
+        id: node_id.clone(),
+        token: Some(b"token".to_vec()),
+        peers: vec![first.into(), second.into(), first.into()],
+    });
+
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+    let response = client
+        .get_peers(node_address, NodeID::random().as_bytes())
+        .await?;
+
+    match response.message_type {
+        GetPeersResponseType::Peers(peers) => assert_eq!(peers, vec![first, second]),
+        GetPeersResponseType::NextHop(_) => panic!("expected peers"),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sample_infohashes() -> Result<(), Error> {
+    let sample = NodeID::random();
+
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let (node_id, node_address) = (node.id.clone(), node.address);
+    let response_id = node_id.clone();
+    let response_sample = sample.clone();
+    node.serve(move || Response::Samples {
+        id: response_id.clone(),
+        interval: None,
+        nodes: Vec::new(),
+        num: None,
+        samples: vec![response_sample.clone()],
+    });
+
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+    let response = client
+        .sample_infohashes(node_address, NodeID::random())
+        .await?;
+
+    assert_eq!(response.id, node_id);
+    assert_eq!(response.interval, None);
+    assert_eq!(response.num, None);
+    assert_eq!(response.samples, vec![sample]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn raw_query() -> Result<(), Error> {
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let (node_id, node_address) = (node.id.clone(), node.address);
+    let response_id = node_id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+    let response = client
+        .query(
+            node_address,
+            Query::Ping {
+                id: NodeID::random(),
+            },
+        )
+        .await?;
+
+    match response {
+        Response::OnlyID { id } => assert_eq!(id, node_id),
+        other => panic!("unexpected response {:?}", other),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn traced_query_captures_bytes() -> Result<(), Error> {
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let (node_id, node_address) = (node.id.clone(), node.address);
+    let response_id = node_id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+    let id = NodeID::random();
+    let (response, trace) = client
+        .query_traced(node_address, Query::Ping { id: id.clone() })
+        .await;
+
+    assert_eq!(
+        response?,
+        Response::OnlyID {
+            id: node_id.clone()
+        }
+    );
+    assert_eq!(trace.address, SocketAddr::from(node_address));
+    assert!(trace.elapsed.is_some());
+
+    let sent = Envelope::decode(&trace.sent)?;
+    assert_eq!(
+        sent.message_type,
+        Message::Query {
+            query: Query::Ping { id }
+        }
+    );
+    assert_eq!(
+        sent.transaction_id,
+        trace.transaction_id.unwrap().to_be_bytes().to_vec()
+    );
+
+    let received = Envelope::decode(&trace.received.unwrap())?;
+    assert_eq!(received.transaction_id, sent.transaction_id);
+    assert_eq!(
+        received.message_type,
+        Message::Response {
+            response: Response::OnlyID { id: node_id }
+        }
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn traced_query_keeps_raw_response() -> Result<(), Error> {
+    let responder = UdpSocket::bind("127.0.0.1:0").await?;
+    let responder_address = responder.local_addr()?;
+    let responder_id = NodeID::random();
+
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?);
+    let query = client.query_traced(
+        responder_address,
+        Query::Ping {
+            id: NodeID::random(),
+        },
+    );
+
+    // Includes the ip and v keys, which aren't kept once decoded.
+    let respond = async {
+        let mut buffer = [0u8; 1024];
+        let (size, from) = responder.recv_from(&mut buffer).await?;
+        let from = match from {
+            SocketAddr::V4(from) => from,
+            SocketAddr::V6(_) => panic!("not v4"),
+        };
+        let response = Envelope {
+            ip: Some(from.into()),
+            transaction_id: Envelope::decode(&buffer[..size])?.transaction_id,
+            version: Some(b"UT01".to_vec().into()),
+            message_type: Message::Response {
+                response: Response::OnlyID {
+                    id: responder_id.clone(),
+                },
+            },
+            read_only: false,
+        }
+        .encode()?;
+        responder.send_to(&response, from).await?;
+
+        Ok::<_, Error>(response)
+    };
+
+    let ((response, trace), sent_response) = future::join(query, respond).await;
+    response?;
+
+    assert_eq!(trace.received, Some(sent_response?));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn global_query_rate_respected() -> Result<(), Error> {
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let (node_id, node_address) = (node.id.clone(), node.address);
+    let response_id = node_id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let client_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .max_queries_per_second(10)
+        .build(client_socket)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    let started = Instant::now();
+    let responses = future::join_all((0..15).map(|_| client.ping(node_address))).await;
+
+    // The first 10 queries go out right away and each of the rest waits 100ms
+    // for its turn.
+    assert!(started.elapsed() >= Duration::from_millis(400));
+    for response in responses {
+        assert_eq!(response?, node_id);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limit_wait_not_counted_in_timeout() -> Result<(), Error> {
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let node_address = node.address;
+    let response_id = node.id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let client_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .max_queries_per_second(2)
+        .build(client_socket)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport)
+        .with_timeout(Duration::from_millis(300));
+
+    // The last query waits longer than the timeout for its turn.
+    let responses = future::join_all((0..3).map(|_| client.ping(node_address))).await;
+    for response in responses {
+        response?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn abandoned_queries_dont_use_rate_limit() -> Result<(), Error> {
+    pause();
+
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let node_address = node.address;
+    let response_id = node.id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let client_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .max_queries_per_second(1)
+        .build(client_socket)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let ping = || {
+        send_transport.request(
+            node_address.into(),
+            Query::Ping {
+                id: NodeID::random(),
+            },
+        )
+    };
+
+    let started = tokio::time::Instant::now();
+    ping().await?;
+
+    // Each gives up before its turn comes.
+    for _ in 0..5 {
+        assert!(timeout(Duration::from_millis(100), ping()).await.is_err());
+    }
+
+    ping().await?;
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn send_transport_rate_limited() -> Result<(), Error> {
+    pause();
+
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let node_address = node.address;
+    let response_id = node.id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let send_transport = serve_client(UdpSocket::bind("127.0.0.1:0").await?).with_rate_limit(10);
+
+    let started = tokio::time::Instant::now();
+    let responses = future::join_all((0..100).map(|_| {
+        send_transport.request(
+            node_address.into(),
+            Query::Ping {
+                id: NodeID::random(),
+            },
+        )
+    }))
+    .await;
+
+    // The first 10 queries go out right away and the other 90 at 10 a second.
+    assert!(started.elapsed() >= Duration::from_secs(9));
+    for response in responses {
+        response?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn duplicate_responses_counted() -> Result<(), Error> {
+    let server_id = NodeID::random();
+    let server_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let server_address = match server_socket.local_addr()? {
+        SocketAddr::V4(v4) => v4,
+        SocketAddr::V6(_) => panic!("not v4"),
+    };
+
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .log_duplicate_responses(true)
+        .build(UdpSocket::bind("127.0.0.1:0").await?)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let send_transport = Arc::new(send_transport);
+    let client = RequestTransport::new(NodeID::random(), send_transport.clone());
+
+    let respond = async {
+        let mut buffer = [0u8; 1024];
+        let (size, from) = server_socket.recv_from(&mut buffer).await?;
+        let query = Envelope::decode(&buffer[..size])?;
+
+        let response = Envelope {
+            ip: None,
+            transaction_id: query.transaction_id,
+            version: None,
+            message_type: Message::Response {
+                response: Response::OnlyID {
+                    id: server_id.clone(),
+                },
+            },
+            read_only: false,
+        }
+        .encode()?;
+
+        // Both arrive before the client reads the first.
+        server_socket.send_to(&response, from).await?;
+        server_socket.send_to(&response, from).await?;
+
+        Ok::<(), Error>(())
+    };
+
+    let (response, responded) = future::join(client.ping(server_address), respond).await;
+    responded?;
+
+    assert_eq!(response?, server_id);
+    assert_eq!(send_transport.duplicate_responses(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn duplicate_response_after_read_counted() -> Result<(), Error> {
+    let server_id = NodeID::random();
+    let server_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let server_address = match server_socket.local_addr()? {
+        SocketAddr::V4(v4) => v4,
+        SocketAddr::V6(_) => panic!("not v4"),
+    };
+
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .build(UdpSocket::bind("127.0.0.1:0").await?)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let send_transport = Arc::new(send_transport);
+    let client = RequestTransport::new(NodeID::random(), send_transport.clone());
+
+    let respond = async {
+        let mut buffer = [0u8; 1024];
+        let (size, from) = server_socket.recv_from(&mut buffer).await?;
+        let query = Envelope::decode(&buffer[..size])?;
+
+        let response = Envelope {
+            ip: None,
+            transaction_id: query.transaction_id,
+            version: None,
+            message_type: Message::Response {
+                response: Response::OnlyID {
+                    id: server_id.clone(),
+                },
+            },
+            read_only: false,
+        }
+        .encode()?;
+
+        server_socket.send_to(&response, from).await?;
+
+        Ok::<_, Error>((response, from))
+    };
+
+    let (response, responded) = future::join(client.ping(server_address), respond).await;
+    let (duplicate, client_address) = responded?;
+    assert_eq!(response?, server_id);
+
+    // The first response was read and its transaction removed.
+    server_socket.send_to(&duplicate, client_address).await?;
+
+    timeout(Duration::from_secs(1), async {
+        while send_transport.duplicate_responses() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    assert_eq!(send_transport.duplicate_responses(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failing_destination_backed_off() -> Result<(), Error> {
+    // Never reads, so queries to it always time out.
+    let silent_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let silent_address = match silent_socket.local_addr()? {
+        SocketAddr::V4(v4) => v4,
+        SocketAddr::V6(_) => panic!("not v4"),
+    };
+
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let (node_id, node_address) = (node.id.clone(), node.address);
+    let response_id = node_id.clone();
+    node.serve(move || Response::OnlyID {
+        id: response_id.clone(),
+    });
+
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .backoff(BackoffConfig {
+            failure_threshold: 2,
+            initial: Duration::from_secs(60),
+            max: Duration::from_secs(60),
+        })
+        .build(UdpSocket::bind("127.0.0.1:0").await?)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport)
+        .with_timeout(Duration::from_millis(100));
+
+    for _ in 0..2 {
+        let err = client.ping(silent_address).await.unwrap_err();
+        match err.kind() {
+            ErrorKind::Timeout { .. } => (),
+            kind => panic!("unexpected error {}", kind),
+        };
+    }
+
+    let err = client.ping(silent_address).await.unwrap_err();
+    match err.kind() {
+        ErrorKind::BackingOff { address, .. } => {
+            assert_eq!(*address, SocketAddr::V4(silent_address))
+        }
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    // Other destinations are unaffected.
+    assert_eq!(client.ping(node_address).await?, node_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn abandoned_requests_not_backed_off() -> Result<(), Error> {
+    // Never reads, so queries to it always time out.
+    let silent_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let silent_address = match silent_socket.local_addr()? {
+        SocketAddr::V4(v4) => v4,
+        SocketAddr::V6(_) => panic!("not v4"),
+    };
+
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .backoff(BackoffConfig {
+            failure_threshold: 2,
+            initial: Duration::from_secs(60),
+            max: Duration::from_secs(60),
+        })
+        .build(UdpSocket::bind("127.0.0.1:0").await?)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport)
+        .with_timeout(Duration::from_millis(200));
+
+    // Dropped before their own timeout.
+    for _ in 0..2 {
+        let result = timeout(Duration::from_millis(50), client.ping(silent_address)).await;
+        assert!(result.is_err());
+    }
+
+    let err = client.ping(silent_address).await.unwrap_err();
+    match err.kind() {
+        ErrorKind::Timeout { .. } => (),
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn oversized_message_truncated() -> Result<(), Error> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let address = socket.local_addr()?;
+    let (_send_transport, queries, _) = KRPCNode::builder()
+        .recv_buffer_size(64)
+        .build(socket)
+        .serve();
+    let mut queries = Box::pin(queries);
+
+    let sender = UdpSocket::bind("127.0.0.1:0").await?;
+    sender.send_to(&[b'x'; 100], address).await?;
+
+    let err = queries.next().await.unwrap().unwrap_err();
+    match err.kind() {
+        RecvErrorKind::MessageTruncated { from, max_size } => {
+            assert_eq!(*from, sender.local_addr()?);
+            assert_eq!(*max_size, 64);
+        }
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    // messages which fit are still received
+    let ping = Envelope {
+        ip: None,
+        transaction_id: b"aa".to_vec(),
+        version: None,
+        message_type: Message::Query {
+            query: Query::Ping {
+                id: NodeID::random(),
+            },
+        },
+        read_only: false,
+    };
+    sender.send_to(&ping.encode()?, address).await?;
+
+    let (query, _) = queries.next().await.unwrap()?;
+    assert_eq!(query.transaction_id, b"aa".to_vec());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn query_types_use_their_timeouts() -> Result<(), Error> {
+    // Never reads, so queries to it always time out.
+    let silent_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let silent_address = silent_socket.local_addr()?;
+
+    let timeouts = QueryTimeouts {
+        ping: Duration::from_millis(50),
+        find_node: Duration::from_millis(100),
+        get_peers: Duration::from_millis(150),
+        announce_peer: Duration::from_millis(200),
+        sample_infohashes: Duration::from_millis(250),
+    };
+    let client = make_client(UdpSocket::bind("127.0.0.1:0").await?).with_timeouts(timeouts);
+
+    let assert_timed_out =
+        |err: tokio_krpc::send_errors::Error, expected: Duration| match err.kind() {
+            ErrorKind::Timeout { address, timeout } => {
+                assert_eq!(*address, silent_address);
+                assert_eq!(*timeout, expected);
+            }
+            kind => panic!("unexpected error {}", kind),
+        };
+
+    let started = Instant::now();
+    assert_timed_out(
+        client.ping(silent_address).await.unwrap_err(),
+        timeouts.ping,
+    );
+    assert!(started.elapsed() >= timeouts.ping);
+
+    assert_timed_out(
+        client
+            .find_node(silent_address, NodeID::random())
+            .await
+            .unwrap_err(),
+        timeouts.find_node,
+    );
+
+    assert_timed_out(
+        client
+            .get_peers(silent_address, NodeID::random().as_bytes())
+            .await
+            .unwrap_err(),
+        timeouts.get_peers,
+    );
+
+    assert_timed_out(
+        client
+            .announce_peer(
+                b"token".to_vec(),
+                silent_address,
+                NodeID::random().as_bytes(),
+                PortType::Implied(6881),
+            )
+            .await
+            .unwrap_err(),
+        timeouts.announce_peer,
+    );
+
+    assert_timed_out(
+        client
+            .sample_infohashes(silent_address, NodeID::random())
+            .await
+            .unwrap_err(),
+        timeouts.sample_infohashes,
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn external_addr_reported() -> Result<(), Error> {
+    let server_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let server_address = server_socket.local_addr()?;
+    let observed: SocketAddrV4 = "203.0.113.7:6881".parse()?;
+
+    let (reports_tx, mut reports_rx) = mpsc::unbounded();
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .on_external_addr_report(move |from, addr| {
+            let _ = reports_tx.unbounded_send((from, addr));
+        })
+        .build(UdpSocket::bind("127.0.0.1:0").await?)
+        .serve();
+    spawn(request_stream.for_each(|_| future::ready(())));
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    let server_id = NodeID::random();
+    let respond = async {
+        let mut buffer = [0u8; 1024];
+        let (size, from) = server_socket.recv_from(&mut buffer).await?;
+        let query = Envelope::decode(&buffer[..size])?;
+
+        let response = Envelope {
+            ip: Some(observed.into()),
+            transaction_id: query.transaction_id,
+            version: None,
+            message_type: Message::Response {
+                response: Response::OnlyID {
+                    id: server_id.clone(),
+                },
+            },
+            read_only: false,
+        };
+        server_socket.send_to(&response.encode()?, from).await?;
+
+        Ok::<(), Error>(())
+    };
+
+    let (response, responded) = future::join(client.ping(server_address), respond).await;
+    responded?;
+    assert_eq!(response?, server_id);
+
+    assert_eq!(reports_rx.next().await, Some((server_address, observed)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn timed_out_request_dropped_from_transactions() -> Result<(), Error> {
+    // Never reads, so queries to it always time out.
+    let silent_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let silent_address = silent_socket.local_addr()?;
+
+    let send_transport = Arc::new(serve_client(UdpSocket::bind("127.0.0.1:0").await?));
+    let client = RequestTransport::new(NodeID::random(), send_transport.clone())
+        .with_timeout(Duration::from_millis(100));
+
+    let err = client.ping(silent_address).await.unwrap_err();
+    match err.kind() {
+        ErrorKind::Timeout { address, timeout } => {
+            assert_eq!(*address, silent_address);
+            assert_eq!(*timeout, Duration::from_millis(100));
+        }
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    assert_eq!(send_transport.pending_requests()?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn untyped_query_to_silent_node_times_out() -> Result<(), Error> {
+    // Never reads, so queries to it always time out.
+    let silent_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let silent_address = silent_socket.local_addr()?;
+
+    let send_transport = Arc::new(serve_client(UdpSocket::bind("127.0.0.1:0").await?));
+    let client = RequestTransport::new(NodeID::random(), send_transport.clone())
+        .with_timeout(Duration::from_millis(100));
+
+    let err = client
+        .query(
+            silent_address,
+            Query::Ping {
+                id: NodeID::random(),
+            },
+        )
+        .await
+        .unwrap_err();
+    match err.kind() {
+        ErrorKind::Timeout { address, .. } => assert_eq!(*address, silent_address),
+        kind => panic!("unexpected error {}", kind),
+    };
+
+    assert_eq!(send_transport.pending_requests()?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn concurrent_requests_get_their_own_responses() -> Result<(), Error> {
+    // Echoes the id in each ping so responses can be matched to requests.
+    let server_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let server_address = server_socket.local_addr()?;
+    let (server_transport, queries, _) = KRPCNode::new(server_socket).serve();
+    spawn(async move {
+        let mut queries = Box::pin(queries);
+
+        while let Some(Ok((query, from))) = queries.next().await {
+            let id = match query.query {
+                Query::Ping { id } => id,
+                _ => continue,
+            };
+
+            let envelope = Envelope {
+                ip: None,
+                transaction_id: query.transaction_id,
+                version: None,
+                message_type: Message::Response {
+                    response: Response::OnlyID { id },
+                },
+                read_only: false,
+            };
+
+            let _ = server_transport.send(from, envelope).await;
+        }
+    });
+
+    // Two byte ids make collisions between random ids likely.
+    let client_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .transaction_id_width(TransactionIdWidth::Two)
+        .build(client_socket)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+
+    let requests = (0..2000).map(|_| {
+        let send_transport = &send_transport;
+
+        async move {
+            let id = NodeID::random();
+            let response = timeout(
+                Duration::from_secs(5),
+                send_transport.request(server_address, Query::Ping { id: id.clone() }),
+            )
+            .await;
+
+            (id, response)
+        }
+    });
+
+    let mut answered = 0;
+    for (id, response) in future::join_all(requests).await {
+        // Packets dropped by the socket buffers time out rather than fail.
+        if let Ok(response) = response {
+            assert_eq!(response?, Response::OnlyID { id });
+            answered += 1;
+        }
+    }
+
+    assert!(answered > 0);
+    assert_eq!(send_transport.pending_requests()?, 0);
+
+    Ok(())
+}
+
+/// Sends a ping to `socket` and returns whether it arrived marked read-only.
+async fn sent_read_only(transport: &SendTransport, socket: &UdpSocket) -> Result<bool, Error> {
+    let request = transport.request(
+        socket.local_addr()?,
+        Query::Ping {
+            id: NodeID::random(),
+        },
+    );
+    let receive = async {
+        let mut buffer = [0u8; 1024];
+        let (size, _) = socket.recv_from(&mut buffer).await?;
+
+        Ok::<bool, Error>(Envelope::decode(&buffer[..size])?.read_only)
+    };
+
+    // Nothing responds, so only wait for the query to arrive.
+    match future::select(Box::pin(request), Box::pin(receive)).await {
+        Either::Left((result, _)) => panic!("request finished first {:?}", result.err()),
+        Either::Right((read_only, _)) => read_only,
+    }
+}
+
+#[tokio::test]
+async fn read_only_toggled_at_runtime() -> Result<(), Error> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let transport = serve_client(UdpSocket::bind("127.0.0.1:0").await?);
+
+    assert!(!transport.is_read_only());
+    assert!(!sent_read_only(&transport, &socket).await?);
+
+    transport.set_read_only(true);
+    assert!(sent_read_only(&transport, &socket).await?);
+
+    transport.set_read_only(false);
+    assert!(!sent_read_only(&transport, &socket).await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn metrics_counted() -> Result<(), Error> {
+    let responder = FakeNode::bind(NodeID::random()).await?;
+    let responder_address = responder.address;
+    let id = responder.id.clone();
+    responder.serve(move || Response::OnlyID { id: id.clone() });
+
+    // Never reads, so queries to it always time out.
+    let silent_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let silent_address = silent_socket.local_addr()?;
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let client_address = socket.local_addr()?;
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .collect_metrics(true)
+        .build(socket)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+
+    let send_transport = Arc::new(send_transport);
+    let client = RequestTransport::new(NodeID::random(), send_transport.clone())
+        .with_timeout(Duration::from_millis(100));
+
+    client.ping(responder_address).await?;
+    assert!(client.ping(silent_address).await.is_err());
+
+    let garbage = b"not bencode";
+    silent_socket.send_to(garbage, client_address).await?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let metrics = send_transport.metrics();
+    assert_eq!(metrics.requests_sent, 2);
+    assert_eq!(metrics.responses_received, 1);
+    assert_eq!(metrics.timeouts, 1);
+    assert_eq!(metrics.decode_failures, 1);
+    assert!(metrics.bytes_sent > 0);
+    assert!(metrics.bytes_received > garbage.len() as u64);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn metrics_off_by_default() -> Result<(), Error> {
+    let responder = FakeNode::bind(NodeID::random()).await?;
+    let responder_address = responder.address;
+    let id = responder.id.clone();
+    responder.serve(move || Response::OnlyID { id: id.clone() });
+
+    let send_transport = Arc::new(serve_client(UdpSocket::bind("127.0.0.1:0").await?));
+    let client = RequestTransport::new(NodeID::random(), send_transport.clone());
+    client.ping(responder_address).await?;
+
+    assert_eq!(send_transport.metrics(), TransportMetrics::default());
+
+    Ok(())
+}
+
+/// Hands out a fixed sequence of transaction ids.
+struct SequenceGenerator {
+    ids: Mutex<Vec<TransactionId>>,
+}
+
+impl TransactionIdGenerator for SequenceGenerator {
+    fn next(&self) -> TransactionId {
+        self.ids.lock().unwrap().remove(0)
+    }
+}
+
+#[tokio::test]
+async fn custom_transaction_id_generator() -> Result<(), Error> {
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let node_address = node.address;
+    let id = node.id.clone();
+    node.serve(move || Response::OnlyID { id: id.clone() });
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .transaction_id_width(TransactionIdWidth::Two)
+        .transaction_id_generator(SequenceGenerator {
+            // The second 7 is skipped as it's still in flight and the top
+            // bytes of 0x10008 don't fit in two bytes.
+            ids: Mutex::new(vec![7, 7, 0x10008]),
+        })
+        .build(socket)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    let ping = || Query::Ping {
+        id: NodeID::random(),
+    };
+    let ((first, first_trace), (second, second_trace)) = future::join(
+        client.query_traced(node_address, ping()),
+        client.query_traced(node_address, ping()),
+    )
+    .await;
+    first?;
+    second?;
+
+    assert_eq!(first_trace.transaction_id, Some(7));
+    assert_eq!(second_trace.transaction_id, Some(8));
+    assert_eq!(
+        Envelope::decode(&second_trace.sent)?.transaction_id,
+        vec![0, 8]
+    );
+
+    Ok(())
+}
+
+/// Hands out the same transaction id every time.
+struct FixedGenerator(TransactionId);
+
+impl TransactionIdGenerator for FixedGenerator {
+    fn next(&self) -> TransactionId {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn colliding_transaction_ids_fall_back_to_next_free() -> Result<(), Error> {
+    let node = FakeNode::bind(NodeID::random()).await?;
+    let node_address = node.address;
+    let id = node.id.clone();
+    node.serve(move || Response::OnlyID { id: id.clone() });
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let (send_transport, request_stream, _) = KRPCNode::builder()
+        .transaction_id_width(TransactionIdWidth::Two)
+        .transaction_id_generator(FixedGenerator(0xffff))
+        .build(socket)
+        .serve();
+    spawn(
+        request_stream
+            .map_err(|err| println!("Error in Request Stream: {}", err))
+            .for_each(|_| future::ready(())),
+    );
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    let ping = || Query::Ping {
+        id: NodeID::random(),
+    };
+    let ((first, first_trace), (second, second_trace), (third, third_trace)) = future::join3(
+        client.query_traced(node_address, ping()),
+        client.query_traced(node_address, ping()),
+        client.query_traced(node_address, ping()),
+    )
+    .await;
+    first?;
+    second?;
+    third?;
+
+    // Ids after the generated one wrap around within the width.
+    assert_eq!(first_trace.transaction_id, Some(0xffff));
+    assert_eq!(second_trace.transaction_id, Some(0));
+    assert_eq!(third_trace.transaction_id, Some(1));
+
+    Ok(())
+}
+
+#[test]
+fn nodes_with_wrong_address_family_dropped() -> Result<(), Error> {
+    let v4 = NodeInfo::new(NodeID::random(), "10.0.0.1:6881".parse()?);
+    let v6 = NodeInfo::new(NodeID::random(), "[2001:db8::1]:6881".parse()?);
+    let v6_in_nodes = NodeInfo::new(NodeID::random(), "[2001:db8::2]:6881".parse()?);
+    let v4_in_nodes6 = NodeInfo::new(NodeID::random(), "10.0.0.2:6881".parse()?);
+
+    let response = || Response::NextHop {
+        id: NodeID::random(),
+        token: None,
+        nodes: vec![v6_in_nodes.clone(), v4.clone()],
+        nodes6: vec![v6.clone(), v4_in_nodes6.clone()],
+    };
+
+    let find_node = FindNodeResponse::from_response(response())?;
+    assert_eq!(find_node.nodes, vec![v4.clone(), v6.clone()]);
+
+    match GetPeersResponse::from_response(response())?.message_type {
+        GetPeersResponseType::NextHop(nodes) => assert_eq!(nodes, vec![v4, v6]),
+        GetPeersResponseType::Peers(peers) => panic!("unexpected peers {:?}", peers),
+    };
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_stops_serving() -> Result<(), Error> {
+    // Never reads, so pings to it wait until shutdown.
+    let silent_socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let silent_address = silent_socket.local_addr()?;
+
+    let (send_transport, request_stream, shutdown) =
+        KRPCNode::new(UdpSocket::bind("127.0.0.1:0").await?).serve();
+    let serving = spawn(request_stream.for_each(|_| future::ready(())));
+    let client = RequestTransport::new(NodeID::random(), send_transport);
+
+    let (result, shutdown_result) = future::join(client.ping(silent_address), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.shutdown()
+    })
+    .await;
+    shutdown_result?;
+
+    match result.unwrap_err().kind() {
+        ErrorKind::Shutdown => {}
+        kind => panic!("unexpected error {}", kind),
+    };
+    assert!(shutdown.is_shutdown());
+
+    timeout(Duration::from_secs(1), serving).await??;
+
+    match client.ping(silent_address).await.unwrap_err().kind() {
+        ErrorKind::Shutdown => {}
+        kind => panic!("unexpected error {}", kind),
+    };
 
     Ok(())
 }